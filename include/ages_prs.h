@@ -0,0 +1,76 @@
+/*
+ * C interface to ages-prs, compression and decompression of SEGA's PRS.
+ *
+ * Build the library with
+ *
+ *     cargo rustc --release --features ffi --crate-type cdylib
+ *
+ * Functions return AGES_PRS_OK on success and a negative error code
+ * otherwise. Buffers returned by the library are owned by the caller and
+ * must be released with ages_prs_free.
+ */
+
+#ifndef AGES_PRS_H
+#define AGES_PRS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* PRS variants */
+#define AGES_PRS_LEGACY 0u
+#define AGES_PRS_MODERN 1u
+
+/* Error codes */
+#define AGES_PRS_OK 0
+#define AGES_PRS_ERR_NULL (-1)
+#define AGES_PRS_ERR_VARIANT (-2)
+#define AGES_PRS_ERR_EOF (-3)
+#define AGES_PRS_ERR_INVALID_POINTER (-4)
+#define AGES_PRS_ERR_BUFFER_TOO_SMALL (-5)
+#define AGES_PRS_ERR_OTHER (-6)
+
+/* Largest possible compressed size of len bytes. */
+size_t ages_prs_compress_bound(size_t len);
+
+/* Compress src into a new buffer stored in *out, of length *out_len. */
+int32_t ages_prs_compress(uint32_t variant,
+                          const uint8_t *src, size_t src_len,
+                          uint8_t **out, size_t *out_len);
+
+/* Compress src into dst, storing the compressed length in *written. */
+int32_t ages_prs_compress_into(uint32_t variant,
+                               const uint8_t *src, size_t src_len,
+                               uint8_t *dst, size_t dst_len,
+                               size_t *written);
+
+/* Decompress src into a new buffer stored in *out, of length *out_len. */
+int32_t ages_prs_decompress(uint32_t variant,
+                            const uint8_t *src, size_t src_len,
+                            uint8_t **out, size_t *out_len);
+
+/* Decompress src into dst, storing the decompressed length in *written. */
+int32_t ages_prs_decompress_into(uint32_t variant,
+                                 const uint8_t *src, size_t src_len,
+                                 uint8_t *dst, size_t dst_len,
+                                 size_t *written);
+
+/* Compute the decompressed size of src without decompressing it. */
+int32_t ages_prs_decompressed_size(uint32_t variant,
+                                   const uint8_t *src, size_t src_len,
+                                   uint64_t *size);
+
+/* Release a buffer returned by ages_prs_compress or ages_prs_decompress. */
+void ages_prs_free(uint8_t *buf, size_t len);
+
+/* Static, NUL-terminated description of an error code. */
+const char *ages_prs_error_message(int32_t code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* AGES_PRS_H */
@@ -0,0 +1,258 @@
+//! Inspection of the commands of a PRS buffer: statistics with
+//! [`analyze`](fn.analyze.html) and a listing with [`dump`](fn.dump.html).
+
+use crate::decompress::{Cmd, CmdReader};
+use crate::{Command, CommandForm, DecompressError, Variant};
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use core::fmt::Write;
+
+/// Largest distance a long pointer can encode.
+const MAX_ENCODED_DISTANCE: usize = 8192;
+
+/// Counts of the commands in a PRS buffer, produced by
+/// [`analyze`](fn.analyze.html).
+///
+/// Pointers are counted by how they were encoded, so a copy an encoder could
+/// have written as a short pointer but wrote as a long one shows up as long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PrsStats {
+    /// Number of literal commands.
+    pub literals: u64,
+    /// Number of pointers packed into the command bits and a single byte.
+    pub short_pointers: u64,
+    /// Number of two-byte pointers.
+    pub long_pointers: u64,
+    /// Number of two-byte pointers followed by a size byte.
+    pub sized_pointers: u64,
+    /// Number of pointers of each length: `lengths[n]` pointers copy `n`
+    /// bytes.
+    pub lengths: Vec<u64>,
+    /// Number of pointers at each distance: `distances[n]` pointers copy
+    /// from `n` bytes back. Long pointers can encode distances up to 8192,
+    /// one more than encoders use.
+    pub distances: Vec<u64>,
+    /// Whether the end of stream command was reached. If not, the input
+    /// ended partway through the stream and the counts cover what came
+    /// before.
+    pub complete: bool,
+    /// Length of the PRS stream in the input, or of the input if the stream
+    /// is incomplete.
+    pub compressed_size: usize,
+    /// Length of the decompressed data.
+    pub decompressed_size: u64,
+}
+
+impl PrsStats {
+    /// Total number of pointer commands.
+    pub fn pointers(&self) -> u64 {
+        self.short_pointers + self.long_pointers + self.sized_pointers
+    }
+}
+
+/// Count the commands of the PRS stream at the start of `src` by type,
+/// length and distance.
+///
+/// Pointers are not checked against the output, so this also works on
+/// streams that would fail to decompress.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{analyze, compress, Legacy};
+///
+/// let compressed = compress::<Legacy>(b"abcabcabc");
+/// let stats = analyze::<Legacy>(&compressed);
+/// assert_eq!(stats.literals, 3);
+/// assert_eq!(stats.pointers(), 1);
+/// assert_eq!(stats.lengths[6], 1);
+/// assert_eq!(stats.distances[3], 1);
+/// assert_eq!(stats.decompressed_size, 9);
+/// ```
+pub fn analyze<V: Variant>(src: &[u8]) -> PrsStats {
+    let mut stats = PrsStats {
+        literals: 0,
+        short_pointers: 0,
+        long_pointers: 0,
+        sized_pointers: 0,
+        lengths: vec![0; V::MAX_COPY_LENGTH as usize + 1],
+        distances: vec![0; MAX_ENCODED_DISTANCE + 1],
+        complete: false,
+        compressed_size: src.len(),
+        decompressed_size: 0,
+    };
+
+    let mut reader = CmdReader::<V>::resume(src, 0, 0);
+    while let Ok(cmd) = reader.next_cmd() {
+        match cmd {
+            None => {
+                stats.complete = true;
+                stats.compressed_size = reader.pos;
+                break;
+            },
+            Some(Cmd::Literal(_)) => {
+                stats.literals += 1;
+                stats.decompressed_size += 1;
+            },
+            Some(Cmd::Pointer(distance, length)) => {
+                match reader.form {
+                    CommandForm::ShortPointer => stats.short_pointers += 1,
+                    CommandForm::LongPointer => stats.long_pointers += 1,
+                    _ => stats.sized_pointers += 1,
+                }
+                stats.lengths[length] += 1;
+                stats.distances[distance] += 1;
+                stats.decompressed_size += length as u64;
+            },
+        }
+    }
+    stats
+}
+
+/// A command of a PRS stream along with where and how it is encoded, as
+/// yielded by [`Records`](struct.Records.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Record {
+    /// Offset in the input where the command starts, as reported by
+    /// [`CommandIter`](../struct.CommandIter.html).
+    pub offset: usize,
+    /// Offset in the input of the byte holding the command's first flag bit.
+    pub flag_offset: usize,
+    /// Index of the command's first flag bit in that byte, counting from the
+    /// least significant bit.
+    pub flag_bit: u8,
+    /// Offset in the output the command writes to.
+    pub output_len: u64,
+    /// How the command is encoded.
+    pub form: CommandForm,
+    /// The command.
+    pub command: Command,
+}
+
+/// Iterator over the commands of a PRS buffer as
+/// [`Record`](struct.Record.html)s, returned by
+/// [`records`](fn.records.html).
+///
+/// Like [`CommandIter`](../struct.CommandIter.html), pointers are not
+/// checked against the output, and iteration ends after the end of stream
+/// command or after an error if the input ends mid-stream.
+pub struct Records<'a, V: Variant> {
+    reader: CmdReader<'a, V>,
+    output_len: u64,
+    done: bool,
+}
+
+/// Iterate over the commands of the PRS stream at the start of `src`, with
+/// their positions and encodings.
+pub fn records<V: Variant>(src: &[u8]) -> Records<'_, V> {
+    Records {
+        reader: CmdReader::resume(src, 0, 0),
+        output_len: 0,
+        done: false,
+    }
+}
+
+impl<'a, V: Variant> Iterator for Records<'a, V> {
+    type Item = Result<Record, DecompressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let offset = self.reader.pos;
+        let (flag_offset, flag_bit) = if self.reader.rem == 0 {
+            (offset, 0)
+        } else {
+            (self.reader.cmds_pos, 8 - self.reader.rem)
+        };
+        let cmd = match self.reader.next_cmd() {
+            Ok(cmd) => cmd,
+            Err(f) => {
+                self.done = true;
+                return Some(Err(f.at(offset as u64, self.output_len)));
+            },
+        };
+        let command = match cmd {
+            None => {
+                self.done = true;
+                Command::Eof
+            },
+            Some(Cmd::Literal(b)) => Command::Literal(b),
+            Some(Cmd::Pointer(distance, length)) => Command::Pointer { distance, length },
+        };
+        let record = Record {
+            offset,
+            flag_offset,
+            flag_bit,
+            output_len: self.output_len,
+            form: self.reader.form,
+            command,
+        };
+        self.output_len += match command {
+            Command::Literal(_) => 1,
+            Command::Pointer { length, .. } => length as u64,
+            Command::Eof => 0,
+        };
+        Some(Ok(record))
+    }
+}
+
+/// List the commands of the PRS stream at the start of `src`, one per line.
+///
+/// Each line has the input offset of the command, the offset and bit index
+/// of its first flag bit, the output offset it writes to, and the command.
+/// The byte each literal outputs is shown if `literals` is set. An error is
+/// listed as the last line if the input ends mid-stream.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, dump, Legacy};
+///
+/// let compressed = compress::<Legacy>(b"abcabcabc");
+/// let listing = dump::<Legacy>(&compressed, true);
+/// assert_eq!(listing.lines().next(), Some("00000000 00000000.0 00000000 literal 61"));
+/// assert!(listing.contains("00000004 00000000.3 00000003 long distance=3 length=6"));
+/// ```
+pub fn dump<V: Variant>(src: &[u8], literals: bool) -> String {
+    let mut out = String::new();
+    for record in records::<V>(src) {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let _ = writeln!(out, "error: {}", e);
+                break;
+            },
+        };
+        let _ = write!(
+            out,
+            "{:08x} {:08x}.{} {:08x} ",
+            record.offset,
+            record.flag_offset,
+            record.flag_bit,
+            record.output_len,
+        );
+        let form = match record.form {
+            CommandForm::Literal => "literal",
+            CommandForm::ShortPointer => "short",
+            CommandForm::LongPointer => "long",
+            CommandForm::SizedPointer => "sized",
+            CommandForm::Eof => "eof",
+        };
+        out.push_str(form);
+        let _ = match record.command {
+            Command::Literal(b) if literals => writeln!(out, " {:02x}", b),
+            Command::Pointer { distance, length } => {
+                writeln!(out, " distance={} length={}", distance, length)
+            },
+            _ => writeln!(out),
+        };
+    }
+    out
+}
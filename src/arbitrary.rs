@@ -0,0 +1,108 @@
+//! Structured PRS stream generation for fuzzing, enabled by the `arbitrary`
+//! feature.
+//!
+//! Random bytes rarely make it past the first few commands of a PRS decoder.
+//! [`CommandStream`](struct.CommandStream.html) implements `Arbitrary` by
+//! generating commands that are valid by construction, so a fuzzer spends its
+//! time on streams that decode, and knows what they should decode to.
+//!
+//! ```
+//! use arbitrary::{Arbitrary, Unstructured};
+//! use ages_prs::{decompress, Legacy, Modern};
+//! use ages_prs::arbitrary::CommandStream;
+//!
+//! let mut u = Unstructured::new(&[7, 1, 200, 3, 0, 9, 1, 1, 0, 5]);
+//! let stream = CommandStream::arbitrary(&mut u).unwrap();
+//! let expected = stream.decompressed();
+//! assert_eq!(decompress::<Legacy>(&stream.to_prs::<Legacy>()).unwrap(), expected);
+//! assert_eq!(decompress::<Modern>(&stream.to_prs::<Modern>()).unwrap(), expected);
+//! ```
+
+use crate::compress::PrsSink;
+use crate::lz77::{Code, Sink};
+use crate::{Command, Legacy, Modern, Variant};
+
+use alloc::vec::Vec;
+
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+/// Furthest distance a pointer can copy from.
+const MAX_DISTANCE: usize = Modern::MAX_COPY_DISTANCE as usize;
+
+/// Longest copy every variant can encode.
+const MAX_LENGTH: usize = Legacy::MAX_COPY_LENGTH as usize;
+
+/// A sequence of literal and pointer commands that is valid in every
+/// variant.
+///
+/// Pointers never reach before the start of the output, and their lengths
+/// can be encoded by both Legacy and Modern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandStream {
+    commands: Vec<Command>,
+}
+
+impl CommandStream {
+    /// The commands of the stream, without the end of stream command.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Encode the commands as a PRS stream of variant `V`, ending with the
+    /// end of stream command.
+    pub fn to_prs<V: Variant>(&self) -> Vec<u8> {
+        let mut sink = PrsSink::<V>::new(self.commands.len() * 2 + 3);
+        for cmd in &self.commands {
+            match *cmd {
+                Command::Literal(b) => sink.consume(Code::Literal(b)),
+                Command::Pointer { distance, length } => {
+                    sink.write_pointer(distance as u16, length as u16);
+                },
+                Command::Eof => unreachable!(),
+            }
+        }
+        sink.write_eof();
+        sink.out
+    }
+
+    /// The data the stream decompresses to.
+    pub fn decompressed(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for cmd in &self.commands {
+            match *cmd {
+                Command::Literal(b) => out.push(b),
+                Command::Pointer { distance, length } => {
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance]);
+                    }
+                },
+                Command::Eof => unreachable!(),
+            }
+        }
+        out
+    }
+}
+
+impl<'a> Arbitrary<'a> for CommandStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<CommandStream> {
+        let mut commands = Vec::new();
+        let mut output_len = 0;
+        while !u.is_empty() && u.arbitrary::<bool>()? {
+            if output_len == 0 || u.arbitrary::<bool>()? {
+                commands.push(Command::Literal(u.arbitrary()?));
+                output_len += 1;
+                continue;
+            }
+
+            let max = core::cmp::min(output_len, MAX_DISTANCE);
+            let distance = u.int_in_range(1..=max)?;
+            // a copy of 2 only has an encoding common to all variants as a
+            // short pointer
+            let min = if distance <= Modern::MAX_SHORT_COPY_DISTANCE as usize { 2 } else { 3 };
+            let length = u.int_in_range(min..=MAX_LENGTH)?;
+            commands.push(Command::Pointer { distance, length });
+            output_len += length;
+        }
+        Ok(CommandStream { commands })
+    }
+}
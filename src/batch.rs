@@ -0,0 +1,76 @@
+//! Compression and decompression of many buffers reusing one set of
+//! allocations.
+
+use crate::compress::{compress_bound, lz77_encoder, PrsSink};
+use crate::decompress::decompress_to_buf;
+use crate::lz77::Lz77Encoder;
+use crate::{DecompressError, Variant};
+
+use alloc::vec::Vec;
+
+/// Compressor and decompressor for many buffers in a row, such as the files
+/// of an archive being repacked.
+///
+/// The one-shot functions set up a new matcher and output buffer for every
+/// call. A batch keeps its matcher, along with the input buffer the matcher
+/// works from, and both output buffers between calls, so once it has seen
+/// its largest file they stop growing. Each result borrows the batch's
+/// buffer and is only valid until the next call.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{Modern, PrsBatch};
+///
+/// let mut batch = PrsBatch::<Modern>::new();
+/// let mut archive = Vec::new();
+/// for file in &[&b"Hello Hello Hello "[..], b"abcabcabc"] {
+///     archive.push(batch.compress_one(file).to_vec());
+/// }
+/// assert_eq!(batch.decompress_one(&archive[1]).unwrap(), b"abcabcabc");
+/// ```
+pub struct PrsBatch<V: Variant> {
+    encoder: Lz77Encoder,
+    sink: PrsSink<V>,
+    /// output of `decompress_one`
+    out: Vec<u8>,
+}
+
+impl<V: Variant> PrsBatch<V> {
+    /// Create a batch. Nothing is allocated until the first call.
+    pub fn new() -> PrsBatch<V> {
+        PrsBatch {
+            encoder: lz77_encoder::<V>(),
+            sink: PrsSink::new(0),
+            out: Vec::new(),
+        }
+    }
+
+    /// Compress `src`, returning the PRS stream.
+    ///
+    /// The stream is the same as [`compress`](fn.compress.html) returns.
+    pub fn compress_one(&mut self, src: &[u8]) -> &[u8] {
+        // the last call flushed the matcher, so this keeps its buffer
+        self.encoder.reset();
+        self.sink.reset();
+        self.sink.out.reserve(compress_bound::<V>(src.len()));
+        self.encoder.encode(src, &mut self.sink);
+        self.encoder.flush(&mut self.sink);
+        self.sink.write_eof();
+        &self.sink.out
+    }
+
+    /// Decompress `src`, returning the data.
+    ///
+    /// Fails as [`decompress`](fn.decompress.html) does.
+    pub fn decompress_one(&mut self, src: &[u8]) -> Result<&[u8], DecompressError> {
+        decompress_to_buf::<V>(src, &mut self.out)?;
+        Ok(&self.out)
+    }
+}
+
+impl<V: Variant> Default for PrsBatch<V> {
+    fn default() -> PrsBatch<V> {
+        PrsBatch::new()
+    }
+}
@@ -0,0 +1,196 @@
+//! Command line tool for compressing and decompressing PRS files.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use ages_prs::{bufread::PrsDecoder, Legacy, Modern, PrsEncoder, Variant};
+
+const USAGE: &str = "\
+usage: prs <compress|decompress> --variant <legacy|modern> [-o OUTPUT] [INPUT]
+       prs <compress|decompress> --variant <legacy|modern> -r INPUT_DIR -o OUTPUT_DIR
+
+Reads INPUT, or stdin if it is missing or -, and writes to OUTPUT, or stdout
+if it is missing or -.
+
+With -r, every file under INPUT_DIR is processed into the same place under
+OUTPUT_DIR. Compressed files get a .prs extension added; when decompressing,
+only files ending in .prs are processed and the extension is removed.
+
+options:
+    -v, --variant <legacy|modern>  PRS variant of the compressed data
+    -o, --output <PATH>            output file, or directory with -r
+    -r, --recursive                process a directory tree
+    -h, --help                     show this message";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Compress,
+    Decompress,
+}
+
+struct Args {
+    mode: Mode,
+    legacy: bool,
+    recursive: bool,
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = env::args_os().skip(1);
+    let mode = match args.next().as_ref().and_then(|a| a.to_str()) {
+        Some("compress") => Mode::Compress,
+        Some("decompress") => Mode::Decompress,
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            process::exit(0);
+        },
+        _ => return Err("expected compress or decompress".into()),
+    };
+
+    let mut legacy = None;
+    let mut recursive = false;
+    let mut input = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-v") | Some("--variant") => {
+                legacy = match args.next().as_ref().and_then(|a| a.to_str()) {
+                    Some("legacy") => Some(true),
+                    Some("modern") => Some(false),
+                    _ => return Err("--variant must be legacy or modern".into()),
+                };
+            },
+            Some("-o") | Some("--output") => {
+                let path = args.next().ok_or("--output needs a path")?;
+                output = Some(PathBuf::from(path));
+            },
+            Some("-r") | Some("--recursive") => recursive = true,
+            Some("-h") | Some("--help") => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            Some(a) if a.starts_with('-') && a != "-" => {
+                return Err(format!("unknown option {}", a));
+            },
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err("more than one input given".into()),
+        }
+    }
+
+    let legacy = legacy.ok_or("--variant is required")?;
+    if recursive && (input.is_none() || output.is_none()) {
+        return Err("-r needs an input and an output directory".into());
+    }
+    Ok(Args { mode, legacy, recursive, input, output })
+}
+
+/// Compress or decompress everything read from `input` into `output`.
+fn transform<V, R, W>(mode: Mode, input: R, output: W) -> io::Result<()>
+where
+    V: Variant,
+    R: Read,
+    W: Write,
+{
+    match mode {
+        Mode::Compress => {
+            let mut input = input;
+            let mut encoder = PrsEncoder::<_, V>::new(output);
+            io::copy(&mut input, &mut encoder)?;
+            encoder.into_inner().map_err(|e| e.into_error())?.flush()
+        },
+        Mode::Decompress => {
+            let mut decoder = PrsDecoder::<_, V>::new(BufReader::new(input));
+            let mut output = output;
+            io::copy(&mut decoder, &mut output)?;
+            output.flush()
+        },
+    }
+}
+
+fn is_stdio(path: &Option<PathBuf>) -> bool {
+    path.as_ref().is_none_or(|p| p.as_os_str() == "-")
+}
+
+fn process_stream<V: Variant>(args: &Args) -> io::Result<()> {
+    let input: Box<dyn Read> = if is_stdio(&args.input) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(args.input.as_ref().unwrap())?)
+    };
+    let output: Box<dyn Write> = if is_stdio(&args.output) {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(args.output.as_ref().unwrap())?)
+    };
+    transform::<V, _, _>(args.mode, input, BufWriter::new(output))
+}
+
+fn process_tree<V: Variant>(mode: Mode, input: &Path, output: &Path) -> io::Result<()> {
+    fs::create_dir_all(output)?;
+    for entry in fs::read_dir(input)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        if entry.file_type()?.is_dir() {
+            process_tree::<V>(mode, &path, &output.join(&name))?;
+            continue;
+        }
+
+        let out_path = match mode {
+            Mode::Compress => {
+                let mut name = name;
+                name.push(".prs");
+                output.join(name)
+            },
+            Mode::Decompress => match path.extension() {
+                Some(ext) if ext.eq_ignore_ascii_case("prs") => {
+                    output.join(path.file_stem().unwrap())
+                },
+                _ => continue,
+            },
+        };
+
+        let r = File::open(&path).and_then(|input| {
+            let output = BufWriter::new(File::create(&out_path)?);
+            transform::<V, _, _>(mode, input, output)
+        });
+        if let Err(e) = r {
+            return Err(io::Error::new(e.kind(), format!("{}: {}", path.display(), e)));
+        }
+    }
+    Ok(())
+}
+
+fn run<V: Variant>(args: &Args) -> io::Result<()> {
+    if args.recursive {
+        let input = args.input.as_ref().unwrap();
+        let output = args.output.as_ref().unwrap();
+        process_tree::<V>(args.mode, input, output)
+    } else {
+        process_stream::<V>(args)
+    }
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("prs: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let r = if args.legacy {
+        run::<Legacy>(&args)
+    } else {
+        run::<Modern>(&args)
+    };
+    if let Err(e) = r {
+        eprintln!("prs: {}", e);
+        process::exit(1);
+    }
+}
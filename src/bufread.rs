@@ -0,0 +1,351 @@
+//! PRS decoding over `BufRead` sources.
+//!
+//! [`crate::PrsDecoder`](../struct.PrsDecoder.html) reads its input a byte
+//! at a time, which is slow over readers where every read is a system call.
+//! The decoder in this module instead decodes directly out of the buffer of a
+//! `BufRead`, such as a `BufReader` or an in-memory `&[u8]`.
+
+use crate::Variant;
+use crate::decompress::{DecoderState, Decompress, Status};
+use crate::progress::Progress;
+
+use std::io::{self, BufRead, Read};
+use std::ops::Range;
+
+/// An IO source for decoding a PRS stream read from a `BufRead`.
+///
+/// Only the bytes making up the PRS stream are consumed from the inner
+/// `BufRead`. Once the end of the stream has been reached, anything following
+/// it is still available from the inner reader.
+///
+/// Decoders over a cloneable `BufRead` can be cloned; see
+/// [`crate::PrsDecoder`](../struct.PrsDecoder.html).
+pub struct PrsDecoder<R: BufRead, V: Variant> {
+    inner: R,
+    state: Decompress<V>,
+    progress: Progress,
+}
+
+impl<R: BufRead, V: Variant> PrsDecoder<R, V> {
+    /// Wraps a BufRead source, initializing the decoder state.
+    pub fn new(inner: R) -> PrsDecoder<R, V> {
+        PrsDecoder {
+            inner,
+            state: Decompress::new(),
+            progress: Progress::new("decode"),
+        }
+    }
+
+    /// Wraps a BufRead source, failing once more than `limit` bytes would be
+    /// decompressed from it.
+    ///
+    /// See [`crate::PrsDecoder::with_limit`](../struct.PrsDecoder.html#method.with_limit).
+    pub fn with_limit(inner: R, limit: u64) -> PrsDecoder<R, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.state.set_limit(Some(limit));
+        decoder
+    }
+
+    /// Wraps a BufRead source of a stream compressed against the preset
+    /// dictionary `dict`.
+    ///
+    /// See [`Decompress::set_dictionary`](../struct.Decompress.html#method.set_dictionary).
+    pub fn with_dictionary(inner: R, dict: &[u8]) -> PrsDecoder<R, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.state.set_dictionary(dict);
+        decoder
+    }
+
+    /// Call `f` with the number of compressed bytes consumed and
+    /// decompressed bytes produced so far, every time data is decompressed.
+    ///
+    /// See [`crate::PrsDecoder::on_progress`](../struct.PrsDecoder.html#method.on_progress).
+    pub fn on_progress<F>(&mut self, f: F)
+    where
+        F: FnMut(u64, u64) + Send + Sync + 'static,
+    {
+        self.progress.set(f);
+    }
+
+    /// Fail once more than `ratio` times as many bytes would be decompressed
+    /// as were read, or lift the limit with `None`.
+    ///
+    /// See [`crate::PrsDecoder::set_max_ratio`](../struct.PrsDecoder.html#method.set_max_ratio).
+    pub fn set_max_ratio(&mut self, ratio: Option<u32>) {
+        self.state.set_max_ratio(ratio);
+    }
+
+    /// Reference the inner BufRead.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Mutably reference the inner BufRead.
+    ///
+    /// Reading from it directly will corrupt the PRS stream being decoded.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Number of compressed bytes consumed from the inner BufRead so far.
+    pub fn total_in(&self) -> u64 {
+        self.state.total_in()
+    }
+
+    /// Number of decompressed bytes returned so far.
+    pub fn total_out(&self) -> u64 {
+        self.state.total_out()
+    }
+
+    /// Unwrap the inner BufRead.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Capture the decoding progress, to resume from later with
+    /// [`restore`](#method.restore).
+    ///
+    /// See [`crate::PrsDecoder::checkpoint`](../struct.PrsDecoder.html#method.checkpoint).
+    pub fn checkpoint(&self) -> DecoderState {
+        self.state.checkpoint()
+    }
+
+    /// Resume decoding from a state captured by
+    /// [`checkpoint`](#method.checkpoint).
+    ///
+    /// See [`crate::PrsDecoder::restore`](../struct.PrsDecoder.html#method.restore).
+    pub fn restore(&mut self, state: &DecoderState) {
+        self.state.restore(state);
+    }
+
+    /// Start decoding a new PRS stream from `inner`, returning the previous
+    /// BufRead.
+    ///
+    /// See [`crate::PrsDecoder::reset`](../struct.PrsDecoder.html#method.reset).
+    pub fn reset(&mut self, inner: R) -> R {
+        self.state.reset();
+        self.progress.start();
+        std::mem::replace(&mut self.inner, inner)
+    }
+}
+
+impl<T: AsRef<[u8]>, V: Variant> PrsDecoder<SharedSlice<T>, V> {
+    /// Decode the stream at `range` in `data`, holding on to `data` itself
+    /// rather than borrowing it.
+    ///
+    /// With `data` an `Arc<[u8]>` or another cheaply cloned owner of an
+    /// archive, each worker thread can decode its own entries without copying
+    /// them out or borrowing from the thread owning the archive. See
+    /// [`SharedSlice`](struct.SharedSlice.html).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds of `data`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// use ages_prs::{compress, Modern};
+    /// use ages_prs::bufread::PrsDecoder;
+    ///
+    /// let first = compress::<Modern>(b"first entry");
+    /// let second = compress::<Modern>(b"second entry");
+    /// let archive: Arc<[u8]> = [&first[..], &second[..]].concat().into();
+    ///
+    /// let ranges = [0..first.len(), first.len()..archive.len()];
+    /// let workers: Vec<_> = ranges.iter().map(|range| {
+    ///     let mut decoder = PrsDecoder::<_, Modern>::from_shared(archive.clone(), range.clone());
+    ///     thread::spawn(move || {
+    ///         let mut out = Vec::new();
+    ///         decoder.read_to_end(&mut out).unwrap();
+    ///         out
+    ///     })
+    /// }).collect();
+    /// let entries: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+    /// assert_eq!(entries, [&b"first entry"[..], &b"second entry"[..]]);
+    /// ```
+    pub fn from_shared(data: T, range: Range<usize>) -> PrsDecoder<SharedSlice<T>, V> {
+        PrsDecoder::new(SharedSlice::with_range(data, range))
+    }
+}
+
+impl<R: BufRead + Clone, V: Variant> Clone for PrsDecoder<R, V> {
+    fn clone(&self) -> PrsDecoder<R, V> {
+        PrsDecoder {
+            inner: self.inner.clone(),
+            state: self.state.clone(),
+            progress: Progress::new("decode"),
+        }
+    }
+}
+
+impl<R: BufRead, V: Variant> Read for PrsDecoder<R, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let input = self.inner.fill_buf()?;
+            let at_eof = input.is_empty();
+            let total_in = self.state.total_in();
+            let total_out = self.state.total_out();
+
+            let result = self.state.run(input, buf);
+            let consumed = (self.state.total_in() - total_in) as usize;
+            let produced = (self.state.total_out() - total_out) as usize;
+            self.inner.consume(consumed);
+            // errors found after output was produced are already held back
+            // by `run` for the next call
+            let status = result?;
+
+            match status {
+                Status::NeedsInput if produced == 0 => {
+                    if at_eof {
+                        return Err(self.state.eof_error().into());
+                    }
+                },
+                _ => {
+                    if status == Status::Done {
+                        self.progress.finish(self.state.total_in(), self.state.total_out());
+                    }
+                    self.progress.report(self.state.total_in(), self.state.total_out());
+                    return Ok(produced);
+                },
+            }
+        }
+    }
+}
+
+/// An IO source for decoding back-to-back PRS streams read from a `BufRead`.
+///
+/// Some archive formats store several PRS streams one after another. When
+/// one stream ends, this decoder continues with the next one, and only
+/// reports the end of the data once the inner `BufRead` is exhausted right
+/// after a stream. Anything following the last stream is decoded as well,
+/// so trailing data that isn't PRS causes an error.
+pub struct MultiPrsDecoder<R: BufRead, V: Variant> {
+    decoder: PrsDecoder<R, V>,
+}
+
+impl<R: BufRead, V: Variant> MultiPrsDecoder<R, V> {
+    /// Wraps a BufRead source, initializing the decoder state.
+    pub fn new(inner: R) -> MultiPrsDecoder<R, V> {
+        MultiPrsDecoder {
+            decoder: PrsDecoder::new(inner),
+        }
+    }
+
+    /// Reference the inner BufRead.
+    pub fn get_ref(&self) -> &R {
+        self.decoder.get_ref()
+    }
+
+    /// Mutably reference the inner BufRead.
+    ///
+    /// Reading from it directly will corrupt the PRS stream being decoded.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.decoder.get_mut()
+    }
+
+    /// Unwrap the inner BufRead.
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+impl<R: BufRead, V: Variant> Read for MultiPrsDecoder<R, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.decoder.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            // the current stream ended; start on the next one if there is one
+            if self.decoder.inner.fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            self.decoder.state.reset();
+        }
+    }
+}
+
+/// A `BufRead` over a range of bytes owned by `T`, such as an `Arc<[u8]>`.
+///
+/// Like `Cursor`, but limited to part of the data, so a single shared buffer
+/// holding many streams can be handed to as many readers as needed. It is
+/// `Send` and `'static` whenever `T` is, and cheap to clone whenever `T`
+/// is.
+#[derive(Debug, Clone)]
+pub struct SharedSlice<T: AsRef<[u8]>> {
+    data: T,
+    pos: usize,
+    end: usize,
+}
+
+impl<T: AsRef<[u8]>> SharedSlice<T> {
+    /// Read all of `data`.
+    pub fn new(data: T) -> SharedSlice<T> {
+        let end = data.as_ref().len();
+        SharedSlice { data, pos: 0, end }
+    }
+
+    /// Read `range` of `data`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds of `data`.
+    pub fn with_range(data: T, range: Range<usize>) -> SharedSlice<T> {
+        assert!(
+            range.start <= range.end && range.end <= data.as_ref().len(),
+            "range out of bounds of the shared data"
+        );
+        SharedSlice {
+            data,
+            pos: range.start,
+            end: range.end,
+        }
+    }
+
+    /// Position of the next byte to be read, from the start of the data.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// The bytes not yet read.
+    pub fn remaining(&self) -> &[u8] {
+        &self.data.as_ref()[self.pos..self.end]
+    }
+
+    /// Reference the underlying data.
+    pub fn get_ref(&self) -> &T {
+        &self.data
+    }
+
+    /// Unwrap the underlying data.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for SharedSlice<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.remaining().read(buf)?;
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl<T: AsRef<[u8]>> BufRead for SharedSlice<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = std::cmp::min(self.pos + amt, self.end);
+    }
+}
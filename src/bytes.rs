@@ -0,0 +1,118 @@
+//! PRS encoding and decoding over the `Buf` and `BufMut` traits of the
+//! `bytes` crate. Requires the `bytes` feature.
+//!
+//! Input may be split over any number of chunks, as in a chain of buffers
+//! or a queue of received packets, and is read chunk by chunk without
+//! being flattened first. Decoding consumes only the PRS stream, leaving
+//! anything after it in the `Buf`.
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::Modern;
+//! use ages_prs::bytes::{compress, decompress};
+//! use bytes::Buf;
+//!
+//! let compressed = compress::<Modern, _>(&mut &b"Hello Hello Hello "[..]);
+//! let (head, tail) = compressed.split_at(3);
+//! let mut packets = head.chain(tail).chain(&b"next"[..]);
+//!
+//! let data = decompress::<Modern, _>(&mut packets).unwrap();
+//! assert_eq!(&data[..], b"Hello Hello Hello ");
+//! assert_eq!(packets.chunk(), b"next");
+//! ```
+
+use crate::{Compress, Decompress, DecompressError, Status, Variant};
+
+use ::bytes::{Buf, BufMut, BytesMut};
+
+/// Size of the scratch buffer data is staged in on its way to the `BufMut`.
+const CHUNK_LEN: usize = 8192;
+
+/// Compress everything remaining in `src` into a new `BytesMut`.
+pub fn compress<V: Variant, B: Buf>(src: &mut B) -> BytesMut {
+    let mut out = BytesMut::new();
+    compress_to::<V, _, _>(src, &mut out);
+    out
+}
+
+/// Compress everything remaining in `src`, appending the PRS stream to
+/// `dst`.
+///
+/// # Panics
+///
+/// Panics if `dst` runs out of room, as with `BufMut::put_slice`.
+pub fn compress_to<V, B, M>(src: &mut B, dst: &mut M)
+where
+    V: Variant,
+    B: Buf,
+    M: BufMut,
+{
+    let mut state = Compress::<V>::new();
+    let mut buf = [0; CHUNK_LEN];
+    while src.has_remaining() {
+        let chunk = src.chunk();
+        let len = chunk.len();
+        let mut out_pos = state.total_out();
+        let mut status = state.run(chunk, &mut buf);
+        loop {
+            dst.put_slice(&buf[..(state.total_out() - out_pos) as usize]);
+            if status != Status::NeedsOutput {
+                break;
+            }
+            out_pos = state.total_out();
+            status = state.run(&[], &mut buf);
+        }
+        // nothing was pending, so the chunk was consumed in full
+        src.advance(len);
+    }
+    loop {
+        let out_pos = state.total_out();
+        let status = state.finish(&mut buf);
+        dst.put_slice(&buf[..(state.total_out() - out_pos) as usize]);
+        if status == Status::Done {
+            break;
+        }
+    }
+}
+
+/// Decompress the PRS stream at the front of `src` into a new `BytesMut`.
+///
+/// See [`decompress_to`](fn.decompress_to.html).
+pub fn decompress<V: Variant, B: Buf>(src: &mut B) -> Result<BytesMut, DecompressError> {
+    let mut out = BytesMut::new();
+    decompress_to::<V, _, _>(src, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress the PRS stream at the front of `src`, appending the data to
+/// `dst` and returning its length.
+///
+/// `src` is advanced past the end of the stream and no further. On error,
+/// `dst` holds whatever was decompressed before the error was found.
+///
+/// # Panics
+///
+/// Panics if `dst` runs out of room, as with `BufMut::put_slice`.
+pub fn decompress_to<V, B, M>(src: &mut B, dst: &mut M) -> Result<u64, DecompressError>
+where
+    V: Variant,
+    B: Buf,
+    M: BufMut,
+{
+    let mut state = Decompress::<V>::new();
+    let mut buf = [0; CHUNK_LEN];
+    loop {
+        let in_pos = state.total_in();
+        let out_pos = state.total_out();
+        let status = state.run(src.chunk(), &mut buf)?;
+        src.advance((state.total_in() - in_pos) as usize);
+        dst.put_slice(&buf[..(state.total_out() - out_pos) as usize]);
+        match status {
+            Status::Done => return Ok(state.total_out()),
+            Status::NeedsOutput => {},
+            Status::NeedsInput if src.has_remaining() => {},
+            Status::NeedsInput => return Err(state.eof_error()),
+        }
+    }
+}
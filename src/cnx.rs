@@ -0,0 +1,365 @@
+//! Compression and decompression of CNX, an LZ77 format used in several
+//! Sonic Team games.
+//!
+//! CNX data starts with a 16 byte header:
+//!
+//! - the magic bytes [`MAGIC`](constant.MAGIC.html);
+//! - the original file's extension, padded with zeros to three bytes;
+//! - the byte `0x10`;
+//! - the length of the data after the header as a big endian `u32`;
+//! - the decompressed size as a big endian `u32`.
+//!
+//! Commands are two bits each, read four to a flag byte from the least
+//! significant bits: a single literal byte, a run of up to 255 literal bytes
+//! behind a count, a two byte pointer copying 4 to 35 bytes from up to 2048
+//! bytes back, or a skip over padding to the next flag byte.
+//!
+//! [`compress`](fn.compress.html) and [`decompress`](fn.decompress.html)
+//! work on whole buffers, and [`CnxEncoder`](struct.CnxEncoder.html) and
+//! [`CnxDecoder`](struct.CnxDecoder.html) adapt them to `Write` and `Read`
+//! like the PRS encoder and decoder. As the header records both sizes, the
+//! encoder can only write anything once all of its input is known.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Read, Write};
+//!
+//! use ages_prs::cnx::{CnxDecoder, CnxEncoder};
+//!
+//! let mut encoder = CnxEncoder::new(Vec::new(), *b"txt");
+//! encoder.write_all(b"Hello Hello Hello ").unwrap();
+//! let compressed = encoder.into_inner().unwrap();
+//!
+//! let mut decoder = CnxDecoder::new(&compressed[..]);
+//! let mut decomp = Vec::new();
+//! decoder.read_to_end(&mut decomp).unwrap();
+//! assert_eq!(&decomp[..], b"Hello Hello Hello ");
+//! ```
+
+use crate::lz77::{Code, Lz77Encoder, Sink};
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+/// Magic bytes starting CNX data.
+pub const MAGIC: [u8; 4] = *b"CNX\x02";
+
+/// Length of the header preceding the compressed data.
+pub const HEADER_LEN: usize = 16;
+
+const MIN_LENGTH: usize = 4;
+const MAX_LENGTH: u16 = 35;
+const WINDOW: usize = 2048;
+const MAX_RUN: usize = 255;
+
+const CMD_SKIP: u8 = 0;
+const CMD_LITERAL: u8 = 1;
+const CMD_POINTER: u8 = 2;
+const CMD_RUN: u8 = 3;
+
+/// Contents of a CNX header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Header {
+    /// The original file's extension, padded with zeros.
+    pub extension: [u8; 3],
+    /// Length of the data after the header.
+    pub compressed_size: u32,
+    /// Length of the data once decompressed.
+    pub decompressed_size: u32,
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32(src: &[u8]) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&src[..4]);
+    u32::from_be_bytes(bytes)
+}
+
+fn size(len: usize) -> u32 {
+    u32::try_from(len).expect("CNX sizes must fit in 32 bits")
+}
+
+/// Parse a CNX header from the start of `src`.
+///
+/// Fails with `InvalidData` if the header is not valid.
+pub fn read_header(src: &[u8]) -> io::Result<Header> {
+    if src.len() < HEADER_LEN {
+        return Err(invalid("CNX header is truncated"));
+    }
+    if src[..4] != MAGIC {
+        return Err(invalid("not CNX data"));
+    }
+    let mut extension = [0; 3];
+    extension.copy_from_slice(&src[4..7]);
+    Ok(Header {
+        extension,
+        compressed_size: read_u32(&src[8..]),
+        decompressed_size: read_u32(&src[12..]),
+    })
+}
+
+struct CnxSink {
+    out: Vec<u8>,
+    /// index in `out` of the current flag byte
+    flags: usize,
+    /// commands in the current flag byte
+    count: u8,
+    /// literals not yet written, to be grouped into runs
+    literals: Vec<u8>,
+}
+
+impl CnxSink {
+    fn command(&mut self, cmd: u8) {
+        if self.count == 4 {
+            self.flags = self.out.len();
+            self.out.push(0);
+            self.count = 0;
+        }
+        self.out[self.flags] |= cmd << (self.count * 2);
+        self.count += 1;
+    }
+
+    fn write_literals(&mut self) {
+        if self.literals.len() == 1 {
+            self.command(CMD_LITERAL);
+            self.out.push(self.literals[0]);
+        } else {
+            let mut start = 0;
+            while start < self.literals.len() {
+                let end = std::cmp::min(start + MAX_RUN, self.literals.len());
+                self.command(CMD_RUN);
+                self.out.push((end - start) as u8);
+                self.out.extend_from_slice(&self.literals[start..end]);
+                start = end;
+            }
+        }
+        self.literals.clear();
+    }
+}
+
+impl Sink for CnxSink {
+    fn consume(&mut self, code: Code) {
+        match code {
+            Code::Literal(b) => self.literals.push(b),
+            Code::Pointer { length, backward_distance } => {
+                self.write_literals();
+                self.command(CMD_POINTER);
+                let pair = ((backward_distance - 1) << 5) | (length - MIN_LENGTH as u16);
+                self.out.extend_from_slice(&pair.to_be_bytes());
+            },
+        }
+    }
+}
+
+/// Compress `data` to CNX, recording `extension` in the header.
+///
+/// # Panics
+///
+/// Panics if `data` or its compressed form are 4 GiB or longer, as the
+/// header can't record their sizes.
+pub fn compress(data: &[u8], extension: [u8; 3]) -> Vec<u8> {
+    let mut sink = CnxSink {
+        out: vec![0; HEADER_LEN],
+        flags: 0,
+        count: 4,
+        literals: Vec::new(),
+    };
+    let mut matcher = Lz77Encoder::with_limits(MIN_LENGTH, MAX_LENGTH, WINDOW);
+    matcher.encode(data, &mut sink);
+    matcher.flush(&mut sink);
+    sink.write_literals();
+
+    let mut out = sink.out;
+    let len = size(out.len() - HEADER_LEN);
+    out[..4].copy_from_slice(&MAGIC);
+    out[4..7].copy_from_slice(&extension);
+    out[7] = 0x10;
+    out[8..12].copy_from_slice(&len.to_be_bytes());
+    out[12..16].copy_from_slice(&size(data.len()).to_be_bytes());
+    out
+}
+
+/// Decompress the CNX data at the start of `src`.
+///
+/// Fails with `InvalidData` if the header is not valid, the data ends before
+/// the decompressed size given in the header is reached, or a pointer copies
+/// from before the start of the output.
+pub fn decompress(src: &[u8]) -> io::Result<Vec<u8>> {
+    let header = read_header(src)?;
+    let data = src
+        .get(HEADER_LEN..HEADER_LEN + header.compressed_size as usize)
+        .ok_or_else(|| invalid("CNX data is truncated"))?;
+    decode(data, header.decompressed_size as usize)
+}
+
+fn decode(src: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let truncated = || invalid("CNX data ends early");
+    // a pointer outputs at most 35 bytes for 2 of input
+    let mut out = Vec::with_capacity(std::cmp::min(len, src.len().saturating_mul(18)));
+    let mut pos = 0;
+    while out.len() < len {
+        let mut flags = *src.get(pos).ok_or_else(truncated)?;
+        pos += 1;
+        for _ in 0..4 {
+            if out.len() >= len {
+                break;
+            }
+            match flags & 3 {
+                CMD_SKIP => {
+                    // padding up to the next flag byte, behind its length
+                    let skip = *src.get(pos).ok_or_else(truncated)? as usize;
+                    pos += skip + 1;
+                    break;
+                },
+                CMD_LITERAL => {
+                    out.push(*src.get(pos).ok_or_else(truncated)?);
+                    pos += 1;
+                },
+                CMD_POINTER => {
+                    let pair = src.get(pos..pos + 2).ok_or_else(truncated)?;
+                    pos += 2;
+                    let pair = u16::from_be_bytes([pair[0], pair[1]]) as usize;
+                    let distance = (pair >> 5) + 1;
+                    let length = (pair & 0x1F) + MIN_LENGTH;
+                    if distance > out.len() {
+                        return Err(invalid("CNX pointer copies from before the output"));
+                    }
+                    for _ in 0..std::cmp::min(length, len - out.len()) {
+                        out.push(out[out.len() - distance]);
+                    }
+                },
+                _ => {
+                    let count = *src.get(pos).ok_or_else(truncated)? as usize;
+                    let run = src.get(pos + 1..pos + 1 + count).ok_or_else(truncated)?;
+                    pos += count + 1;
+                    let count = std::cmp::min(count, len - out.len());
+                    out.extend_from_slice(&run[..count]);
+                },
+            }
+            flags >>= 2;
+        }
+    }
+    Ok(out)
+}
+
+/// Compress data written to it to CNX, writing it to an inner Write when
+/// finished.
+///
+/// All input is buffered until [`into_inner`](#method.into_inner), since
+/// the header needs the sizes of both the input and the output.
+pub struct CnxEncoder<W: Write> {
+    inner: W,
+    extension: [u8; 3],
+    buf: Vec<u8>,
+}
+
+impl<W: Write> CnxEncoder<W> {
+    /// Create an encoder writing to `inner`, recording `extension` in the
+    /// header.
+    pub fn new(inner: W, extension: [u8; 3]) -> CnxEncoder<W> {
+        CnxEncoder {
+            inner,
+            extension,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Gets a reference to the inner Write.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner Write.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Compress everything written and write it out, returning the inner
+    /// Write.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.inner.write_all(&compress(&self.buf, self.extension))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for CnxEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Does nothing, as nothing can be written before all input is known.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decompress CNX data from an inner Read.
+///
+/// The data is read and decompressed in full on the first read. Only as much
+/// as the header says the data takes is read, so data following it can still
+/// be read from the inner Read afterwards.
+pub struct CnxDecoder<R: Read> {
+    inner: R,
+    out: Option<Vec<u8>>,
+    pos: usize,
+}
+
+impl<R: Read> CnxDecoder<R> {
+    /// Create a decoder reading CNX data from `inner`.
+    pub fn new(inner: R) -> CnxDecoder<R> {
+        CnxDecoder {
+            inner,
+            out: None,
+            pos: 0,
+        }
+    }
+
+    /// Gets a reference to the inner Read.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner Read.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner Read.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn fill(&mut self) -> io::Result<&[u8]> {
+        if self.out.is_none() {
+            let mut header = [0; HEADER_LEN];
+            self.inner.read_exact(&mut header)?;
+            let compressed_size = read_header(&header)?.compressed_size as usize;
+            let mut src = header.to_vec();
+            (&mut self.inner)
+                .take(compressed_size as u64)
+                .read_to_end(&mut src)?;
+            if src.len() < HEADER_LEN + compressed_size {
+                return Err(invalid("CNX data is truncated"));
+            }
+            self.out = Some(decompress(&src)?);
+        }
+        Ok(&self.out.as_ref().unwrap()[self.pos..])
+    }
+}
+
+impl<R: Read> Read for CnxDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let rest = self.fill()?;
+        let len = std::cmp::min(rest.len(), buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,331 @@
+//! Traits for code that compresses and decompresses PRS without depending on
+//! a particular variant.
+//!
+//! Libraries built on this crate, such as archive readers, can take a
+//! [`PrsCompress`](trait.PrsCompress.html) or
+//! [`PrsDecompress`](trait.PrsDecompress.html) instead of naming `Legacy` or
+//! `Modern`, leaving the choice to their callers. [`Codec`](struct.Codec.html)
+//! implements both for each variant, and callers can supply their own
+//! implementations, e.g. to wrap a differently tuned encoder.
+//!
+//! Both traits are object safe, so a library handling several formats can
+//! keep a `Box<dyn PrsDecompress>` per entry. Besides the PRS variants, the
+//! [`Raw`](struct.Raw.html) codec stores data as is, and with the `lz` and
+//! `cnx` features [`Lz01`](struct.Lz01.html) and [`Cnx`](struct.Cnx.html)
+//! cover those formats. With the `std` feature, the traits also wrap a
+//! `Write` or `Read` to compress or decompress a stream.
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::Modern;
+//! use ages_prs::codec::{Codec, PrsCompress, PrsDecompress};
+//!
+//! fn roundtrip(codec: &(impl PrsCompress + PrsDecompress), data: &[u8]) -> Vec<u8> {
+//!     codec.decompress(&codec.compress(data)).unwrap()
+//! }
+//!
+//! let codec = Codec::<Modern>::new();
+//! assert_eq!(roundtrip(&codec, b"Hello Hello Hello "), b"Hello Hello Hello ");
+//! ```
+
+use crate::{DecompressError, Variant};
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::marker::PhantomData;
+
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "lz")]
+use std::io::Cursor;
+
+/// Compression of whole buffers and streams.
+pub trait PrsCompress {
+    /// Compress `src` into a new `Vec`.
+    fn compress(&self, src: &[u8]) -> Vec<u8>;
+
+    /// Wrap `writer` in an encoder compressing everything written to it.
+    ///
+    /// The stream is only complete once the encoder is
+    /// [`finish`](trait.FinishWrite.html#tymethod.finish)ed.
+    #[cfg(feature = "std")]
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> Box<dyn FinishWrite + 'a>;
+}
+
+/// Decompression of whole buffers and streams.
+pub trait PrsDecompress {
+    /// Decompress `src` into a new `Vec`.
+    fn decompress(&self, src: &[u8]) -> Result<Vec<u8>, DecompressError>;
+
+    /// Wrap `reader` in a decoder reading the decompressed data.
+    #[cfg(feature = "std")]
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a>;
+}
+
+/// An encoder returned by [`PrsCompress::encoder`](trait.PrsCompress.html#tymethod.encoder).
+#[cfg(feature = "std")]
+pub trait FinishWrite: Write {
+    /// Finish the compressed stream and flush it to the wrapped Write.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+#[cfg(feature = "std")]
+impl<W: Write, V: Variant> FinishWrite for crate::PrsEncoder<W, V> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let mut inner = (*self).into_inner().map_err(|e| e.into_error())?;
+        inner.flush()
+    }
+}
+
+/// The crate's own compressor and decompressor for variant `V`.
+pub struct Codec<V: Variant> {
+    limit: Option<u64>,
+    _pd: PhantomData<V>,
+}
+
+impl<V: Variant> Codec<V> {
+    /// Create a codec with no limit on decompressed size.
+    pub fn new() -> Codec<V> {
+        Codec {
+            limit: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Create a codec failing with `DecompressError::LimitExceeded` when more
+    /// than `limit` bytes would be decompressed.
+    ///
+    /// See [`decompress_with_limit`](../fn.decompress_with_limit.html).
+    pub fn with_limit(limit: u64) -> Codec<V> {
+        Codec {
+            limit: Some(limit),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<V: Variant> Default for Codec<V> {
+    fn default() -> Codec<V> {
+        Codec::new()
+    }
+}
+
+impl<V: Variant> Clone for Codec<V> {
+    fn clone(&self) -> Codec<V> {
+        Codec {
+            limit: self.limit,
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<V: Variant> fmt::Debug for Codec<V> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Codec")
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+impl<V: Variant + 'static> PrsCompress for Codec<V> {
+    fn compress(&self, src: &[u8]) -> Vec<u8> {
+        crate::compress::<V>(src)
+    }
+
+    #[cfg(feature = "std")]
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> Box<dyn FinishWrite + 'a> {
+        Box::new(crate::PrsEncoder::<_, V>::new(writer))
+    }
+}
+
+impl<V: Variant + 'static> PrsDecompress for Codec<V> {
+    fn decompress(&self, src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        match self.limit {
+            Some(limit) => crate::decompress_with_limit::<V>(src, limit),
+            None => crate::decompress::<V>(src),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        match self.limit {
+            Some(limit) => Box::new(crate::PrsDecoder::<_, V>::with_limit(reader, limit)),
+            None => Box::new(crate::PrsDecoder::<_, V>::new(reader)),
+        }
+    }
+}
+
+/// A codec storing data uncompressed, for archive entries that aren't
+/// compressed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Raw;
+
+impl PrsCompress for Raw {
+    fn compress(&self, src: &[u8]) -> Vec<u8> {
+        src.to_vec()
+    }
+
+    #[cfg(feature = "std")]
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> Box<dyn FinishWrite + 'a> {
+        Box::new(RawEncoder(writer))
+    }
+}
+
+impl PrsDecompress for Raw {
+    fn decompress(&self, src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        Ok(src.to_vec())
+    }
+
+    #[cfg(feature = "std")]
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        reader
+    }
+}
+
+#[cfg(feature = "std")]
+struct RawEncoder<'a>(Box<dyn Write + 'a>);
+
+#[cfg(feature = "std")]
+impl<'a> Write for RawEncoder<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> FinishWrite for RawEncoder<'a> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// The [LZ01](../lz01/index.html) format.
+#[cfg(feature = "lz")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lz01;
+
+#[cfg(feature = "lz")]
+impl PrsCompress for Lz01 {
+    fn compress(&self, src: &[u8]) -> Vec<u8> {
+        crate::lz01::compress(src)
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> Box<dyn FinishWrite + 'a> {
+        Box::new(BufferedEncoder {
+            inner: writer,
+            buf: Vec::new(),
+            compress: crate::lz01::compress,
+        })
+    }
+}
+
+#[cfg(feature = "lz")]
+impl PrsDecompress for Lz01 {
+    fn decompress(&self, src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        crate::lz01::decompress(src).map_err(DecompressError::Io)
+    }
+
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        Box::new(BufferedDecoder {
+            inner: Some(reader),
+            out: Cursor::new(Vec::new()),
+            decompress: crate::lz01::decompress,
+        })
+    }
+}
+
+/// The [CNX](../cnx/index.html) format, recording `extension` in the headers
+/// of compressed data.
+#[cfg(feature = "cnx")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cnx {
+    /// Extension of the original file, padded with zeros.
+    pub extension: [u8; 3],
+}
+
+#[cfg(feature = "cnx")]
+impl PrsCompress for Cnx {
+    fn compress(&self, src: &[u8]) -> Vec<u8> {
+        crate::cnx::compress(src, self.extension)
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + 'a>) -> Box<dyn FinishWrite + 'a> {
+        Box::new(crate::cnx::CnxEncoder::new(writer, self.extension))
+    }
+}
+
+#[cfg(feature = "cnx")]
+impl PrsDecompress for Cnx {
+    fn decompress(&self, src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        crate::cnx::decompress(src).map_err(DecompressError::Io)
+    }
+
+    fn decoder<'a>(&self, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        Box::new(crate::cnx::CnxDecoder::new(reader))
+    }
+}
+
+#[cfg(feature = "cnx")]
+impl<W: Write> FinishWrite for crate::cnx::CnxEncoder<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        (*self).into_inner().map(drop)
+    }
+}
+
+/// Encoder for formats that can only be written once all input is known.
+#[cfg(feature = "lz")]
+struct BufferedEncoder<'a> {
+    inner: Box<dyn Write + 'a>,
+    buf: Vec<u8>,
+    compress: fn(&[u8]) -> Vec<u8>,
+}
+
+#[cfg(feature = "lz")]
+impl<'a> Write for BufferedEncoder<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "lz")]
+impl<'a> FinishWrite for BufferedEncoder<'a> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.inner.write_all(&(self.compress)(&self.buf))?;
+        self.inner.flush()
+    }
+}
+
+/// Decoder for formats that are decompressed in one go, reading all of the
+/// input on the first read.
+#[cfg(feature = "lz")]
+struct BufferedDecoder<'a> {
+    inner: Option<Box<dyn Read + 'a>>,
+    out: Cursor<Vec<u8>>,
+    decompress: fn(&[u8]) -> io::Result<Vec<u8>>,
+}
+
+#[cfg(feature = "lz")]
+impl<'a> Read for BufferedDecoder<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(inner) = self.inner.as_mut() {
+            let mut src = Vec::new();
+            inner.read_to_end(&mut src)?;
+            self.out = Cursor::new((self.decompress)(&src)?);
+            self.inner = None;
+        }
+        self.out.read(buf)
+    }
+}
@@ -1,89 +1,673 @@
 //! Compression routine for PRS
 
-use crate::Variant;
+use crate::{CommandForm, Variant};
+use crate::variant::private::BitsMut;
+use crate::decompress::Status;
+use crate::lz77::{Code, Lz77Encoder, Sink};
+#[cfg(feature = "std")]
+use crate::progress::Progress;
 
-use std::fmt;
-use std::error;
-use std::io::{self, Write};
+use core::error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, IoSlice, Write};
 
-use libflate_lz77::{
-    Code,
-    DefaultLz77Encoder,
-    DefaultLz77EncoderBuilder,
-    Lz77Encode,
-    Sink,
-    MAX_LENGTH,
-};
+use alloc::vec::Vec;
+
+/// Returns the largest possible size of a PRS stream compressed from
+/// `input_len` bytes, including command bits and the end of stream marker.
+///
+/// This is the size to allocate when compressing into a fixed destination
+/// buffer. The bound is the same for every variant.
+pub fn compress_bound<V: Variant>(input_len: usize) -> usize {
+    // no command costs more than emitting its bytes as literals, which is one
+    // command bit and one byte each. the stream ends with a 2 bit long ptr
+    // command and its zero offset
+    let cmd_bits = input_len + 2;
+    input_len + cmd_bits.div_ceil(8) + 2
+}
+
+/// Compress a buffer into a new `Vec`.
+pub fn compress<V: Variant>(src: &[u8]) -> Vec<u8> {
+    let mut sink = PrsSink::<V>::new(compress_bound::<V>(src.len()));
+    let mut encoder = lz77_encoder::<V>();
+    encoder.encode(src, &mut sink);
+    encoder.flush(&mut sink);
+    sink.write_eof();
+    sink.out
+}
+
+/// Compress a buffer into a new `Vec`, ending the stream as `terminator`
+/// says.
+pub(crate) fn compress_terminated<V: Variant>(src: &[u8], terminator: &Terminator) -> Vec<u8> {
+    let mut sink = PrsSink::<V>::new(compress_bound::<V>(src.len()));
+    let mut encoder = lz77_encoder::<V>();
+    encoder.encode(src, &mut sink);
+    encoder.flush(&mut sink);
+    sink.write_terminator(terminator, 0);
+    sink.out
+}
+
+/// Compress a buffer into `dst`, replacing its contents.
+///
+/// Like [`compress`](fn.compress.html), but reusing `dst`'s allocation, so
+/// compressing many buffers in turn into the same `Vec` only allocates
+/// when one compresses larger than any before.
+pub fn compress_to_buf<V: Variant>(src: &[u8], dst: &mut Vec<u8>) {
+    dst.clear();
+    dst.reserve(compress_bound::<V>(src.len()));
+    let mut sink = PrsSink::<V>::with_buf(core::mem::take(dst));
+    let mut encoder = lz77_encoder::<V>();
+    encoder.encode(src, &mut sink);
+    encoder.flush(&mut sink);
+    sink.write_eof();
+    *dst = sink.out;
+}
+
+/// Compress a buffer into `dst`, returning the compressed length.
+///
+/// Nothing is allocated for the output. If the compressed stream does not
+/// fit, this fails with `CompressError::BufferTooSmall`; `dst` may have been
+/// partially written in that case. A destination of
+/// [`compress_bound`](fn.compress_bound.html) bytes is always large enough.
+pub fn compress_into<V: Variant>(
+    src: &[u8],
+    dst: &mut [u8],
+) -> Result<usize, CompressError> {
+    let buf = SliceBuf { buf: dst, len: 0, overflow: false };
+    let mut sink = PrsSink::<V, _>::with_buf(buf);
+    let mut encoder = lz77_encoder::<V>();
+    encoder.encode(src, &mut sink);
+    encoder.flush(&mut sink);
+    sink.write_eof();
+    let buf = sink.out;
+
+    if buf.overflow {
+        return Err(CompressError::BufferTooSmall);
+    }
+    Ok(buf.len)
+}
+
+/// Compute the size of the PRS stream `compress` would produce for `src`,
+/// without producing it.
+///
+/// The matcher runs as usual, but commands are only counted, so nothing is
+/// allocated for the output. Useful for deciding whether to store data
+/// compressed at all.
+pub fn estimate_compressed_size<V: Variant>(src: &[u8]) -> usize {
+    let mut sink = PrsSink::<V, _>::with_buf(CountBuf(0));
+    let mut encoder = lz77_encoder::<V>();
+    encoder.encode(src, &mut sink);
+    encoder.flush(&mut sink);
+    sink.write_eof();
+    sink.out.0
+}
+
+pub(crate) fn lz77_encoder<V: Variant>() -> Lz77Encoder {
+    Lz77Encoder::new(V::MAX_COPY_LENGTH)
+}
 
 /// An IO sink for compressing and encoding a stream to PRS.
+///
+/// Writes first write out compressed data left over from earlier writes, and
+/// fail without consuming any input if that fails. Once input is accepted,
+/// errors writing out its compressed data are reported by the next write or
+/// flush instead. A write failing with `WouldBlock` can therefore be retried
+/// with the same data, so the encoder works over non-blocking sinks.
+#[cfg(feature = "std")]
 pub struct PrsEncoder<W: Write, V: Variant> {
-    sink: Option<PrsSink<V>>,
+    sink: PrsSink<V>,
+    /// how much of `sink.out` has already been written to `inner`
+    flushed: usize,
     inner: Option<W>,
-    encoder: DefaultLz77Encoder,
-    _pd: std::marker::PhantomData<V>,
+    encoder: Lz77Encoder,
+    /// whether the EOF command has been written to the sink
+    finished: bool,
+    finish_on_drop: bool,
+    terminator: Terminator,
+    total_in: u64,
+    total_out: u64,
+    /// most compressed data the stream may take
+    max_output: Option<u64>,
+    progress: Progress,
+    _pd: core::marker::PhantomData<V>,
+}
+
+/// Error returned when compressing into a buffer fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CompressError {
+    /// The destination buffer is too small for the compressed stream.
+    BufferTooSmall,
+    /// The compressed stream would exceed the encoder's output limit. See
+    /// [`PrsEncoder::with_max_output`](struct.PrsEncoder.html#method.with_max_output).
+    LimitExceeded,
 }
 
-/// Error returned when `PrsEncoder::into_inner` fails.
+/// Error returned when `PrsEncoder::into_inner` or `PrsEncoder::finish`
+/// fails, holding the encoder so finishing the stream can be retried.
+#[cfg(feature = "std")]
 #[derive(Debug)]
-pub struct IntoInnerError<W>(W, io::Error);
+pub struct IntoInnerError<W>(Box<W>, io::Error);
+
+/// Statistics about a finished PRS stream, returned by
+/// [`PrsEncoder::finish`](struct.PrsEncoder.html#method.finish).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct EncodeStats {
+    /// Number of uncompressed bytes written to the encoder.
+    pub input_len: u64,
+    /// Number of compressed bytes written to the inner Write.
+    pub output_len: u64,
+    /// Number of literal commands.
+    pub literals: u64,
+    /// Number of short pointer commands.
+    pub short_pointers: u64,
+    /// Number of long pointer commands.
+    pub long_pointers: u64,
+}
+
+impl EncodeStats {
+    /// Compressed size as a fraction of the uncompressed size. Lower is
+    /// better; values above 1 mean the data grew.
+    pub fn ratio(&self) -> f64 {
+        if self.input_len == 0 {
+            return 1.0;
+        }
+        self.output_len as f64 / self.input_len as f64
+    }
+}
+
+/// How an encoder ends a PRS stream, set with
+/// [`PrsEncoder::set_terminator`](struct.PrsEncoder.html#method.set_terminator)
+/// or [`Compress::set_terminator`](struct.Compress.html#method.set_terminator).
+///
+/// Every stream ends with the end of stream command. Encoders differ in what
+/// they do with the flag bits left over in the last command byte and
+/// whether they pad the stream afterward, and some game loaders check for
+/// what their own encoder wrote. The default leaves the flag bits clear and
+/// adds nothing, like the original encoders.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{Compress, Legacy, Terminator};
+///
+/// let mut state = Compress::<Legacy>::new();
+/// state.set_terminator(Terminator::new().align(4));
+/// let mut out = [0; 64];
+/// state.run(b"Hello", &mut out);
+/// let len = state.total_out() as usize;
+/// state.finish(&mut out[len..]);
+/// assert_eq!(state.total_out() % 4, 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Terminator {
+    fill_bits: bool,
+    trailing_zeros: usize,
+    align: usize,
+}
+
+impl Terminator {
+    /// End streams with just the end of stream command.
+    pub fn new() -> Terminator {
+        Terminator::default()
+    }
+
+    /// Set whether the flag bits left over after the end of stream command
+    /// are set rather than clear.
+    pub fn fill_bits(mut self, fill_bits: bool) -> Terminator {
+        self.fill_bits = fill_bits;
+        self
+    }
+
+    /// Append `count` zero bytes after the end of stream command.
+    pub fn trailing_zeros(mut self, count: usize) -> Terminator {
+        self.trailing_zeros = count;
+        self
+    }
+
+    /// Pad the stream with zero bytes to a multiple of `align` bytes, after
+    /// any trailing zeros. 0 and 1 turn this off.
+    pub fn align(mut self, align: usize) -> Terminator {
+        self.align = align;
+        self
+    }
+}
+
+/// Settings for creating [`PrsEncoder`](struct.PrsEncoder.html)s, in one
+/// place rather than spread across constructors and setters.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Write;
+///
+/// use ages_prs::{Modern, PrsEncoderBuilder, Terminator};
+///
+/// let builder = PrsEncoderBuilder::<Modern>::new()
+///     .dictionary(b"Hello ")
+///     .terminator(Terminator::new().align(4));
+/// let mut encoder = builder.build(Vec::new());
+/// encoder.write_all(b"Hello Hello").unwrap();
+/// assert_eq!(encoder.into_inner().unwrap().len() % 4, 0);
+/// ```
+#[cfg(feature = "std")]
+pub struct PrsEncoderBuilder<V: Variant> {
+    capacity: usize,
+    dictionary: Vec<u8>,
+    terminator: Terminator,
+    finish_on_drop: bool,
+    max_output: Option<u64>,
+    _pd: core::marker::PhantomData<V>,
+}
 
+#[cfg(feature = "std")]
+impl<V: Variant> PrsEncoderBuilder<V> {
+    /// Start from the settings of [`PrsEncoder::new`](struct.PrsEncoder.html#method.new).
+    pub fn new() -> PrsEncoderBuilder<V> {
+        PrsEncoderBuilder {
+            capacity: 0,
+            dictionary: Vec::new(),
+            terminator: Terminator::new(),
+            finish_on_drop: true,
+            max_output: None,
+            _pd: core::marker::PhantomData,
+        }
+    }
+
+    /// Reserve `capacity` bytes for buffered compressed data. See
+    /// [`PrsEncoder::with_capacity`](struct.PrsEncoder.html#method.with_capacity).
+    pub fn capacity(mut self, capacity: usize) -> PrsEncoderBuilder<V> {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Compress against the preset dictionary `dict`. See
+    /// [`PrsEncoder::with_dictionary`](struct.PrsEncoder.html#method.with_dictionary).
+    pub fn dictionary(mut self, dict: &[u8]) -> PrsEncoderBuilder<V> {
+        self.dictionary.clear();
+        self.dictionary.extend_from_slice(dict);
+        self
+    }
+
+    /// Set how streams are ended. See [`Terminator`](struct.Terminator.html).
+    pub fn terminator(mut self, terminator: Terminator) -> PrsEncoderBuilder<V> {
+        self.terminator = terminator;
+        self
+    }
+
+    /// Set whether dropping the encoder finishes the PRS stream. See
+    /// [`PrsEncoder::set_finish_on_drop`](struct.PrsEncoder.html#method.set_finish_on_drop).
+    pub fn finish_on_drop(mut self, finish_on_drop: bool) -> PrsEncoderBuilder<V> {
+        self.finish_on_drop = finish_on_drop;
+        self
+    }
+
+    /// Fail once the compressed stream would exceed `limit` bytes. See
+    /// [`PrsEncoder::with_max_output`](struct.PrsEncoder.html#method.with_max_output).
+    pub fn max_output(mut self, limit: u64) -> PrsEncoderBuilder<V> {
+        self.max_output = Some(limit);
+        self
+    }
+
+    /// Create an encoder with these settings, wrapping `inner`.
+    pub fn build<W: Write>(&self, inner: W) -> PrsEncoder<W, V> {
+        let mut encoder = PrsEncoder::with_capacity(inner, self.capacity);
+        if !self.dictionary.is_empty() {
+            encoder.encoder.set_dictionary(&self.dictionary);
+        }
+        encoder.terminator = self.terminator;
+        encoder.finish_on_drop = self.finish_on_drop;
+        encoder.max_output = self.max_output;
+        encoder
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Variant> Default for PrsEncoderBuilder<V> {
+    fn default() -> PrsEncoderBuilder<V> {
+        PrsEncoderBuilder::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Variant> Clone for PrsEncoderBuilder<V> {
+    fn clone(&self) -> PrsEncoderBuilder<V> {
+        PrsEncoderBuilder {
+            capacity: self.capacity,
+            dictionary: self.dictionary.clone(),
+            terminator: self.terminator,
+            finish_on_drop: self.finish_on_drop,
+            max_output: self.max_output,
+            _pd: core::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V: Variant> fmt::Debug for PrsEncoderBuilder<V> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("PrsEncoderBuilder")
+            .field("capacity", &self.capacity)
+            .field("dictionary_len", &self.dictionary.len())
+            .field("terminator", &self.terminator)
+            .field("finish_on_drop", &self.finish_on_drop)
+            .field("max_output", &self.max_output)
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
 impl<W: Write, V: Variant> PrsEncoder<W, V> {
     /// Wraps a Write sink, initializing the encoder state
     pub fn new(inner: W) -> PrsEncoder<W, V> {
-        let encoder = DefaultLz77EncoderBuilder::new()
-            .window_size(8191)
-            .max_length(std::cmp::min(MAX_LENGTH, V::MAX_COPY_LENGTH))
-            .build();
-        
         PrsEncoder {
-            sink: Some(PrsSink::new(32)),
+            sink: PrsSink::new(32),
+            flushed: 0,
             inner: Some(inner),
-            encoder,
-            _pd: std::marker::PhantomData,
+            encoder: lz77_encoder::<V>(),
+            finished: false,
+            finish_on_drop: true,
+            terminator: Terminator::new(),
+            total_in: 0,
+            total_out: 0,
+            max_output: None,
+            progress: Progress::new("encode"),
+            _pd: core::marker::PhantomData,
+        }
+    }
+
+    /// Wraps a Write sink, reserving `capacity` bytes for compressed data
+    /// waiting to be written out rather than 32.
+    ///
+    /// The buffer grows as needed either way. Sizing it for the output of
+    /// a typical write avoids growing it in small steps at the start.
+    pub fn with_capacity(inner: W, capacity: usize) -> PrsEncoder<W, V> {
+        let mut encoder = PrsEncoder::new(inner);
+        encoder.sink.out.reserve(capacity);
+        encoder
+    }
+
+    /// Wraps a Write sink, failing once the compressed stream would be longer
+    /// than `limit` bytes.
+    ///
+    /// For data that has to fit a fixed size slot. The limit is checked as
+    /// compressed data is produced, so an oversized stream fails on the write
+    /// that crosses it instead of once it is finished, and nothing past the
+    /// limit reaches the inner Write. Writes, flushes and finishing then fail
+    /// with an `InvalidInput` error wrapping `CompressError::LimitExceeded`,
+    /// leaving [`abort`](#method.abort) or
+    /// [`into_parts`](#method.into_parts) to recover the inner Write.
+    ///
+    /// The limit counts the end of stream command and the terminator's
+    /// padding, and applies to each stream after a [`reset`](#method.reset).
+    pub fn with_max_output(inner: W, limit: u64) -> PrsEncoder<W, V> {
+        let mut encoder = PrsEncoder::new(inner);
+        encoder.max_output = Some(limit);
+        encoder
+    }
+
+    /// Wraps a Write sink, compressing against the preset dictionary `dict`.
+    ///
+    /// Data similar to the dictionary compresses better, since pointers can
+    /// copy from it as if it preceded the input. The stream can only be
+    /// decompressed with the same dictionary, and only the last 8 KiB of it
+    /// are used.
+    pub fn with_dictionary(inner: W, dict: &[u8]) -> PrsEncoder<W, V> {
+        let mut encoder = PrsEncoder::new(inner);
+        encoder.encoder.set_dictionary(dict);
+        encoder
+    }
+
+    /// Call `f` with the number of uncompressed bytes written and compressed
+    /// bytes flushed to the inner Write so far, every time they change.
+    ///
+    /// Both counts start over when the encoder is [`reset`](#method.reset).
+    pub fn on_progress<F>(&mut self, f: F)
+    where
+        F: FnMut(u64, u64) + Send + Sync + 'static,
+    {
+        self.progress.set(f);
+    }
+
+    /// Set whether dropping the encoder finishes the PRS stream. Enabled by
+    /// default.
+    ///
+    /// Errors can't be reported from a drop, so a stream finished that way
+    /// may be silently truncated. With this disabled, an encoder dropped
+    /// without [`try_finish`](#method.try_finish) or
+    /// [`finish`](#method.finish) leaves a stream with no end of stream
+    /// command, which decoders reject instead of accepting as complete.
+    pub fn set_finish_on_drop(&mut self, finish_on_drop: bool) {
+        self.finish_on_drop = finish_on_drop;
+    }
+
+    /// Set how streams are ended. See [`Terminator`](struct.Terminator.html).
+    pub fn set_terminator(&mut self, terminator: Terminator) {
+        self.terminator = terminator;
+    }
+
+    /// Reference the inner Write.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Mutably reference the inner Write.
+    ///
+    /// Writing to it directly will corrupt the PRS stream being encoded.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Number of uncompressed bytes written to the encoder so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Number of compressed bytes written to the inner Write so far.
+    ///
+    /// Compressed data still buffered by the encoder isn't counted, so this
+    /// is the length of the stream only once it is finished.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Number of compressed bytes buffered by the encoder, not yet written to
+    /// the inner Write.
+    ///
+    /// This includes the current command byte and everything after it, which
+    /// are held back until the byte's remaining command bits are written (see
+    /// [`buffered_command_bits`](#method.buffered_command_bits)), as well as
+    /// anything a failed or partial write left behind. Input still being
+    /// matched has not been compressed yet and isn't counted.
+    pub fn pending_bytes(&self) -> usize {
+        self.sink.out.len() - self.flushed
+    }
+
+    /// Number of command bits in the current command byte, from 0 to 7.
+    ///
+    /// While nonzero and the stream is unfinished, the command byte and the
+    /// data following it can't be written out yet, since later commands still
+    /// have to set the rest of its bits.
+    pub fn buffered_command_bits(&self) -> u32 {
+        if self.finished {
+            0
+        } else {
+            self.sink.cmd_bits_used() as u32
         }
     }
 
     /// Finish encoding the PRS stream, returning the inner Write.
     ///
-    /// Errors will leave the PRS stream in an incomplete state; the E type is
-    /// only present to capture the inner Write for inspection. There is no way
-    /// to recover the broken PRS stream if this operation fails.
-    pub fn into_inner(mut self) -> Result<W, IntoInnerError<W>> {
-        match self.flush_buf() {
-            Err(e) => Err(IntoInnerError(self.inner.take().unwrap(), e)),
+    /// If writing to the inner Write fails, the error hands the encoder back
+    /// with the rest of the stream still buffered, so finishing can be retried
+    /// after a transient error such as `WouldBlock`.
+    pub fn into_inner(self) -> Result<W, IntoInnerError<PrsEncoder<W, V>>> {
+        self.finish().map(|(inner, _)| inner)
+    }
+
+    /// Finish encoding the PRS stream, returning the inner Write along with
+    /// statistics about the stream.
+    ///
+    /// Fails in the same way as [`into_inner`](#method.into_inner).
+    pub fn finish(
+        mut self,
+    ) -> Result<(W, EncodeStats), IntoInnerError<PrsEncoder<W, V>>> {
+        match self.finish_stream() {
+            Err(e) => Err(IntoInnerError(Box::new(self), e)),
             Ok(()) => {
-                let mut sink = self.sink.take().unwrap();
-                let mut inner = self.inner.take().unwrap();
-                self.encoder.flush(&mut sink);
-                let buf = sink.finish();
-
-                match inner.write_all(&buf[..]) {
-                    Err(e) => Err(IntoInnerError(inner, e)),
-                    Ok(_) => Ok(inner),
-                }
+                let stats = EncodeStats {
+                    input_len: self.total_in,
+                    output_len: self.total_out,
+                    literals: self.sink.literals,
+                    short_pointers: self.sink.short_pointers,
+                    long_pointers: self.sink.long_pointers,
+                };
+                Ok((self.inner.take().unwrap(), stats))
             },
         }
     }
 
-    /// Attempt to flush the intermediary buffer to the sink
-    fn flush_buf(&mut self) -> io::Result<()> {
-        let mut sink = self.sink.as_mut().unwrap();
-        let inner = self.inner.as_mut().unwrap();
+    /// Write out everything written so far so that it can be decompressed in
+    /// full, without finishing the stream, then flush the inner Write.
+    ///
+    /// For streams carrying messages over a long-lived connection, where the
+    /// receiver needs each message before the next is sent. PRS has no way
+    /// to mark such a point, so the rest of the current command byte is
+    /// handed to the next bytes written, up to 7, which are stored as
+    /// literals. The stream can't be finished until they have been written;
+    /// finishing earlier fails with `InvalidInput`.
+    pub fn flush_sync(&mut self) -> io::Result<()> {
+        self.check_unfinished()?;
+        self.encoder.flush(&mut self.sink);
+        let reserved = self.sink.reserve_literals();
+        self.encoder.force_literals(reserved as usize);
+        // the cmd byte is full, so everything up to the end can be written
+        self.flush_buf(true)?;
+        self.inner.as_mut().unwrap().flush()
+    }
 
-        // everything before the current cmd index is safe to write
-        let high_water = sink.cmd_index;
-        if high_water == 0 {
-            // don't flush; we don't have a saturated command byte yet
-            return Ok(());
+    /// Finish encoding the PRS stream and write out everything that is
+    /// buffered, without unwrapping the inner Write.
+    ///
+    /// Can be retried if writing to the inner Write fails. Once it succeeds,
+    /// dropping the encoder writes nothing more, and further writes fail with
+    /// `InvalidInput` until the encoder is [`reset`](#method.reset).
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.finish_stream()
+    }
+
+    /// Abandon the PRS stream, returning the inner Write.
+    ///
+    /// Nothing more is written to the inner Write, which is left holding an
+    /// incomplete PRS stream. Unlike dropping the encoder, this doesn't try
+    /// to terminate the stream first.
+    pub fn abort(self) -> W {
+        self.into_parts().0
+    }
+
+    /// Abandon the PRS stream, returning the inner Write along with the
+    /// compressed data that was buffered but not yet written to it.
+    ///
+    /// The buffered data continues the incomplete stream already written to
+    /// the inner Write. It ends partway through a command, and input still
+    /// being matched by the encoder is not part of it.
+    pub fn into_parts(mut self) -> (W, Vec<u8>) {
+        let buffered = self.sink.out[self.flushed..].to_vec();
+        (self.inner.take().unwrap(), buffered)
+    }
+
+    /// Finish the current PRS stream and start a new one on `inner`, returning
+    /// the previous Write.
+    ///
+    /// The encoder's buffers are kept, so compressing many small streams with
+    /// one encoder avoids reallocating them for each stream. If finishing the
+    /// current stream fails, the error is returned and the encoder still
+    /// holds the previous Write.
+    pub fn reset(&mut self, inner: W) -> io::Result<W> {
+        self.finish_stream()?;
+        self.encoder.reset();
+        self.sink.reset();
+        self.flushed = 0;
+        self.finished = false;
+        self.total_in = 0;
+        self.total_out = 0;
+        self.progress.start();
+        Ok(self.inner.replace(inner).unwrap())
+    }
+
+    /// Terminate the PRS stream if that hasn't happened yet, then write out
+    /// everything that is buffered.
+    fn finish_stream(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.encoder.flush(&mut self.sink);
+            if self.sink.reserved_literals() > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "too few bytes were written after flush_sync to end the stream"
+                ));
+            }
+            let written = self.total_out - self.flushed as u64;
+            self.sink.write_terminator(&self.terminator, written);
+            self.finished = true;
         }
+        self.flush_buf(false)?;
+        self.progress.finish(self.total_in, self.total_out);
+        Ok(())
+    }
+
+    /// Fail if the EOF command has been written, since nothing can follow it.
+    fn check_unfinished(&self) -> io::Result<()> {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the PRS stream is already finished"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fail if more compressed data has been produced than the output limit
+    /// allows.
+    fn check_output(&self) -> io::Result<()> {
+        if let Some(limit) = self.max_output {
+            let produced = self.total_out + (self.sink.out.len() - self.flushed) as u64;
+            if produced > limit {
+                return Err(CompressError::LimitExceeded.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Attempt to flush the intermediary buffer to the sink
+    fn flush_buf(&mut self, all: bool) -> io::Result<()> {
+        self.check_output()?;
+
+        // everything before the current cmd index is safe to write, unless
+        // the stream is finished, all was asked for or the cmd byte is full,
+        // because no more bits will be written to it
+        let high_water = if self.finished || all || self.sink.cmd_bits_rem == 0 {
+            self.sink.out.len()
+        } else {
+            self.sink.cmd_index
+        };
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            parent: self.progress.span(),
+            buffered = self.sink.out.len() - self.flushed,
+            writable = high_water.saturating_sub(self.flushed),
+            "flushing compressed data"
+        );
+
+        let sink = &mut self.sink;
+        let inner = self.inner.as_mut().unwrap();
 
-        let mut written = 0;
-        let len = high_water;
         let mut ret: io::Result<()> = Ok(());
 
-        while written < len {
-            // only write up to len bytes this flush
-            let r = inner.write(&sink.out[written..len]);
+        while self.flushed < high_water {
+            // only write up to high_water bytes this flush
+            let r = inner.write(&sink.out[self.flushed..high_water]);
 
             match r {
                 Ok(0) => {
@@ -93,7 +677,10 @@ impl<W: Write, V: Variant> PrsEncoder<W, V> {
                     ));
                     break;
                 },
-                Ok(n) => written += n,
+                Ok(n) => {
+                    self.flushed += n;
+                    self.total_out += n as u64;
+                },
                 Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
                 Err(e) => {
                     ret = Err(e);
@@ -101,47 +688,70 @@ impl<W: Write, V: Variant> PrsEncoder<W, V> {
                 }
             }
         }
-        if written > 0 {
-            sink.out.drain(..written);
-            sink.cmd_index -= written;
+
+        // drop the written bytes once they make up half the buffer, so the
+        // cost of moving the rest down is amortized over many flushes
+        if self.flushed > 0 && self.flushed * 2 >= sink.out.len() {
+            sink.out.drain(..self.flushed);
+            sink.cmd_index = sink.cmd_index.saturating_sub(self.flushed);
+            self.flushed = 0;
         }
+        self.progress.report(self.total_in, self.total_out);
         ret
     }
 }
 
+#[cfg(feature = "std")]
 impl<W: Write, V: Variant> Write for PrsEncoder<W, V> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_unfinished()?;
+        // write out what is left from earlier calls first, so an error here
+        // leaves the input unconsumed and the call can simply be retried
+        self.flush_buf(false)?;
         // unlike BufWriter we can't flush when buffer capacity is hit
-        {
-            self.encoder.encode(buf, self.sink.as_mut().unwrap());
-        }
+        self.encoder.encode(buf, &mut self.sink);
+        self.total_in += buf.len() as u64;
+        self.check_output()?;
         // we'll try to flush as much as possible since buffer perf is not
-        // the goal here; PrsEncoder<BufWriter<_>, _> is fine for that
-        self.flush_buf()?;
+        // the goal here; PrsEncoder<BufWriter<_>, _> is fine for that. the
+        // input is consumed either way, so errors wait for the next call
+        let _ = self.flush_buf(false);
         Ok(buf.len())
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        // encode every slice before flushing once. the buffered output is
+        // contiguous, so the inner Write gets a single plain write regardless
+        self.check_unfinished()?;
+        self.flush_buf(false)?;
+        let mut len = 0;
+        for buf in bufs {
+            self.encoder.encode(buf, &mut self.sink);
+            len += buf.len();
+        }
+        self.total_in += len as u64;
+        self.check_output()?;
+        let _ = self.flush_buf(false);
+        Ok(len)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
-        self.flush_buf().and_then(|()| self.inner.as_mut().unwrap().flush())
+        self.flush_buf(false).and_then(|()| self.inner.as_mut().unwrap().flush())
     }
 }
 
+#[cfg(feature = "std")]
 impl<W: Write, V: Variant> Drop for PrsEncoder<W, V> {
     fn drop(&mut self) {
-        if self.inner.is_some() && self.sink.is_some() {
-            let _r = self.flush_buf();
-            let mut sink = self.sink.take().unwrap();
-            let mut inner = self.inner.take().unwrap();
-            self.encoder.flush(&mut sink);
-            let buf = sink.finish();
-
+        if self.finish_on_drop && self.inner.is_some() {
             // we'll try to finish the stream but it is impossible to report
             // errors from a Drop
-            let _r = inner.write_all(&buf[..]);
+            let _r = self.finish_stream();
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl<W: Write, V: Variant> fmt::Debug for PrsEncoder<W, V>
 where
     W: fmt::Debug,
@@ -149,55 +759,485 @@ where
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("PrsEncoder")
             .field("writer", &self.inner.as_ref().unwrap())
-            .field("buffer", &self.sink.as_ref().unwrap().out)
+            .field("buffer", &&self.sink.out[self.flushed..])
             .finish()
     }
 }
 
+#[cfg(feature = "std")]
 impl<W> fmt::Display for IntoInnerError<W> {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> fmt::Result {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(fmt, "Failed to complete PRS stream: {}", self.1)
     }
 }
 
+#[cfg(feature = "std")]
 impl<W: Send + fmt::Debug> error::Error for IntoInnerError<W> {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         Some(&self.1)
     }
 }
 
+impl fmt::Display for CompressError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::BufferTooSmall => {
+                write!(fmt, "destination buffer too small")
+            },
+            CompressError::LimitExceeded => {
+                write!(fmt, "compressed output exceeds the limit")
+            },
+        }
+    }
+}
+
+impl error::Error for CompressError {}
+
+#[cfg(feature = "std")]
+impl From<CompressError> for io::Error {
+    fn from(e: CompressError) -> io::Error {
+        let kind = match e {
+            CompressError::BufferTooSmall => io::ErrorKind::WriteZero,
+            CompressError::LimitExceeded => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+#[cfg(feature = "std")]
 impl<W> IntoInnerError<W> {
     /// Reference the IO error that failed the operation.
     pub fn error(&self) -> &io::Error {
         &self.1
     }
 
-    /// Retrieve the inner type.
+    /// Retrieve the encoder that failed to finish.
     pub fn into_inner(self) -> W {
-        self.0
+        *self.0
+    }
+
+    /// Retrieve the IO error that failed the operation, dropping the encoder.
+    pub fn into_error(self) -> io::Error {
+        self.1
+    }
+
+    /// Retrieve both the IO error and the encoder that failed to finish.
+    pub fn into_parts(self) -> (io::Error, W) {
+        (self.1, *self.0)
+    }
+}
+
+/// Resumable PRS encoder operating on caller-provided input and output
+/// buffers.
+///
+/// This is the state machine behind the encoders in this crate, for callers
+/// that can't use the standard IO traits. Input is fed through
+/// [`run`](#method.run) and the stream is terminated with
+/// [`finish`](#method.finish). Progress is reported through
+/// [`total_in`](#method.total_in) and [`total_out`](#method.total_out).
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{decompress, Compress, Modern, Status};
+///
+/// let mut state = Compress::<Modern>::new();
+/// let mut out = [0; 64];
+/// assert_eq!(state.run(b"Hello Hello Hello ", &mut out), Status::NeedsInput);
+/// let len = state.total_out() as usize;
+/// assert_eq!(state.finish(&mut out[len..]), Status::Done);
+///
+/// let compressed = &out[..state.total_out() as usize];
+/// assert_eq!(&decompress::<Modern>(compressed).unwrap()[..], b"Hello Hello Hello ");
+/// ```
+pub struct Compress<V: Variant> {
+    sink: PrsSink<V>,
+    /// how much of `sink.out` has already been written out
+    pos: usize,
+    encoder: Lz77Encoder,
+    /// whether the EOF command has been written to the sink
+    finished: bool,
+    terminator: Terminator,
+    total_in: u64,
+    total_out: u64,
+}
+
+impl<V: Variant> Compress<V> {
+    /// Create an encoder for a new PRS stream.
+    pub fn new() -> Compress<V> {
+        Compress {
+            sink: PrsSink::new(32),
+            pos: 0,
+            encoder: lz77_encoder::<V>(),
+            finished: false,
+            terminator: Terminator::new(),
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    /// Set how streams are ended. See [`Terminator`](struct.Terminator.html).
+    pub fn set_terminator(&mut self, terminator: Terminator) {
+        self.terminator = terminator;
+    }
+
+    /// Compress against the preset dictionary `dict`, starting a new stream.
+    ///
+    /// See [`PrsEncoder::with_dictionary`](struct.PrsEncoder.html#method.with_dictionary).
+    /// An empty dictionary turns this off.
+    pub fn set_dictionary(&mut self, dict: &[u8]) {
+        self.encoder.set_dictionary(dict);
+        self.reset();
+    }
+
+    /// Prepare to encode a new stream, keeping the buffer allocation and the
+    /// dictionary.
+    ///
+    /// Whatever remained of the previous stream is discarded.
+    pub fn reset(&mut self) {
+        self.encoder.reset();
+        self.sink.reset();
+        self.pos = 0;
+        self.finished = false;
+        self.total_in = 0;
+        self.total_out = 0;
+    }
+
+    /// Number of uncompressed bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Number of compressed bytes written out so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Compress `input`, writing as much of the stream as is ready into
+    /// `output`. Progress is reflected in `total_in` and `total_out`.
+    ///
+    /// Input is only consumed once all previously compressed data has been
+    /// written out, and then it is consumed entirely. `NeedsOutput` means
+    /// compressed data is still waiting; call again with more room.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called after [`finish`](#method.finish) with nonempty input.
+    pub fn run(&mut self, input: &[u8], output: &mut [u8]) -> Status {
+        let out_pos = self.drain(output);
+        if self.pending() > 0 {
+            return Status::NeedsOutput;
+        }
+        if !input.is_empty() {
+            assert!(!self.finished, "input after the end of the PRS stream");
+            self.encoder.encode(input, &mut self.sink);
+            self.total_in += input.len() as u64;
+            self.drain(&mut output[out_pos..]);
+        }
+
+        if self.pending() > 0 {
+            Status::NeedsOutput
+        } else if self.finished {
+            Status::Done
+        } else {
+            Status::NeedsInput
+        }
+    }
+
+    /// Terminate the stream, writing as much of it as fits into `output`.
+    ///
+    /// Returns `Done` once the whole stream has been written out, and
+    /// `NeedsOutput` until then; call again with more room.
+    pub fn finish(&mut self, output: &mut [u8]) -> Status {
+        if !self.finished {
+            self.encoder.flush(&mut self.sink);
+            let written = self.total_out - self.pos as u64;
+            self.sink.write_terminator(&self.terminator, written);
+            self.finished = true;
+        }
+        self.drain(output);
+
+        if self.pending() > 0 {
+            Status::NeedsOutput
+        } else {
+            Status::Done
+        }
+    }
+
+    /// Number of final bytes in the sink not yet written out.
+    fn pending(&self) -> usize {
+        // the byte at the cmd index still receives bits until the stream ends
+        let high_water = if self.finished {
+            self.sink.out.len()
+        } else {
+            self.sink.cmd_index
+        };
+        high_water - self.pos
+    }
+
+    /// Move as many final bytes as fit into `output`, returning the count.
+    fn drain(&mut self, output: &mut [u8]) -> usize {
+        let len = core::cmp::min(self.pending(), output.len());
+        output[..len].copy_from_slice(&self.sink.out[self.pos..self.pos + len]);
+        self.pos += len;
+        self.total_out += len as u64;
+
+        // drop the written bytes once they make up half the buffer
+        if self.pos > 0 && self.pos * 2 >= self.sink.out.len() {
+            self.sink.out.drain(..self.pos);
+            self.sink.cmd_index = self.sink.cmd_index.saturating_sub(self.pos);
+            self.pos = 0;
+        }
+        len
+    }
+}
+
+impl<V: Variant> Default for Compress<V> {
+    fn default() -> Compress<V> {
+        Compress::new()
+    }
+}
+
+/// Writer of individual PRS commands.
+///
+/// Where [`PrsEncoder`](struct.PrsEncoder.html) chooses its own commands,
+/// this emits exactly the literals and pointers it is given, for tools that
+/// need to reproduce a particular command layout. Pointers are written in
+/// their shortest encoding. The stream is terminated by
+/// [`finish`](#method.finish); dropping the writer leaves it incomplete.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{decompress, CommandWriter, Legacy};
+///
+/// let mut writer = CommandWriter::<_, Legacy>::new(Vec::new());
+/// writer.put_literal(b'a').unwrap();
+/// writer.put_literal(b'b').unwrap();
+/// writer.put_pointer(2, 6).unwrap();
+/// let compressed = writer.finish().unwrap();
+///
+/// assert_eq!(&decompress::<Legacy>(&compressed).unwrap()[..], b"abababab");
+/// ```
+#[cfg(feature = "std")]
+pub struct CommandWriter<W: Write, V: Variant> {
+    inner: W,
+    sink: PrsSink<V>,
+    /// bytes of the stream written so far, for checking pointers
+    output_len: u64,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write, V: Variant> CommandWriter<W, V> {
+    /// Wraps a Write sink to write a new PRS stream to.
+    pub fn new(inner: W) -> CommandWriter<W, V> {
+        CommandWriter {
+            inner,
+            sink: PrsSink::new(32),
+            output_len: 0,
+        }
+    }
+
+    /// Reference the inner Write.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Mutably reference the inner Write.
+    ///
+    /// Writing to it directly will corrupt the PRS stream being written.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Write a literal command.
+    pub fn put_literal(&mut self, b: u8) -> io::Result<()> {
+        self.sink.consume(Code::Literal(b));
+        self.output_len += 1;
+        self.flush_buf(false)
+    }
+
+    /// Write a pointer command copying `length` bytes starting `distance`
+    /// bytes back in the decompressed output.
+    ///
+    /// Fails with `InvalidInput` if the copy can't be encoded in this
+    /// variant or reaches back before the start of the output.
+    pub fn put_pointer(&mut self, distance: usize, length: usize) -> io::Result<()> {
+        if let Some(msg) = pointer_error::<V>(distance, length) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        if distance as u64 > self.output_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pointer copies from before the start of the output",
+            ));
+        }
+        self.sink.write_pointer(distance as u16, length as u16);
+        self.output_len += length as u64;
+        self.flush_buf(false)
+    }
+
+    /// Write the end of stream command and everything still buffered,
+    /// returning the inner Write.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.sink.write_eof();
+        self.flush_buf(true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Write out the buffered bytes that no more command bits will go into,
+    /// or all of them at the end of the stream. Small amounts are left
+    /// buffered until then.
+    fn flush_buf(&mut self, all: bool) -> io::Result<()> {
+        let high_water = if all {
+            self.sink.out.len()
+        } else {
+            self.sink.cmd_index
+        };
+        if all || high_water >= 4096 {
+            self.inner.write_all(&self.sink.out[..high_water])?;
+            self.sink.out.drain(..high_water);
+            self.sink.cmd_index = self.sink.cmd_index.saturating_sub(high_water);
+        }
+        Ok(())
+    }
+}
+
+/// Describe why a pointer can't be encoded in variant `V`, if it can't.
+#[cfg(feature = "std")]
+fn pointer_error<V: Variant>(distance: usize, length: usize) -> Option<&'static str> {
+    if distance == 0 || distance > V::MAX_COPY_DISTANCE as usize {
+        return Some("pointer distance can't be encoded in this variant");
+    }
+    let short = distance <= V::MAX_SHORT_COPY_DISTANCE as usize
+        && length >= V::MIN_COPY_LENGTH as usize
+        && length <= V::MAX_SHORT_COPY_LENGTH as usize;
+    let inline = (3..=9).contains(&length);
+    let sized = length >= V::MIN_LONG_COPY_LENGTH as usize
+        && length <= V::MAX_COPY_LENGTH as usize;
+    if short || inline || sized {
+        None
+    } else {
+        Some("pointer length can't be encoded in this variant")
     }
 }
 
 // ---- LZ77 Sink implementation ----
 
-struct PrsSink<V: Variant> {
+/// Storage for the bytes emitted by a `PrsSink`.
+pub(crate) trait SinkBuf {
+    fn len(&self) -> usize;
+    fn push(&mut self, b: u8);
+    /// Set bits in an already pushed command byte.
+    fn set_bits(&mut self, index: usize, bits: u8);
+}
+
+impl SinkBuf for Vec<u8> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn push(&mut self, b: u8) {
+        Vec::push(self, b);
+    }
+
+    fn set_bits(&mut self, index: usize, bits: u8) {
+        self[index] |= bits;
+    }
+}
+
+/// Fixed-size sink storage. Bytes past the end are dropped and the overflow
+/// is remembered, since an LZ77 `Sink` can't report errors.
+struct SliceBuf<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl<'a> SinkBuf for SliceBuf<'a> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, b: u8) {
+        match self.buf.get_mut(self.len) {
+            Some(dst) => {
+                *dst = b;
+                self.len += 1;
+            },
+            None => self.overflow = true,
+        }
+    }
+
+    fn set_bits(&mut self, index: usize, bits: u8) {
+        if let Some(dst) = self.buf.get_mut(index) {
+            *dst |= bits;
+        }
+    }
+}
+
+/// Sink storage that only counts the bytes pushed.
+struct CountBuf(usize);
+
+impl SinkBuf for CountBuf {
+    fn len(&self) -> usize {
+        self.0
+    }
+
+    fn push(&mut self, _b: u8) {
+        self.0 += 1;
+    }
+
+    fn set_bits(&mut self, _index: usize, _bits: u8) {}
+}
+
+pub(crate) struct PrsSink<V: Variant, B: SinkBuf = Vec<u8>> {
     /// index into `out` which is the current cmd stream head
-    cmd_index: usize,
+    pub(crate) cmd_index: usize,
     /// how many cmd bits can we still write
     cmd_bits_rem: u8,
+    /// how many of the next literals already have their cmd bits written
+    reserved_literals: u8,
     /// the output buffer
-    out: Vec<u8>,
+    pub(crate) out: B,
 
-    _pd: std::marker::PhantomData<V>,
+    literals: u64,
+    short_pointers: u64,
+    long_pointers: u64,
+
+    _pd: core::marker::PhantomData<V>,
 }
 
 impl<V: Variant> PrsSink<V> {
-    fn new(capacity: usize) -> PrsSink<V> {
+    pub(crate) fn new(capacity: usize) -> PrsSink<V> {
+        PrsSink::with_buf(Vec::with_capacity(capacity))
+    }
+}
+
+impl<V: Variant> PrsSink<V> {
+    pub(crate) fn reset(&mut self) {
+        self.cmd_index = 0;
+        self.cmd_bits_rem = 0;
+        self.reserved_literals = 0;
+        self.out.clear();
+        self.literals = 0;
+        self.short_pointers = 0;
+        self.long_pointers = 0;
+    }
+}
+
+impl<V: Variant, B: SinkBuf> PrsSink<V, B> {
+    fn with_buf(out: B) -> PrsSink<V, B> {
         PrsSink {
             cmd_index: 0,
             cmd_bits_rem: 0,
-            out: Vec::with_capacity(capacity),
-            _pd: std::marker::PhantomData,
+            reserved_literals: 0,
+            out,
+            literals: 0,
+            short_pointers: 0,
+            long_pointers: 0,
+            _pd: core::marker::PhantomData,
         }
     }
 
@@ -209,74 +1249,111 @@ impl<V: Variant> PrsSink<V> {
         }
 
         if bit {
-            self.out[self.cmd_index] |= 1 << (8 - self.cmd_bits_rem);
+            self.out.set_bits(self.cmd_index, 1 << (8 - self.cmd_bits_rem));
         }
 
         self.cmd_bits_rem -= 1;
     }
 
-    fn finish(mut self) -> Vec<u8> {
+    /// Number of bits written to the current cmd byte, or 0 if it is full.
+    #[cfg(feature = "std")]
+    pub(crate) fn cmd_bits_used(&self) -> u8 {
+        if self.cmd_bits_rem == 0 {
+            0
+        } else {
+            8 - self.cmd_bits_rem
+        }
+    }
+
+    /// Fill the rest of the current cmd byte with literal flags, returning
+    /// how many were written. The next that many commands must be literals.
+    #[cfg(feature = "std")]
+    pub(crate) fn reserve_literals(&mut self) -> u8 {
+        let count = self.cmd_bits_rem;
+        while self.cmd_bits_rem > 0 {
+            self.write_bit(true);
+        }
+        self.reserved_literals += count;
+        count
+    }
+
+    /// Number of literals whose cmd bits are already written.
+    #[cfg(feature = "std")]
+    pub(crate) fn reserved_literals(&self) -> u8 {
+        self.reserved_literals
+    }
+
+    pub(crate) fn write_eof(&mut self) {
         self.write_bit(false);
-        self.write_bit(true); // long ptr
-        self.out.push(0); // zero offset = EOF
-        self.out.push(0);
+        V::write_eof(self);
+    }
 
-        self.out
+    /// Write the end of stream command and whatever `terminator` adds after
+    /// it. `written` is the length of the stream already removed from `out`.
+    fn write_terminator(&mut self, terminator: &Terminator, written: u64) {
+        self.write_eof();
+        if terminator.fill_bits {
+            while self.cmd_bits_rem > 0 {
+                self.write_bit(true);
+            }
+        }
+        for _ in 0..terminator.trailing_zeros {
+            self.out.push(0);
+        }
+        if terminator.align > 1 {
+            let align = terminator.align as u64;
+            let len = written + self.out.len() as u64;
+            for _ in 0..(align - len % align) % align {
+                self.out.push(0);
+            }
+        }
+    }
+
+    /// Write a pointer command in its shortest encoding. The copy must be
+    /// encodable in this variant; see `pointer_error`.
+    pub(crate) fn write_pointer(&mut self, backward_distance: u16, length: u16) {
+        self.write_bit(false);
+        match V::write_pointer(self, backward_distance, length) {
+            CommandForm::ShortPointer => self.short_pointers += 1,
+            _ => self.long_pointers += 1,
+        }
+    }
+}
+
+impl<V: Variant, B: SinkBuf> BitsMut for PrsSink<V, B> {
+    fn put_bit(&mut self, bit: bool) {
+        self.write_bit(bit);
+    }
+
+    fn put_byte(&mut self, b: u8) {
+        self.out.push(b);
     }
 }
 
-impl<V: Variant> Sink for PrsSink<V> {
+impl<V: Variant, B: SinkBuf> Sink for PrsSink<V, B> {
     fn consume(&mut self, code: Code) {
         match code {
             Code::Literal(b) => {
-                self.write_bit(true);
+                self.literals += 1;
+                if self.reserved_literals > 0 {
+                    self.reserved_literals -= 1;
+                } else {
+                    self.write_bit(true);
+                }
                 self.out.push(b);
             },
             Code::Pointer { length, backward_distance } => {
                 // preconditions
-                if length < 2 {
+                if length < V::MIN_COPY_LENGTH {
                     panic!("copy length too small (< 2)");
                 }
                 if length > V::MAX_COPY_LENGTH {
                     panic!("copy length too large");
                 }
-                if backward_distance >= 8192 {
+                if backward_distance > V::MAX_COPY_DISTANCE {
                     panic!("copy distance too far (>8191)");
                 }
-
-                if backward_distance >= 256 || length > 5 {
-                    // long ptr
-                    self.write_bit(false);
-                    self.write_bit(true);
-
-                    let mut offset = backward_distance as i32;
-                    
-                    offset = -offset;
-                    offset <<= 3;
-                    if (length - 2) < 8 {
-                        offset |= (length - 2) as i32;
-                    }
-
-                    self.out.extend_from_slice(&(offset as u16).to_le_bytes());
-                    
-                    if (length - 2) >= 8 {
-                        let size = (
-                            length - (V::MIN_LONG_COPY_LENGTH as u16)
-                        ) as u8;
-                        self.out.push(size);
-                    }
-                } else {
-                    // short ptr
-                    self.write_bit(false);
-                    self.write_bit(false);
-
-                    let offset = backward_distance as i32;
-                    let size = (length - 2) as i32;
-                    
-                    self.write_bit(size & 0b10 > 0);
-                    self.write_bit(size & 0b01 > 0);
-                    self.out.push((-offset & 0xFF) as u8);
-                }
+                self.write_pointer(backward_distance, length);
             },
         }
     }
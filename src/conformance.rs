@@ -0,0 +1,159 @@
+//! Checking a variant against a corpus of known good decompressions.
+//!
+//! Contributors working out a new variant usually have sample files along
+//! with the data the game decompresses them to. [`run`](fn.run.html)
+//! decompresses each [`Fixture`](struct.Fixture.html) and compares the
+//! result to the expected data, and the [`Report`](struct.Report.html) says
+//! which fixtures passed and, for the others, where they first went wrong,
+//! as found by [`diff_reference`](../diff/fn.diff_reference.html).
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::{compress, Modern};
+//! use ages_prs::conformance::{run, Fixture};
+//!
+//! let data = [7; 20];
+//! let compressed = compress::<Modern>(&data);
+//! let fixtures = [
+//!     Fixture::new("ok", &compressed, &data),
+//!     Fixture::new("truncated", &compressed[..3], &data),
+//! ];
+//!
+//! let report = run::<Modern, _>(fixtures.iter().copied());
+//! assert_eq!(report.passed(), 1);
+//! assert_eq!(report.failures().next().unwrap().name, "truncated");
+//! ```
+
+use crate::diff::{diff_reference, Mismatch};
+use crate::{DecompressError, Variant};
+
+use alloc::vec::Vec;
+
+use core::fmt;
+
+/// A compressed sample and the data it should decompress to.
+#[derive(Debug, Clone, Copy)]
+pub struct Fixture<'a> {
+    /// Name identifying the fixture in reports, e.g. its file name.
+    pub name: &'a str,
+    /// The PRS stream.
+    pub compressed: &'a [u8],
+    /// The data the stream is known to decompress to.
+    pub expected: &'a [u8],
+}
+
+impl<'a> Fixture<'a> {
+    /// Create a fixture expecting `compressed` to decompress to `expected`.
+    pub fn new(name: &'a str, compressed: &'a [u8], expected: &'a [u8]) -> Fixture<'a> {
+        Fixture {
+            name,
+            compressed,
+            expected,
+        }
+    }
+}
+
+/// Outcome of checking one [`Fixture`](struct.Fixture.html).
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FixtureResult<'a> {
+    /// Name of the fixture.
+    pub name: &'a str,
+    /// The first command whose output disagrees with the expected data, or
+    /// `None` if the fixture passed.
+    pub mismatch: Option<Mismatch>,
+    /// The error decompressing the fixture, if it failed to decompress
+    /// rather than decompressing to the wrong data.
+    pub error: Option<DecompressError>,
+}
+
+impl<'a> FixtureResult<'a> {
+    /// Whether the fixture decompressed to exactly the expected data.
+    pub fn passed(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+impl<'a> fmt::Display for FixtureResult<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mismatch = match self.mismatch {
+            None => return write!(fmt, "PASS {}", self.name),
+            Some(ref mismatch) => mismatch,
+        };
+        write!(
+            fmt,
+            "FAIL {}: output differs at {}, command at input offset {}",
+            self.name,
+            mismatch.output_len,
+            mismatch.offset,
+        )?;
+        if let Some(command) = mismatch.command {
+            write!(fmt, " ({:?})", command)?;
+        }
+        if let Some(ref error) = self.error {
+            write!(fmt, ": {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Results of a conformance run, one per fixture in the order given.
+///
+/// Displays as one line per fixture.
+#[derive(Debug)]
+pub struct Report<'a> {
+    /// The result for each fixture.
+    pub results: Vec<FixtureResult<'a>>,
+}
+
+impl<'a> Report<'a> {
+    /// Number of fixtures that passed.
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    /// The fixtures that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &FixtureResult<'a>> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// Whether every fixture passed.
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed())
+    }
+}
+
+impl<'a> fmt::Display for Report<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            writeln!(fmt, "{}", result)?;
+        }
+        write!(fmt, "{} of {} passed", self.passed(), self.results.len())
+    }
+}
+
+/// Decompress each fixture as variant `V`, comparing it to the expected
+/// data.
+pub fn run<'a, V, I>(fixtures: I) -> Report<'a>
+where
+    V: Variant,
+    I: IntoIterator<Item = Fixture<'a>>,
+{
+    let results = fixtures
+        .into_iter()
+        .map(|fixture| {
+            let mismatch = diff_reference::<V>(fixture.compressed, fixture.expected);
+            let error = match mismatch {
+                Some(_) => crate::decompress::<V>(fixture.compressed).err(),
+                None => None,
+            };
+            FixtureResult {
+                name: fixture.name,
+                mismatch,
+                error,
+            }
+        })
+        .collect();
+    Report { results }
+}
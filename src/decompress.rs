@@ -1,44 +1,1255 @@
 //! Decompression of PRS buffers.
 
 use crate::Variant;
+use crate::variant::private::Bits;
+#[cfg(feature = "std")]
+use crate::progress::Progress;
 
-use std::collections::VecDeque;
-use std::io::{self, Cursor, Read, Write};
+use core::error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
 
 /// An IO source for decoding a PRS stream.
+///
+/// The inner Read is read a byte at a time so that nothing past the end of
+/// the PRS stream is consumed. Wrap it in a `BufReader`, or use
+/// [`bufread::PrsDecoder`](bufread/struct.PrsDecoder.html) to decode from an
+/// existing `BufRead`, when reads from it are expensive.
+///
+/// Memory use is constant: output is kept in a 16 KiB window for resolving
+/// pointer commands.
+///
+/// Over a seekable Read, the decoder implements `Seek` in the decompressed
+/// data. Seeking forward decodes and discards the data skipped. Seeking
+/// backward restarts decoding from the closest earlier checkpoint, a copy of
+/// the decoder state taken every 1 MiB of output passed over while seeking,
+/// or from the start of the stream. Each checkpoint takes about 16 KiB.
+///
+/// Decoders over a cloneable Read can be cloned to fork the decoding, e.g.
+/// to try interpreting the rest of the data in several ways. Clones don't
+/// share the [`on_progress`](#method.on_progress) callback; they start
+/// without one.
+#[cfg(feature = "std")]
 pub struct PrsDecoder<R: Read, V: Variant> {
     inner: R,
-    cmds: u8,
-    rem: u8,
-    copy_buf: VecDeque<u8>,
-    eof: bool,
-    pd: std::marker::PhantomData<V>,
+    state: Decompress<V>,
+    /// decoded data buffered for `BufRead`, allocated on first use
+    out_buf: Box<[u8]>,
+    /// size of `out_buf` once allocated
+    capacity: usize,
+    out_pos: usize,
+    out_len: usize,
+    progress: Progress,
+    /// expected decompressed size, for sizing `read_to_end` output
+    size_hint: Option<u64>,
+    /// position of the stream in the inner Read, found on the first seek
+    start: Option<u64>,
+    /// decoder states to resume from when seeking backward, in output order
+    checkpoints: Vec<Decompress<V>>,
+}
+
+/// Capacity of the buffer backing `PrsDecoder`'s `BufRead` impl.
+#[cfg(feature = "std")]
+const OUT_BUF_CAPACITY: usize = 8192;
+
+/// Longest chunk `decompress_with` hands out.
+const CHUNK_LEN: usize = 8192;
+
+/// Output between the checkpoints `PrsDecoder` takes while seeking.
+#[cfg(feature = "std")]
+const CHECKPOINT_INTERVAL: u64 = 1 << 20;
+
+/// Error returned when decompressing a PRS buffer fails.
+///
+/// Format errors record where decoding stopped: `offset` is the position in
+/// the compressed input of the command that failed and `output_len` is the
+/// amount of data decompressed before it.
+///
+/// `DecompressError` converts to and from `io::Error`. Format errors become
+/// `io::Error`s wrapping the `DecompressError`, and converting such an error
+/// back yields the original `DecompressError` rather than `Io`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DecompressError {
+    /// The input ended before the end of stream command.
+    Eof { offset: u64, output_len: u64 },
+    /// A pointer command copies from before the start of the output.
+    InvalidPointer { offset: u64, output_len: u64 },
+    /// The destination buffer is too small for the decompressed data.
+    BufferTooSmall { offset: u64, output_len: u64 },
+    /// The decompressed data would exceed the configured output limit.
+    LimitExceeded { offset: u64, output_len: u64 },
+    /// The decompressed data would exceed the configured expansion ratio.
+    RatioExceeded { offset: u64, output_len: u64 },
+    /// Data other than zero padding follows the end of the stream, found by
+    /// [`decompress_strict`](fn.decompress_strict.html). `offset` is the
+    /// position of the first such byte and `output_len` the length of the
+    /// decompressed data.
+    TrailingData { offset: u64, output_len: u64 },
+    /// Reading the compressed stream failed.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+/// Decoding failure before its position in the stream is attached.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Fault {
+    Eof,
+    InvalidPointer,
+    BufferTooSmall,
+    LimitExceeded,
+    RatioExceeded,
+}
+
+impl Fault {
+    pub(crate) fn at(self, offset: u64, output_len: u64) -> DecompressError {
+        match self {
+            Fault::Eof => DecompressError::Eof { offset, output_len },
+            Fault::InvalidPointer => {
+                DecompressError::InvalidPointer { offset, output_len }
+            },
+            Fault::BufferTooSmall => {
+                DecompressError::BufferTooSmall { offset, output_len }
+            },
+            Fault::LimitExceeded => {
+                DecompressError::LimitExceeded { offset, output_len }
+            },
+            Fault::RatioExceeded => {
+                DecompressError::RatioExceeded { offset, output_len }
+            },
+        }
+    }
+}
+
+/// Summary of a PRS buffer produced by [`validate`](fn.validate.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PrsInfo {
+    /// Number of literal and pointer commands, not counting the end of stream
+    /// command.
+    pub commands: u64,
+    /// Length of the PRS stream in the input, including the end of stream
+    /// command. Any bytes past this were not read.
+    pub compressed_size: usize,
+    /// Length of the decompressed data.
+    pub decompressed_size: u64,
+}
+
+/// What decoders do with pointer commands copying from before the start of
+/// the output, which are invalid.
+///
+/// Some games decode such pointers instead of failing, so files that fail
+/// here load fine there. The tolerant modes reproduce what those decoders
+/// load, for research and for extracting such files as the game sees them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum InvalidPointerMode {
+    /// Fail with `DecompressError::InvalidPointer`.
+    #[default]
+    Fail,
+    /// Read the bytes before the start of the output as zeros.
+    ZeroFill,
+    /// Copy from the start of the output instead, or output zeros if there is
+    /// no output yet.
+    Clamp,
+}
+
+impl InvalidPointerMode {
+    /// Resolve a copy from `offset` bytes back with `len` bytes of history
+    /// available into a number of zeros to output first and the offset to
+    /// copy the rest from, or `None` to fail.
+    fn resolve(self, len: u64, offset: usize, size: usize) -> Option<(usize, usize)> {
+        if offset != 0 && offset as u64 <= len {
+            return Some((0, offset));
+        }
+        match self {
+            InvalidPointerMode::Fail => None,
+            InvalidPointerMode::ZeroFill => {
+                // bytes before the start, then from the start onward
+                let zeros = core::cmp::min(offset as u64 - len, size as u64) as usize;
+                Some((zeros, offset))
+            },
+            InvalidPointerMode::Clamp if len == 0 => Some((size, 0)),
+            InvalidPointerMode::Clamp => Some((0, len as usize)),
+        }
+    }
 }
 
 // LZ77 commands
 #[derive(Debug)]
-enum Cmd {
+pub(crate) enum Cmd {
     Literal(u8),
     Pointer(usize, usize),
 }
 
+/// A command in a PRS stream, as yielded by
+/// [`CommandIter`](struct.CommandIter.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Output a single byte.
+    Literal(u8),
+    /// Copy `length` bytes starting `distance` bytes back in the output.
+    Pointer { distance: usize, length: usize },
+    /// End of the stream.
+    Eof,
+}
+
+/// How a command is encoded in a PRS stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandForm {
+    /// A flag bit and the byte to output.
+    Literal,
+    /// A pointer packed into four flag bits and a distance byte.
+    ShortPointer,
+    /// A pointer in two flag bits and two bytes holding the distance and
+    /// length.
+    LongPointer,
+    /// A long pointer followed by a byte holding the length.
+    SizedPointer,
+    /// The end of stream command, a long pointer of all zero bits.
+    Eof,
+}
+
+/// Iterator over the raw commands of a PRS buffer.
+///
+/// Each item is the offset in the input where the command starts, paired
+/// with the command. Pointers are reported as encoded and not checked
+/// against the output, so this also walks streams that would fail to
+/// decompress. Iteration ends after `Command::Eof`, or after an error if the
+/// input ends mid-stream.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, Command, CommandIter, Legacy};
+///
+/// let compressed = compress::<Legacy>(b"abcabcabc");
+/// let cmds: Vec<_> = CommandIter::<Legacy>::new(&compressed)
+///     .map(|r| r.unwrap().1)
+///     .collect();
+/// assert_eq!(cmds[0], Command::Literal(b'a'));
+/// assert_eq!(cmds[3], Command::Pointer { distance: 3, length: 6 });
+/// assert_eq!(cmds[4], Command::Eof);
+/// ```
+pub struct CommandIter<'a, V: Variant> {
+    reader: CmdReader<'a, V>,
+    output_len: u64,
+    done: bool,
+}
+
+impl<'a, V: Variant> CommandIter<'a, V> {
+    /// Iterate over the commands of the PRS stream at the start of `src`.
+    pub fn new(src: &'a [u8]) -> CommandIter<'a, V> {
+        CommandIter {
+            reader: CmdReader::new(src),
+            output_len: 0,
+            done: false,
+        }
+    }
+
+    /// Offset in the input of the next command.
+    pub fn position(&self) -> usize {
+        self.reader.pos
+    }
+}
+
+impl<'a, V: Variant> Iterator for CommandIter<'a, V> {
+    type Item = Result<(usize, Command), DecompressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let start = self.reader.pos;
+        let cmd = match self.reader.next_cmd() {
+            Ok(cmd) => cmd,
+            Err(f) => {
+                self.done = true;
+                return Some(Err(f.at(start as u64, self.output_len)));
+            },
+        };
+        let cmd = match cmd {
+            None => {
+                self.done = true;
+                Command::Eof
+            },
+            Some(Cmd::Literal(b)) => {
+                self.output_len += 1;
+                Command::Literal(b)
+            },
+            Some(Cmd::Pointer(distance, length)) => {
+                self.output_len += length as u64;
+                Command::Pointer { distance, length }
+            },
+        };
+        Some(Ok((start, cmd)))
+    }
+}
+
+/// Decompress a PRS buffer into a new `Vec`.
+///
+/// The output's capacity is guessed from the input length and grown as
+/// needed. For large outputs, [`decompress_exact`](fn.decompress_exact.html)
+/// avoids the reallocation.
+pub fn decompress<V: Variant>(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let mut out = Vec::with_capacity(src.len().next_power_of_two());
+    decode::<V, _>(src, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress a PRS buffer into a new `Vec` allocated once at exactly the
+/// decompressed size.
+///
+/// The command stream is walked first to find the size, as by
+/// [`decompressed_size`](fn.decompressed_size.html), then decoded. The
+/// extra pass reads the input only, and costs less than growing and copying
+/// a large output.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress_exact, Modern};
+///
+/// let data = decompress_exact::<Modern>(&compress::<Modern>(&[7; 100_000])).unwrap();
+/// assert_eq!(data.len(), 100_000);
+/// assert_eq!(data.capacity(), 100_000);
+/// ```
+pub fn decompress_exact<V: Variant>(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let size = decompressed_size::<V>(src)?;
+    let mut out = Vec::new();
+    // a size too large to allocate is left to fail while decoding
+    let _ = out.try_reserve_exact(core::cmp::min(size, usize::MAX as u64) as usize);
+    decode::<V, _>(src, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress a PRS buffer into `dst`, replacing its contents.
+///
+/// Like [`decompress`](fn.decompress.html), but reusing `dst`'s allocation,
+/// so decompressing many buffers in turn into the same `Vec` only allocates
+/// when one decompresses larger than any before. On error, `dst` holds the
+/// data decompressed before the error was found.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress_to_buf, Modern};
+///
+/// let entries = [compress::<Modern>(b"first"), compress::<Modern>(b"second")];
+/// let mut buf = Vec::new();
+/// for entry in &entries {
+///     decompress_to_buf::<Modern>(entry, &mut buf).unwrap();
+///     assert!(buf.len() <= 6);
+/// }
+/// assert_eq!(&buf[..], b"second");
+/// ```
+pub fn decompress_to_buf<V: Variant>(
+    src: &[u8],
+    dst: &mut Vec<u8>,
+) -> Result<(), DecompressError> {
+    dst.clear();
+    decode::<V, _>(src, dst)?;
+    Ok(())
+}
+
+/// Decompress the PRS stream at the start of `src`, returning the data along
+/// with the length of the stream in `src`.
+///
+/// Decoding stops at the end of stream command and nothing past it is read,
+/// so this suits PRS data embedded in a larger buffer without a recorded
+/// compressed length. Parsing can resume at `src[consumed..]`.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress_prefix, Modern};
+///
+/// let mut archive = compress::<Modern>(b"Hello Hello Hello ");
+/// archive.extend_from_slice(b"next entry");
+///
+/// let (data, consumed) = decompress_prefix::<Modern>(&archive).unwrap();
+/// assert_eq!(&data[..], b"Hello Hello Hello ");
+/// assert_eq!(&archive[consumed..], b"next entry");
+/// ```
+pub fn decompress_prefix<V: Variant>(
+    src: &[u8],
+) -> Result<(Vec<u8>, usize), DecompressError> {
+    let mut out = Vec::with_capacity(src.len().next_power_of_two());
+    let consumed = decode::<V, _>(src, &mut out)?;
+    Ok((out, consumed))
+}
+
+/// Decompress a PRS buffer that must hold nothing but the stream, failing
+/// with `DecompressError::TrailingData` if anything other than zero bytes
+/// follows the end of stream command.
+///
+/// [`decompress`](fn.decompress.html) ignores whatever follows the stream,
+/// so a file whose end of stream command appears early, e.g. because it is
+/// corrupt or not PRS at all, can still decode. Zero bytes are allowed since
+/// archives commonly pad entries with them. To find out how much follows
+/// the stream without failing, use
+/// [`decompress_prefix`](fn.decompress_prefix.html).
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress_strict, DecompressError, Modern};
+///
+/// let mut padded = compress::<Modern>(b"Hello Hello Hello ");
+/// padded.extend_from_slice(&[0, 0, 0]);
+/// assert_eq!(&decompress_strict::<Modern>(&padded).unwrap()[..], b"Hello Hello Hello ");
+///
+/// padded.push(1);
+/// match decompress_strict::<Modern>(&padded) {
+///     Err(DecompressError::TrailingData { offset, .. }) => {
+///         assert_eq!(offset as usize, padded.len() - 1);
+///     },
+///     r => panic!("unexpected result {:?}", r),
+/// }
+/// ```
+pub fn decompress_strict<V: Variant>(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let mut out = Vec::with_capacity(src.len().next_power_of_two());
+    let consumed = decode::<V, _>(src, &mut out)?;
+    match src[consumed..].iter().position(|&b| b != 0) {
+        Some(i) => Err(DecompressError::TrailingData {
+            offset: (consumed + i) as u64,
+            output_len: out.len() as u64,
+        }),
+        None => Ok(out),
+    }
+}
+
+/// Decompress a PRS buffer, handling pointers that copy from before the
+/// start of the output as `mode` says instead of failing.
+///
+/// See [`InvalidPointerMode`](enum.InvalidPointerMode.html).
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{decompress_tolerant, InvalidPointerMode, Legacy};
+///
+/// // a literal, then a copy of 3 bytes from 2 bytes back
+/// let stream = [0b0101_0001, b'a', 0xFE, 0x00, 0x00];
+/// let data = decompress_tolerant::<Legacy>(&stream, InvalidPointerMode::ZeroFill).unwrap();
+/// assert_eq!(&data[..], b"a\0a\0");
+/// let data = decompress_tolerant::<Legacy>(&stream, InvalidPointerMode::Clamp).unwrap();
+/// assert_eq!(&data[..], b"aaaa");
+/// ```
+pub fn decompress_tolerant<V: Variant>(
+    src: &[u8],
+    mode: InvalidPointerMode,
+) -> Result<Vec<u8>, DecompressError> {
+    let mut out = TolerantOutput {
+        inner: Vec::with_capacity(src.len().next_power_of_two()),
+        mode,
+    };
+    decode::<V, _>(src, &mut out)?;
+    Ok(out.inner)
+}
+
+/// Decompress as much of a damaged PRS buffer as possible.
+///
+/// Decoding stops at the first invalid pointer or at the end of the input.
+/// Everything decoded before that point is returned along with the error
+/// describing where decoding stopped, or `None` if the stream was intact.
+pub fn decompress_lossy<V: Variant>(
+    src: &[u8],
+) -> (Vec<u8>, Option<DecompressError>) {
+    let mut out = Vec::with_capacity(src.len().next_power_of_two());
+    let err = decode::<V, _>(src, &mut out).err();
+    (out, err)
+}
+
+/// Decompress a PRS buffer into a new `Vec`, failing with
+/// `DecompressError::LimitExceeded` if the output would be larger than
+/// `limit` bytes.
+///
+/// Use this when decompressing untrusted data, since a small PRS stream can
+/// expand to a very large output.
+pub fn decompress_with_limit<V: Variant>(
+    src: &[u8],
+    limit: u64,
+) -> Result<Vec<u8>, DecompressError> {
+    let capacity = core::cmp::min(src.len().next_power_of_two() as u64, limit) as usize;
+    let mut out = LimitOutput {
+        inner: Vec::with_capacity(capacity),
+        len: 0,
+        limit,
+    };
+    decode::<V, _>(src, &mut out)?;
+    Ok(out.inner)
+}
+
+/// Decompress a PRS buffer into `dst`, returning the decompressed length.
+///
+/// Nothing is allocated. If the decompressed data does not fit, this fails
+/// with `DecompressError::BufferTooSmall`; `dst` may have been partially
+/// written in that case.
+pub fn decompress_into<V: Variant>(
+    src: &[u8],
+    dst: &mut [u8],
+) -> Result<usize, DecompressError> {
+    let mut out = SliceOutput { buf: dst, len: 0 };
+    decode::<V, _>(src, &mut out)?;
+    Ok(out.len)
+}
+
+/// Decompress a PRS buffer, handing the data to `f` in chunks instead of
+/// collecting it, and returning the decompressed length.
+///
+/// The stream is decoded through [`Decompress`](struct.Decompress.html), so
+/// memory use is bounded by its 16 KiB window whatever the size of the
+/// output, and chunks are at most 8 KiB. Chunks handed to `f` before an error
+/// is found are valid output.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress_with, Modern};
+///
+/// let compressed = compress::<Modern>(&[7; 100_000]);
+/// let mut sum = 0u64;
+/// let len = decompress_with::<Modern, _>(&compressed, |chunk| {
+///     sum += chunk.iter().map(|&b| b as u64).sum::<u64>();
+/// }).unwrap();
+/// assert_eq!(len, 100_000);
+/// assert_eq!(sum, 700_000);
+/// ```
+pub fn decompress_with<V, F>(src: &[u8], mut f: F) -> Result<u64, DecompressError>
+where
+    V: Variant,
+    F: FnMut(&[u8]),
+{
+    let mut state = Decompress::<V>::new();
+    let mut buf = [0; CHUNK_LEN];
+    loop {
+        let in_pos = state.total_in() as usize;
+        let out_pos = state.total_out();
+        let status = state.run(&src[in_pos..], &mut buf)?;
+        let len = (state.total_out() - out_pos) as usize;
+        if len > 0 {
+            f(&buf[..len]);
+        }
+        match status {
+            Status::Done => return Ok(state.total_out()),
+            Status::NeedsOutput => {},
+            Status::NeedsInput => return Err(state.eof_error()),
+        }
+    }
+}
+
+/// Compute the decompressed size of a PRS buffer without decompressing it.
+///
+/// The command stream is walked and its literal and copy lengths summed. The
+/// same errors as [`decompress`](fn.decompress.html) are reported, so this
+/// also succeeds only for streams that would decompress.
+pub fn decompressed_size<V: Variant>(src: &[u8]) -> Result<u64, DecompressError> {
+    let mut out = CountOutput { len: 0, commands: 0 };
+    decode::<V, _>(src, &mut out)?;
+    Ok(out.len)
+}
+
+/// Check that a PRS buffer is well-formed without decompressing it.
+///
+/// Every pointer command must copy from inside the output produced so far
+/// and the stream must end with an end of stream command.
+pub fn validate<V: Variant>(src: &[u8]) -> Result<PrsInfo, DecompressError> {
+    let mut out = CountOutput { len: 0, commands: 0 };
+    let compressed_size = decode::<V, _>(src, &mut out)?;
+    Ok(PrsInfo {
+        commands: out.commands,
+        compressed_size,
+        decompressed_size: out.len,
+    })
+}
+
+/// Decode `src` into `out`, returning the number of input bytes read.
+fn decode<V, O>(src: &[u8], out: &mut O) -> Result<usize, DecompressError>
+where
+    V: Variant,
+    O: Output,
+{
+    let mut reader = CmdReader::<V>::new(src);
+    let mut output_len = 0;
+    loop {
+        let start = reader.pos as u64;
+        let cmd = reader.next_cmd().map_err(|f| f.at(start, output_len))?;
+        match cmd {
+            None => break,
+            Some(Cmd::Literal(b)) => {
+                out.literal(b).map_err(|f| f.at(start, output_len))?;
+                output_len += 1;
+            },
+            Some(Cmd::Pointer(offset, size)) => {
+                out.copy(offset, size).map_err(|f| f.at(start, output_len))?;
+                output_len += size as u64;
+            },
+        }
+    }
+    Ok(reader.pos)
+}
+
+#[cfg(feature = "std")]
 impl<R: Read, V: Variant> PrsDecoder<R, V> {
+    /// Wraps a Read source, initializing the decoder state.
     pub fn new(inner: R) -> PrsDecoder<R, V> {
         PrsDecoder {
             inner,
-            cmds: 0,
-            rem: 0,
-            copy_buf: VecDeque::with_capacity(8191),
-            eof: false,
-            pd: std::marker::PhantomData,
+            state: Decompress::new(),
+            out_buf: Box::default(),
+            capacity: OUT_BUF_CAPACITY,
+            out_pos: 0,
+            out_len: 0,
+            progress: Progress::new("decode"),
+            size_hint: None,
+            start: None,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Wraps a Read source, buffering `capacity` bytes of decoded data for
+    /// `BufRead` rather than 8 KiB.
+    ///
+    /// `read_to_end` also grows its output by this much at a time. Larger
+    /// buffers mean fewer calls into the decoder for callers using
+    /// `fill_buf`. A capacity of 0 is taken as 1.
+    pub fn with_capacity(inner: R, capacity: usize) -> PrsDecoder<R, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.capacity = core::cmp::max(capacity, 1);
+        decoder
+    }
+
+    /// Wraps a Read source of a stream expected to decompress to `size`
+    /// bytes.
+    ///
+    /// `read_to_end` reserves room for that much data up front instead of
+    /// growing the `Vec` as it goes. The hint only affects allocation; a
+    /// stream of a different size still decodes correctly, and a hint too
+    /// large to allocate is ignored.
+    pub fn with_size_hint(inner: R, size: u64) -> PrsDecoder<R, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.size_hint = Some(size);
+        decoder
+    }
+
+    /// Wraps a Read source, failing once more than `limit` bytes would be
+    /// decompressed from it.
+    ///
+    /// Reads fail with an `InvalidData` error wrapping
+    /// `DecompressError::LimitExceeded` when the limit is hit. Use this when
+    /// decoding untrusted data, since a small PRS stream can expand to a very
+    /// large output.
+    pub fn with_limit(inner: R, limit: u64) -> PrsDecoder<R, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.state.set_limit(Some(limit));
+        decoder
+    }
+
+    /// Wraps a Read source of a stream compressed against the preset
+    /// dictionary `dict`.
+    ///
+    /// See [`Decompress::set_dictionary`](struct.Decompress.html#method.set_dictionary).
+    pub fn with_dictionary(inner: R, dict: &[u8]) -> PrsDecoder<R, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.state.set_dictionary(dict);
+        decoder
+    }
+
+    /// Call `f` with the number of compressed bytes read and decompressed
+    /// bytes produced so far, every time data is decompressed.
+    ///
+    /// Both counts start over when the decoder is [`reset`](#method.reset).
+    pub fn on_progress<F>(&mut self, f: F)
+    where
+        F: FnMut(u64, u64) + Send + Sync + 'static,
+    {
+        self.progress.set(f);
+    }
+
+    /// Reference the inner Read.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Mutably reference the inner Read.
+    ///
+    /// Reading from it directly will corrupt the PRS stream being decoded.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Number of compressed bytes read from the inner Read so far.
+    pub fn total_in(&self) -> u64 {
+        self.state.total_in()
+    }
+
+    /// Number of decompressed bytes returned so far.
+    ///
+    /// Data decoded into the `BufRead` buffer counts once it is consumed.
+    pub fn total_out(&self) -> u64 {
+        self.state.total_out() - (self.out_len - self.out_pos) as u64
+    }
+
+    /// Unwrap the inner Read.
+    ///
+    /// The decoder only reads as much of the inner Read as it needs, so once
+    /// the end of the PRS stream has been reached, the inner Read is
+    /// positioned right after it.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Fail once more than `ratio` times as many bytes would be decompressed
+    /// as were read, or lift the limit with `None`.
+    ///
+    /// Reads fail with an `InvalidData` error wrapping
+    /// `DecompressError::RatioExceeded` when the ratio is exceeded. See
+    /// [`Decompress::set_max_ratio`](struct.Decompress.html#method.set_max_ratio).
+    pub fn set_max_ratio(&mut self, ratio: Option<u32>) {
+        self.state.set_max_ratio(ratio);
+    }
+
+    /// Set what to do with pointers copying from before the start of the
+    /// output. See [`InvalidPointerMode`](enum.InvalidPointerMode.html).
+    pub fn set_invalid_pointer_mode(&mut self, mode: InvalidPointerMode) {
+        self.state.set_invalid_pointer_mode(mode);
+    }
+
+    /// Fill `buf` with decompressed data, returning how much was written.
+    ///
+    /// Unlike `read`, this only returns less than `buf.len()` at the end of
+    /// the stream. Errors are returned as from `read`; data decompressed
+    /// before an error is lost.
+    pub fn next_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = 0;
+        while len < buf.len() {
+            match self.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(len)
+    }
+
+    /// Iterate over the decompressed data in pieces of `chunk_size` bytes.
+    ///
+    /// Every chunk is full except possibly the last. Iteration ends after
+    /// the end of the stream or the first error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ages_prs::{compress, Legacy, LegacyPrsDecoder};
+    ///
+    /// let compressed = compress::<Legacy>(b"Hello Hello Hello ");
+    /// let mut decoder = LegacyPrsDecoder::new(&compressed[..]);
+    /// let chunks: Vec<Vec<u8>> = decoder.chunks(8).collect::<Result<_, _>>().unwrap();
+    /// assert_eq!(chunks, [&b"Hello He"[..], b"llo Hell", b"o "]);
+    /// ```
+    pub fn chunks(&mut self, chunk_size: usize) -> Chunks<'_, R, V> {
+        assert!(chunk_size > 0, "chunk size must be nonzero");
+        Chunks {
+            decoder: self,
+            chunk_size,
+            done: false,
+        }
+    }
+
+    /// Capture the decoding progress, to resume from later with
+    /// [`restore`](#method.restore).
+    ///
+    /// Data decoded into the `BufRead` buffer but not consumed yet is part of
+    /// the state. See [`DecoderState`](struct.DecoderState.html).
+    pub fn checkpoint(&self) -> DecoderState {
+        self.state.checkpoint_buffered(self.out_len - self.out_pos)
+    }
+
+    /// Resume decoding from a state captured by
+    /// [`checkpoint`](#method.checkpoint).
+    ///
+    /// The inner Read must be positioned `state.total_in()` bytes past the
+    /// start of the stream. Data buffered from before is discarded.
+    pub fn restore(&mut self, state: &DecoderState) {
+        self.state.restore(state);
+        self.out_pos = 0;
+        self.out_len = 0;
+        self.start = None;
+        self.checkpoints.clear();
+    }
+
+    /// Start decoding a new PRS stream from `inner`, returning the previous
+    /// Read.
+    ///
+    /// Any undecoded data from the previous stream is discarded. The
+    /// decoder's buffers and output limits are kept, so decoding many streams
+    /// with one decoder avoids reallocating them for each stream.
+    pub fn reset(&mut self, inner: R) -> R {
+        self.state.reset();
+        self.progress.start();
+        self.out_pos = 0;
+        self.out_len = 0;
+        self.start = None;
+        self.checkpoints.clear();
+        core::mem::replace(&mut self.inner, inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Clone, V: Variant> Clone for PrsDecoder<R, V> {
+    fn clone(&self) -> PrsDecoder<R, V> {
+        PrsDecoder {
+            inner: self.inner.clone(),
+            state: self.state.clone(),
+            out_buf: self.out_buf.clone(),
+            capacity: self.capacity,
+            out_pos: self.out_pos,
+            out_len: self.out_len,
+            progress: Progress::new("decode"),
+            size_hint: self.size_hint,
+            start: self.start,
+            checkpoints: self.checkpoints.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read, V: Variant> Read for PrsDecoder<R, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // data already handed out through fill_buf comes first
+        if self.out_pos < self.out_len {
+            let len = core::cmp::min(buf.len(), self.out_len - self.out_pos);
+            buf[..len].copy_from_slice(&self.out_buf[self.out_pos..self.out_pos + len]);
+            self.out_pos += len;
+            return Ok(len);
+        }
+
+        self.read_decoded(buf)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let start = buf.len();
+        buf.extend_from_slice(&self.out_buf[self.out_pos..self.out_len]);
+        self.out_pos = self.out_len;
+
+        if let Some(hint) = self.size_hint {
+            let expected = hint.saturating_sub(self.state.total_out());
+            // a hint too large to allocate is ignored
+            let expected = core::cmp::min(expected, usize::MAX as u64) as usize;
+            let _ = buf.try_reserve(expected);
+        }
+
+        loop {
+            if buf.len() == buf.capacity() {
+                // check for the end of the stream before growing the Vec, so
+                // an exact size hint leaves it exactly full
+                let mut probe = [0; 32];
+                let n = match self.read_decoded(&mut probe) {
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                if n == 0 {
+                    return Ok(buf.len() - start);
+                }
+                buf.extend_from_slice(&probe[..n]);
+                buf.reserve(self.capacity);
+            }
+
+            // decode straight into the spare capacity
+            let len = buf.len();
+            buf.resize(buf.capacity(), 0);
+            match self.read_decoded(&mut buf[len..]) {
+                Ok(0) => {
+                    buf.truncate(len);
+                    return Ok(len - start);
+                },
+                Ok(n) => buf.truncate(len + n),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                    buf.truncate(len);
+                },
+                Err(e) => {
+                    buf.truncate(len);
+                    return Err(e);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read, V: Variant> BufRead for PrsDecoder<R, V> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.out_pos >= self.out_len {
+            let mut out_buf = core::mem::take(&mut self.out_buf);
+            if out_buf.is_empty() {
+                out_buf = vec![0; self.capacity].into_boxed_slice();
+            }
+            let r = self.read_decoded(&mut out_buf);
+            self.out_buf = out_buf;
+            self.out_len = r?;
+            self.out_pos = 0;
+        }
+        Ok(&self.out_buf[self.out_pos..self.out_len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.out_pos = core::cmp::min(self.out_pos + amt, self.out_len);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek, V: Variant> Seek for PrsDecoder<R, V> {
+    /// Seek in the decompressed data. Seeking past the end stops at the end,
+    /// and the position reached is returned.
+    ///
+    /// The stream is taken to start where the inner Read was positioned
+    /// when the decoder was created.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                let start = self.inner.stream_position()?
+                    .checked_sub(self.state.total_in())
+                    .ok_or_else(|| io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "inner reader moved before the start of the PRS stream",
+                    ))?;
+                self.start = Some(start);
+                start
+            },
+        };
+
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(offset) => {
+                self.skip_to(u64::MAX)?;
+                (self.position(), offset)
+            },
+            SeekFrom::Current(offset) => (self.position(), offset),
+        };
+        let target = base.checked_add_signed(offset).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        ))?;
+
+        if target < self.position() {
+            self.rewind(start, target)?;
+        }
+        self.skip_to(target)?;
+        Ok(self.position())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek, V: Variant> PrsDecoder<R, V> {
+    /// Position in the decompressed data.
+    fn position(&self) -> u64 {
+        self.state.total_out() - (self.out_len - self.out_pos) as u64
+    }
+
+    /// Resume decoding from the last checkpoint at or before `target`, or
+    /// from the start of the stream at `start` in the inner Read.
+    fn rewind(&mut self, start: u64, target: u64) -> io::Result<()> {
+        let i = self.checkpoints.partition_point(|c| c.total_out() <= target);
+        let checkpoint = self.checkpoints[..i].last();
+        let total_in = checkpoint.map_or(0, |c| c.total_in());
+        self.inner.seek(SeekFrom::Start(start + total_in))?;
+
+        // the limits may have changed since the checkpoint was taken
+        let (limit, max_ratio) = (self.state.limit, self.state.max_ratio);
+        match self.checkpoints[..i].last() {
+            Some(checkpoint) => self.state = checkpoint.clone(),
+            None => self.state.reset(),
+        }
+        self.state.set_limit(limit);
+        self.state.set_max_ratio(max_ratio);
+        self.out_pos = 0;
+        self.out_len = 0;
+        Ok(())
+    }
+
+    /// Decode and discard data up to position `target` or the end of the
+    /// stream, taking checkpoints along the way.
+    fn skip_to(&mut self, target: u64) -> io::Result<()> {
+        let buffered = (self.out_len - self.out_pos) as u64;
+        let skip = target - self.position();
+        if skip <= buffered {
+            self.out_pos += skip as usize;
+            return Ok(());
+        }
+        self.out_pos = self.out_len;
+
+        let mut scratch = [0; OUT_BUF_CAPACITY];
+        while self.state.total_out() < target {
+            let last = self.checkpoints.last().map_or(0, |c| c.total_out());
+            if self.state.total_out() >= last + CHECKPOINT_INTERVAL {
+                self.checkpoints.push(self.state.clone());
+            }
+
+            let len = core::cmp::min(target - self.state.total_out(), scratch.len() as u64);
+            match self.read_decoded(&mut scratch[..len as usize]) {
+                Ok(0) => break,
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the decompressed data of a
+/// [`PrsDecoder`](struct.PrsDecoder.html) in fixed-size pieces, returned by
+/// [`PrsDecoder::chunks`](struct.PrsDecoder.html#method.chunks).
+#[cfg(feature = "std")]
+pub struct Chunks<'a, R: Read, V: Variant> {
+    decoder: &'a mut PrsDecoder<R, V>,
+    chunk_size: usize,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<'a, R: Read, V: Variant> Iterator for Chunks<'a, R, V> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        if self.done {
+            return None;
+        }
+
+        let mut chunk = vec![0; self.chunk_size];
+        match self.decoder.next_chunk(&mut chunk) {
+            Ok(0) => {
+                self.done = true;
+                None
+            },
+            Ok(len) => {
+                self.done = len < self.chunk_size;
+                chunk.truncate(len);
+                Some(Ok(chunk))
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read, V: Variant> PrsDecoder<R, V> {
+    /// Decode straight into `buf`, bypassing the `BufRead` buffer.
+    fn read_decoded(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut byte = [0; 1];
+        let mut input: &[u8] = &[];
+        let mut produced = 0;
+        loop {
+            let total_out = self.state.total_out();
+            let status = match self.state.run(input, &mut buf[produced..]) {
+                Ok(status) => status,
+                // as in `Decompress::run`, keep the output of earlier
+                // iterations and report the error on the next read
+                Err(e) if produced > 0 => {
+                    self.state.error = Some(e);
+                    self.report_progress();
+                    return Ok(produced);
+                },
+                Err(e) => return Err(e.into()),
+            };
+            produced += (self.state.total_out() - total_out) as usize;
+            input = &[];
+
+            if status != Status::NeedsInput {
+                if status == Status::Done {
+                    self.progress.finish(self.state.total_in(), self.state.total_out());
+                }
+                self.report_progress();
+                return Ok(produced);
+            }
+            match self.inner.read(&mut byte) {
+                // errors are reported by the next read if data was produced
+                Ok(0) if produced > 0 => {
+                    self.report_progress();
+                    return Ok(produced);
+                },
+                Ok(0) => return Err(self.state.eof_error().into()),
+                Ok(_) => input = &byte,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(_) if produced > 0 => {
+                    self.report_progress();
+                    return Ok(produced);
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn report_progress(&mut self) {
+        self.progress.report(self.state.total_in(), self.state.total_out());
+    }
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (msg, offset, output_len) = match *self {
+            DecompressError::Eof { offset, output_len } => {
+                ("unexpected end of PRS stream", offset, output_len)
+            },
+            DecompressError::InvalidPointer { offset, output_len } => {
+                ("bad pointer copy in stream", offset, output_len)
+            },
+            DecompressError::BufferTooSmall { offset, output_len } => {
+                ("destination buffer too small", offset, output_len)
+            },
+            DecompressError::LimitExceeded { offset, output_len } => {
+                ("decompressed size limit exceeded", offset, output_len)
+            },
+            DecompressError::RatioExceeded { offset, output_len } => {
+                ("expansion ratio limit exceeded", offset, output_len)
+            },
+            DecompressError::TrailingData { offset, output_len } => {
+                ("data after the end of PRS stream", offset, output_len)
+            },
+            #[cfg(feature = "std")]
+            DecompressError::Io(ref e) => {
+                return write!(fmt, "failed to read PRS stream: {}", e);
+            },
+        };
+        write!(
+            fmt,
+            "{} at input offset {} (output length {})",
+            msg,
+            offset,
+            output_len,
+        )
+    }
+}
+
+impl DecompressError {
+    /// Copy a format error; IO errors can't be copied.
+    fn clone_format(&self) -> Option<DecompressError> {
+        match *self {
+            DecompressError::Eof { offset, output_len } => {
+                Some(DecompressError::Eof { offset, output_len })
+            },
+            DecompressError::InvalidPointer { offset, output_len } => {
+                Some(DecompressError::InvalidPointer { offset, output_len })
+            },
+            DecompressError::BufferTooSmall { offset, output_len } => {
+                Some(DecompressError::BufferTooSmall { offset, output_len })
+            },
+            DecompressError::LimitExceeded { offset, output_len } => {
+                Some(DecompressError::LimitExceeded { offset, output_len })
+            },
+            DecompressError::RatioExceeded { offset, output_len } => {
+                Some(DecompressError::RatioExceeded { offset, output_len })
+            },
+            DecompressError::TrailingData { offset, output_len } => {
+                Some(DecompressError::TrailingData { offset, output_len })
+            },
+            #[cfg(feature = "std")]
+            DecompressError::Io(_) => None,
+        }
+    }
+}
+
+impl error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            DecompressError::Io(e) => Some(e),
+            _ => None,
         }
     }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for DecompressError {
+    fn from(e: io::Error) -> DecompressError {
+        if e.get_ref().is_some_and(|inner| inner.is::<DecompressError>()) {
+            // checked above, so neither of these can fail
+            return *e.into_inner().unwrap().downcast().unwrap();
+        }
+        DecompressError::Io(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DecompressError> for io::Error {
+    fn from(e: DecompressError) -> io::Error {
+        let kind = match e {
+            DecompressError::Eof { .. } => io::ErrorKind::UnexpectedEof,
+            DecompressError::InvalidPointer { .. } => io::ErrorKind::InvalidData,
+            DecompressError::BufferTooSmall { .. } => io::ErrorKind::WriteZero,
+            DecompressError::LimitExceeded { .. } => io::ErrorKind::InvalidData,
+            DecompressError::RatioExceeded { .. } => io::ErrorKind::InvalidData,
+            DecompressError::TrailingData { .. } => io::ErrorKind::InvalidData,
+            DecompressError::Io(e) => return e,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+// ---- Buffer decoding ----
+
+/// Command reader over an in-memory PRS buffer.
+pub(crate) struct CmdReader<'a, V: Variant> {
+    src: &'a [u8],
+    pub(crate) pos: usize,
+    pub(crate) cmds: u8,
+    pub(crate) rem: u8,
+    pub(crate) form: CommandForm,
+    /// offset of the byte the flag bits are being read from
+    pub(crate) cmds_pos: usize,
+    pd: core::marker::PhantomData<V>,
+}
+
+impl<'a, V: Variant> CmdReader<'a, V> {
+    fn new(src: &'a [u8]) -> CmdReader<'a, V> {
+        CmdReader::resume(src, 0, 0)
+    }
+
+    /// Continue reading a stream whose command bits were left at `cmds`,
+    /// with `rem` bits remaining.
+    pub(crate) fn resume(src: &'a [u8], cmds: u8, rem: u8) -> CmdReader<'a, V> {
+        CmdReader {
+            src,
+            pos: 0,
+            cmds,
+            rem,
+            form: CommandForm::Eof,
+            cmds_pos: 0,
+            pd: core::marker::PhantomData,
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Fault> {
+        let b = *self.src.get(self.pos).ok_or(Fault::Eof)?;
+        self.pos += 1;
+        Ok(b)
+    }
 
-    fn read_bit(&mut self) -> io::Result<bool> {
+    fn read_bit(&mut self) -> Result<bool, Fault> {
         if self.rem == 0 {
-            let mut buf = [0; 1];
-            self.inner.read_exact(&mut buf)?;
-            self.cmds = buf[0];
+            self.cmds_pos = self.pos;
+            self.cmds = self.read_byte()?;
             self.rem = 8;
         }
 
@@ -46,88 +1257,804 @@ impl<R: Read, V: Variant> PrsDecoder<R, V> {
         self.cmds >>= 1;
         self.rem -= 1;
 
-        match ret { 0 => Ok(false), _ => Ok(true) }
+        Ok(ret != 0)
     }
 
-    fn next_cmd(&mut self) -> io::Result<Option<Cmd>> {
+    pub(crate) fn next_cmd(&mut self) -> Result<Option<Cmd>, Fault> {
         if self.read_bit()? {
-            // literal
-            let mut buf = [0; 1];
-            self.inner.read_exact(&mut buf)?;
-            return Ok(Some(Cmd::Literal(buf[0])));
+            self.form = CommandForm::Literal;
+            return Ok(Some(Cmd::Literal(self.read_byte()?)));
         }
 
-        if self.read_bit()? {
-            // long ptr
-            let mut buf = [0; 2];
-            self.inner.read_exact(&mut buf)?;
-            let mut offset = i16::from_le_bytes(buf) as i32;
+        let (form, distance, length) = V::read_pointer(self).ok_or(Fault::Eof)?;
+        self.form = form;
+        match form {
+            CommandForm::Eof => Ok(None),
+            _ => Ok(Some(Cmd::Pointer(distance, length))),
+        }
+    }
+}
 
-            if offset == 0 {
-                return Ok(None);
-            }
+impl<'a, V: Variant> Bits for CmdReader<'a, V> {
+    fn bit(&mut self) -> Option<bool> {
+        self.read_bit().ok()
+    }
 
-            let mut size = (offset & 0b111) as usize;
-            offset >>= 3;
+    fn byte(&mut self) -> Option<u8> {
+        self.read_byte().ok()
+    }
+}
+
+/// Destination for decoded bytes.
+trait Output {
+    fn literal(&mut self, b: u8) -> Result<(), Fault>;
+    fn copy(&mut self, offset: usize, size: usize) -> Result<(), Fault>;
+}
+
+impl Output for Vec<u8> {
+    fn literal(&mut self, b: u8) -> Result<(), Fault> {
+        self.push(b);
+        Ok(())
+    }
 
-            if size == 0 {
-                // next byte is real size
-                self.inner.read_exact(&mut buf[..1])?;
-                size = buf[0] as usize;
-                // it's probably the minimum long-long-copy size
-                size += V::MIN_LONG_COPY_LENGTH as usize;
-            } else {
-                size += 2;
+    fn copy(&mut self, offset: usize, size: usize) -> Result<(), Fault> {
+        if offset == 0 || self.len() < offset {
+            return Err(Fault::InvalidPointer);
+        }
+        let start = self.len() - offset;
+        if offset == 1 {
+            // a run of one byte
+            let b = self[start];
+            self.resize(self.len() + size, b);
+        } else if size <= offset {
+            // source and destination don't overlap
+            self.extend_from_within(start..start + size);
+        } else {
+            // the copy repeats the last `offset` bytes. copying whole
+            // repetitions from the start keeps the pattern aligned, and
+            // doubles the data available to copy each time
+            self.reserve(size);
+            let mut copied = 0;
+            while copied < size {
+                let len = core::cmp::min(size - copied, offset + copied);
+                self.extend_from_within(start..start + len);
+                copied += len;
             }
-            offset |= -8192i32;
+        }
+        Ok(())
+    }
+}
+
+/// Output handling invalid pointers according to an `InvalidPointerMode`.
+struct TolerantOutput {
+    inner: Vec<u8>,
+    mode: InvalidPointerMode,
+}
+
+impl Output for TolerantOutput {
+    fn literal(&mut self, b: u8) -> Result<(), Fault> {
+        self.inner.literal(b)
+    }
+
+    fn copy(&mut self, offset: usize, size: usize) -> Result<(), Fault> {
+        let len = self.inner.len();
+        let (zeros, offset) = self.mode.resolve(len as u64, offset, size)
+            .ok_or(Fault::InvalidPointer)?;
+        self.inner.resize(len + zeros, 0);
+        if size > zeros {
+            self.inner.copy(offset, size - zeros)?;
+        }
+        Ok(())
+    }
+}
+
+struct SliceOutput<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
 
-            Ok(Some(Cmd::Pointer((-offset) as usize, size)))
+impl<'a> Output for SliceOutput<'a> {
+    fn literal(&mut self, b: u8) -> Result<(), Fault> {
+        let dst = self.buf.get_mut(self.len)
+            .ok_or(Fault::BufferTooSmall)?;
+        *dst = b;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn copy(&mut self, offset: usize, size: usize) -> Result<(), Fault> {
+        if offset == 0 || self.len < offset {
+            return Err(Fault::InvalidPointer);
+        }
+        if self.buf.len() - self.len < size {
+            return Err(Fault::BufferTooSmall);
+        }
+        let start = self.len - offset;
+        if offset == 1 {
+            let b = self.buf[start];
+            self.buf[self.len..self.len + size].fill(b);
+        } else if size <= offset {
+            self.buf.copy_within(start..start + size, self.len);
         } else {
-            // short ptr
-            let mut buf = [0; 1];
-            let flag = if self.read_bit()? { 1 } else { 0 };
-            let bit = if self.read_bit()? { 1 } else { 0 };
-            let size = (bit | (flag << 1)) + 2;
-            self.inner.read_exact(&mut buf)?;
-            let offset = buf[0] as i32;
-            let offset = offset | -256i32;
-            
-            Ok(Some(Cmd::Pointer((-offset) as usize, size)))
+            // see the Vec impl
+            let mut copied = 0;
+            while copied < size {
+                let len = core::cmp::min(size - copied, offset + copied);
+                self.buf.copy_within(start..start + len, self.len + copied);
+                copied += len;
+            }
         }
+        self.len += size;
+        Ok(())
     }
 }
 
-impl<R: Read, V: Variant> Read for PrsDecoder<R, V> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        // first, fill the copy buffer as much as possible
-        while self.copy_buf.len() < 8191 + buf.len() && !self.eof {
-            match self.next_cmd()? {
-                None => {
-                    self.eof = true;
-                    break;
-                },
-                Some(Cmd::Literal(b)) => {
-                    self.copy_buf.push_back(b);
-                },
-                Some(Cmd::Pointer(offset, size)) => {
-                    for _ in 0..size {
-                        if offset == 0 || self.copy_buf.len() < offset {
-                            return Err(io::Error::new(
-                                io::ErrorKind::InvalidData,
-                                "bad pointer copy in stream"
-                            ));
-                        }
-                        self.copy_buf.push_back(self.copy_buf[self.copy_buf.len() - offset]);
-                    }
-                },
+/// Output which only tracks how much would have been written.
+struct CountOutput {
+    len: u64,
+    commands: u64,
+}
+
+impl Output for CountOutput {
+    fn literal(&mut self, _b: u8) -> Result<(), Fault> {
+        self.len += 1;
+        self.commands += 1;
+        Ok(())
+    }
+
+    fn copy(&mut self, offset: usize, size: usize) -> Result<(), Fault> {
+        if offset == 0 || self.len < offset as u64 {
+            return Err(Fault::InvalidPointer);
+        }
+        self.len += size as u64;
+        self.commands += 1;
+        Ok(())
+    }
+}
+
+/// Output wrapper enforcing a maximum output length.
+struct LimitOutput<O: Output> {
+    inner: O,
+    len: u64,
+    limit: u64,
+}
+
+impl<O: Output> LimitOutput<O> {
+    fn reserve(&mut self, size: usize) -> Result<(), Fault> {
+        if self.limit - self.len < size as u64 {
+            return Err(Fault::LimitExceeded);
+        }
+        self.len += size as u64;
+        Ok(())
+    }
+}
+
+impl<O: Output> Output for LimitOutput<O> {
+    fn literal(&mut self, b: u8) -> Result<(), Fault> {
+        self.reserve(1)?;
+        self.inner.literal(b)
+    }
+
+    fn copy(&mut self, offset: usize, size: usize) -> Result<(), Fault> {
+        self.reserve(size)?;
+        self.inner.copy(offset, size)
+    }
+}
+
+// ---- Resumable decoding ----
+
+/// Longest backward distance a pointer command can encode.
+const MAX_DISTANCE: usize = 8192;
+/// Capacity of a `Window`. A power of two, with room for the full history
+/// plus the output not yet returned to the caller.
+const WINDOW_CAP: usize = MAX_DISTANCE * 2;
+const WINDOW_MASK: usize = WINDOW_CAP - 1;
+
+/// Output allowed beyond the maximum expansion ratio.
+const RATIO_SLACK: u64 = 4096;
+
+/// Ring buffer holding the decoded history along with decoded output that has
+/// not been returned yet. Memory use is constant regardless of stream size.
+#[derive(Clone)]
+struct Window {
+    buf: Box<[u8]>,
+    /// total bytes decoded into the window, including the dictionary
+    head: u64,
+    /// length of the dictionary the window was primed with
+    base: u64,
+    /// bytes at the end of the window not yet drained
+    pending: usize,
+}
+
+impl Window {
+    fn new() -> Window {
+        Window {
+            buf: vec![0; WINDOW_CAP].into_boxed_slice(),
+            head: 0,
+            base: 0,
+            pending: 0,
+        }
+    }
+
+    /// Empty the window, then fill it with `dict` as history that is not
+    /// part of the output.
+    fn clear(&mut self, dict: &[u8]) {
+        for (i, &b) in dict.iter().enumerate() {
+            self.buf[i & WINDOW_MASK] = b;
+        }
+        self.head = dict.len() as u64;
+        self.base = self.head;
+        self.pending = 0;
+    }
+
+    /// Number of bytes decoded, not counting the dictionary.
+    fn output_len(&self) -> u64 {
+        self.head - self.base
+    }
+
+    fn push(&mut self, b: u8) {
+        self.buf[self.head as usize & WINDOW_MASK] = b;
+        self.head += 1;
+        self.pending += 1;
+    }
+
+    fn copy(
+        &mut self,
+        offset: usize,
+        size: usize,
+        mode: InvalidPointerMode,
+    ) -> Result<(), Fault> {
+        let (zeros, offset) = mode.resolve(self.head, offset, size)
+            .ok_or(Fault::InvalidPointer)?;
+        for _ in 0..zeros {
+            self.push(0);
+        }
+        for _ in zeros..size {
+            let src = (self.head as usize).wrapping_sub(offset) & WINDOW_MASK;
+            self.push(self.buf[src]);
+        }
+        Ok(())
+    }
+
+    /// The last `len` bytes decoded into the window.
+    fn tail(&self, len: usize) -> Vec<u8> {
+        let start = (self.head as usize).wrapping_sub(len) & WINDOW_MASK;
+        let first = core::cmp::min(len, WINDOW_CAP - start);
+        let mut tail = Vec::with_capacity(len);
+        tail.extend_from_slice(&self.buf[start..start + first]);
+        tail.extend_from_slice(&self.buf[..len - first]);
+        tail
+    }
+
+    /// Put back a window whose last bytes were `tail`, the last `pending`
+    /// of them not drained yet.
+    fn restore(&mut self, head: u64, base: u64, pending: usize, tail: &[u8]) {
+        self.head = head;
+        self.base = base;
+        self.pending = pending;
+        let start = head - tail.len() as u64;
+        for (i, &b) in tail.iter().enumerate() {
+            self.buf[(start as usize).wrapping_add(i) & WINDOW_MASK] = b;
+        }
+    }
+
+    /// Move as many pending bytes as fit into `out`, returning the count.
+    fn drain(&mut self, out: &mut [u8]) -> usize {
+        let len = core::cmp::min(self.pending, out.len());
+        let start = (self.head as usize).wrapping_sub(self.pending) & WINDOW_MASK;
+        // the pending region may wrap around the end of the ring
+        let first = core::cmp::min(len, WINDOW_CAP - start);
+        out[..first].copy_from_slice(&self.buf[start..start + first]);
+        out[first..len].copy_from_slice(&self.buf[..len - first]);
+        self.pending -= len;
+        len
+    }
+}
+
+/// Progress reported by [`Decompress::run`](struct.Decompress.html#method.run)
+/// and [`Compress::run`](struct.Compress.html#method.run).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// All input was consumed; more is needed to continue.
+    NeedsInput,
+    /// The output buffer is full; more output is waiting.
+    NeedsOutput,
+    /// The end of the stream was reached and all output returned.
+    Done,
+}
+
+/// Outcome of trying to parse one command.
+enum Parse {
+    Cmd(Option<Cmd>),
+    /// The input ended partway through the command; the bytes seen so far
+    /// were staged.
+    Incomplete,
+}
+
+/// Resumable PRS decoder operating on caller-provided input and output
+/// buffers.
+///
+/// This is the state machine behind the decoders in this crate, for callers
+/// that can't use the standard IO traits. Input may be split anywhere,
+/// including inside a command; partial commands are staged internally. Only
+/// the bytes of the PRS stream are consumed, so anything following the end of
+/// stream command is left to the caller. Progress is reported through
+/// [`total_in`](#method.total_in) and [`total_out`](#method.total_out).
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, Decompress, Modern, Status};
+///
+/// let compressed = compress::<Modern>(b"Hello Hello Hello ");
+/// let mut state = Decompress::<Modern>::new();
+/// let mut out = [0; 64];
+/// let status = state.run(&compressed, &mut out).unwrap();
+/// assert_eq!(status, Status::Done);
+/// assert_eq!(&out[..state.total_out() as usize], b"Hello Hello Hello ");
+/// ```
+pub struct Decompress<V: Variant> {
+    cmds: u8,
+    rem: u8,
+    /// bytes of a command split across input buffers
+    partial: [u8; 4],
+    partial_len: usize,
+    window: Window,
+    /// history the window is primed with at the start of every stream
+    dictionary: Vec<u8>,
+    eof: bool,
+    total_in: u64,
+    total_out: u64,
+    /// input offset of the command being decoded
+    cmd_start: u64,
+    limit: Option<u64>,
+    max_ratio: Option<u32>,
+    invalid_pointers: InvalidPointerMode,
+    /// error hit after output was already produced, reported on the next call
+    error: Option<DecompressError>,
+    pd: core::marker::PhantomData<V>,
+}
+
+impl<V: Variant> Decompress<V> {
+    /// Create a decoder for a new PRS stream.
+    pub fn new() -> Decompress<V> {
+        Decompress {
+            cmds: 0,
+            rem: 0,
+            partial: [0; 4],
+            partial_len: 0,
+            window: Window::new(),
+            dictionary: Vec::new(),
+            eof: false,
+            total_in: 0,
+            total_out: 0,
+            cmd_start: 0,
+            limit: None,
+            max_ratio: None,
+            invalid_pointers: InvalidPointerMode::Fail,
+            error: None,
+            pd: core::marker::PhantomData,
+        }
+    }
+
+    /// Fail with `DecompressError::LimitExceeded` once more than `limit`
+    /// bytes would be decompressed, or lift the limit with `None`.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    /// Fail with `DecompressError::RatioExceeded` once the decompressed data
+    /// would be more than `ratio` times as large as the compressed data read,
+    /// or lift the limit with `None`.
+    ///
+    /// This bounds the work done per byte of untrusted input, where the
+    /// output limit only bounds the total. Up to 4 KiB of output beyond the
+    /// ratio is allowed, since the ratio of a short stream swings far more
+    /// than that of a whole file.
+    pub fn set_max_ratio(&mut self, ratio: Option<u32>) {
+        self.max_ratio = ratio;
+    }
+
+    /// Set what to do with pointers copying from before the start of the
+    /// output. See [`InvalidPointerMode`](enum.InvalidPointerMode.html).
+    pub fn set_invalid_pointer_mode(&mut self, mode: InvalidPointerMode) {
+        self.invalid_pointers = mode;
+    }
+
+    /// Decode streams compressed against the preset dictionary `dict`,
+    /// starting a new stream.
+    ///
+    /// Pointers may copy from the dictionary as if it preceded the output. It
+    /// must match the dictionary given to the encoder; only its last 8 KiB
+    /// can be referenced, so only those are kept. An empty dictionary turns
+    /// this off.
+    pub fn set_dictionary(&mut self, dict: &[u8]) {
+        let start = dict.len().saturating_sub(MAX_DISTANCE);
+        self.dictionary.clear();
+        self.dictionary.extend_from_slice(&dict[start..]);
+        self.reset();
+    }
+
+    /// Prepare to decode a new stream, keeping the window allocation, the
+    /// dictionary and the output limits.
+    pub fn reset(&mut self) {
+        self.cmds = 0;
+        self.rem = 0;
+        self.partial_len = 0;
+        self.window.clear(&self.dictionary);
+        self.eof = false;
+        self.total_in = 0;
+        self.total_out = 0;
+        self.cmd_start = 0;
+        self.error = None;
+    }
+
+    /// Number of compressed bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Number of decompressed bytes written out so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// The error to report when the input ends before the stream does, that
+    /// is, when `run` asks for more input and there is none left.
+    pub fn eof_error(&self) -> DecompressError {
+        Fault::Eof.at(self.cmd_start, self.window.output_len())
+    }
+
+    /// Capture the decoding progress, to resume from later with
+    /// [`restore`](#method.restore).
+    ///
+    /// See [`DecoderState`](struct.DecoderState.html).
+    pub fn checkpoint(&self) -> DecoderState {
+        self.checkpoint_buffered(0)
+    }
+
+    /// Capture the decoding progress as if the last `buffered` bytes written
+    /// out were still pending, for decoders buffering output themselves.
+    fn checkpoint_buffered(&self, buffered: usize) -> DecoderState {
+        let pending = self.window.pending + buffered;
+        let len = core::cmp::min(
+            self.window.head,
+            core::cmp::max(MAX_DISTANCE, pending) as u64,
+        );
+        DecoderState {
+            cmds: self.cmds,
+            rem: self.rem,
+            partial: self.partial,
+            partial_len: self.partial_len as u8,
+            eof: self.eof,
+            total_in: self.total_in,
+            total_out: self.total_out - buffered as u64,
+            head: self.window.head,
+            pending: pending as u32,
+            history: self.window.tail(len as usize),
+        }
+    }
+
+    /// Resume decoding from a state captured by
+    /// [`checkpoint`](#method.checkpoint).
+    ///
+    /// The next input passed to `run` must continue the stream
+    /// `state.total_in()` bytes from its start. The dictionary and the
+    /// output limits are kept, and the variant must be the one the state was
+    /// captured with.
+    pub fn restore(&mut self, state: &DecoderState) {
+        self.cmds = state.cmds;
+        self.rem = state.rem;
+        self.partial = state.partial;
+        self.partial_len = state.partial_len as usize;
+        self.eof = state.eof;
+        self.total_in = state.total_in;
+        self.total_out = state.total_out;
+        self.cmd_start = state.total_in - state.partial_len as u64;
+        self.error = None;
+        let base = state.head - state.total_out - state.pending as u64;
+        self.window.restore(state.head, base, state.pending as usize, &state.history);
+    }
+
+    /// Decode from `input` into `output` until one of them runs out or the
+    /// stream ends. Progress is reflected in `total_in` and `total_out`.
+    ///
+    /// An error found after earlier commands produced output is reported
+    /// once all of that output has been returned, by a later call, so that it
+    /// isn't lost.
+    pub fn run(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<Status, DecompressError> {
+        // keep pending output from growing into the history still needed
+        let high_water = WINDOW_CAP - MAX_DISTANCE - V::MAX_COPY_LENGTH as usize;
+        let mut in_pos = 0;
+        let mut out_pos = 0;
+
+        if self.window.pending == 0 {
+            if let Some(e) = self.error.take() {
+                return Err(e);
             }
         }
 
-        // then, drain the amount of the copy buffer that is necessary to read
-        let bytes_read = std::cmp::min(buf.len(), self.copy_buf.len());
-        let mut cursor = Cursor::new(buf);
-        self.copy_buf.drain(..bytes_read).for_each(|b| { cursor.write_all(&[b]).unwrap(); });
+        loop {
+            while self.error.is_none() && !self.eof && self.window.pending <= high_water {
+                let (parse, used) = self.parse(&input[in_pos..]);
+                in_pos += used;
+                let r = match parse {
+                    Parse::Cmd(cmd) => self.apply(cmd),
+                    Parse::Incomplete => break,
+                };
+                match r {
+                    Ok(()) => {},
+                    // don't lose the output of earlier commands; it is
+                    // drained first
+                    Err(e) if out_pos > 0 || self.window.pending > 0 => {
+                        self.error = Some(e);
+                        break;
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let drained = self.window.drain(&mut output[out_pos..]);
+            out_pos += drained;
+            self.total_out += drained as u64;
+
+            if self.window.pending > 0 || self.error.is_some() {
+                return Ok(Status::NeedsOutput);
+            }
+            if self.eof {
+                return Ok(Status::Done);
+            }
+            if in_pos == input.len() {
+                return Ok(Status::NeedsInput);
+            }
+        }
+    }
+
+    fn parse(&mut self, input: &[u8]) -> (Parse, usize) {
+        self.cmd_start = self.total_in - self.partial_len as u64;
+
+        let staged = self.partial_len;
+        let used = core::cmp::min(self.partial.len() - staged, input.len());
+        let (src, used) = if staged == 0 {
+            (input, input.len())
+        } else {
+            self.partial[staged..staged + used].copy_from_slice(&input[..used]);
+            (&self.partial[..staged + used], used)
+        };
+
+        let mut reader = CmdReader::<V>::resume(src, self.cmds, self.rem);
+        match reader.next_cmd() {
+            Ok(cmd) => {
+                self.cmds = reader.cmds;
+                self.rem = reader.rem;
+                self.partial_len = 0;
+                let used = reader.pos - staged;
+                self.total_in += used as u64;
+                (Parse::Cmd(cmd), used)
+            },
+            Err(_) => {
+                // no command is longer than the staging buffer
+                debug_assert!(staged + used <= self.partial.len());
+                if staged == 0 {
+                    self.partial[..used].copy_from_slice(input);
+                }
+                self.partial_len = staged + used;
+                self.total_in += used as u64;
+                (Parse::Incomplete, used)
+            },
+        }
+    }
+
+    fn apply(&mut self, cmd: Option<Cmd>) -> Result<(), DecompressError> {
+        let output_len = self.window.output_len();
+        let size = match cmd {
+            None => {
+                self.eof = true;
+                return Ok(());
+            },
+            Some(Cmd::Literal(_)) => 1,
+            Some(Cmd::Pointer(_, size)) => size,
+        };
+        if let Some(limit) = self.limit {
+            if output_len + size as u64 > limit {
+                return Err(Fault::LimitExceeded.at(self.cmd_start, output_len));
+            }
+        }
+        if let Some(ratio) = self.max_ratio {
+            let allowed = self.total_in.saturating_mul(ratio as u64) + RATIO_SLACK;
+            if output_len + size as u64 > allowed {
+                return Err(Fault::RatioExceeded.at(self.cmd_start, output_len));
+            }
+        }
+
+        match cmd {
+            Some(Cmd::Literal(b)) => self.window.push(b),
+            Some(Cmd::Pointer(offset, size)) => {
+                self.window.copy(offset, size, self.invalid_pointers)
+                    .map_err(|f| f.at(self.cmd_start, output_len))?;
+            },
+            None => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+impl<V: Variant> Clone for Decompress<V> {
+    fn clone(&self) -> Decompress<V> {
+        Decompress {
+            cmds: self.cmds,
+            rem: self.rem,
+            partial: self.partial,
+            partial_len: self.partial_len,
+            window: self.window.clone(),
+            dictionary: self.dictionary.clone(),
+            eof: self.eof,
+            total_in: self.total_in,
+            total_out: self.total_out,
+            cmd_start: self.cmd_start,
+            limit: self.limit,
+            max_ratio: self.max_ratio,
+            invalid_pointers: self.invalid_pointers,
+            error: self.error.as_ref().and_then(DecompressError::clone_format),
+            pd: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<V: Variant> Default for Decompress<V> {
+    fn default() -> Decompress<V> {
+        Decompress::new()
+    }
+}
+
+/// Snapshot of a decoder's progress through a PRS stream, taken by
+/// [`Decompress::checkpoint`](struct.Decompress.html#method.checkpoint) or
+/// [`PrsDecoder::checkpoint`](struct.PrsDecoder.html#method.checkpoint).
+///
+/// It holds the position in the command bits and the last 8 KiB of output
+/// that pointers can copy from, along with any output not yet returned, so
+/// decoding can resume from the middle of a long stream without starting
+/// over. [`to_bytes`](#method.to_bytes) and
+/// [`from_bytes`](#method.from_bytes) convert it to and from a byte string,
+/// e.g. to resume after the process restarts. An error not yet reported by
+/// the decoder is not captured.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, Decompress, DecoderState, Modern, Status};
+///
+/// let compressed = compress::<Modern>(b"Hello Hello Hello ");
+/// let mut state = Decompress::<Modern>::new();
+/// let mut out = [0; 64];
+/// state.run(&compressed[..4], &mut out).unwrap();
+/// let saved = state.checkpoint().to_bytes();
+/// let len = state.total_out() as usize;
+///
+/// let mut resumed = Decompress::<Modern>::new();
+/// let checkpoint = DecoderState::from_bytes(&saved).unwrap();
+/// resumed.restore(&checkpoint);
+/// let status = resumed.run(&compressed[checkpoint.total_in() as usize..], &mut out[len..]);
+/// assert_eq!(status.unwrap(), Status::Done);
+/// assert_eq!(&out[..resumed.total_out() as usize], b"Hello Hello Hello ");
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct DecoderState {
+    cmds: u8,
+    rem: u8,
+    partial: [u8; 4],
+    partial_len: u8,
+    eof: bool,
+    total_in: u64,
+    total_out: u64,
+    /// total bytes decoded into the window, including the dictionary
+    head: u64,
+    /// decoded bytes at the end of `history` not returned yet
+    pending: u32,
+    history: Vec<u8>,
+}
+
+/// Version byte leading a serialized `DecoderState`.
+const STATE_VERSION: u8 = 1;
+/// Length of a serialized `DecoderState` without its history.
+const STATE_HEADER_LEN: usize = 37;
+
+impl DecoderState {
+    /// Number of compressed bytes consumed; decoding resumes this far into
+    /// the stream.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Number of decompressed bytes returned; the output resumes this far
+    /// into the decompressed data.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Serialize the state, for restoring with
+    /// [`from_bytes`](#method.from_bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(STATE_HEADER_LEN + self.history.len());
+        bytes.extend_from_slice(&[
+            STATE_VERSION,
+            self.cmds,
+            self.rem,
+            self.eof as u8,
+            self.partial_len,
+        ]);
+        bytes.extend_from_slice(&self.partial);
+        bytes.extend_from_slice(&self.total_in.to_le_bytes());
+        bytes.extend_from_slice(&self.total_out.to_le_bytes());
+        bytes.extend_from_slice(&self.head.to_le_bytes());
+        bytes.extend_from_slice(&self.pending.to_le_bytes());
+        bytes.extend_from_slice(&self.history);
+        bytes
+    }
+
+    /// Deserialize a state written by [`to_bytes`](#method.to_bytes).
+    ///
+    /// Returns `None` if `bytes` is not a state written by this version of
+    /// the crate.
+    pub fn from_bytes(bytes: &[u8]) -> Option<DecoderState> {
+        if bytes.len() < STATE_HEADER_LEN || bytes[0] != STATE_VERSION {
+            return None;
+        }
+        let u64_at = |i: usize| {
+            let mut b = [0; 8];
+            b.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(b)
+        };
+        let mut partial = [0; 4];
+        partial.copy_from_slice(&bytes[5..9]);
+        let state = DecoderState {
+            cmds: bytes[1],
+            rem: bytes[2],
+            eof: match bytes[3] {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+            partial_len: bytes[4],
+            partial,
+            total_in: u64_at(9),
+            total_out: u64_at(17),
+            head: u64_at(25),
+            pending: u32::from_le_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]),
+            history: bytes[STATE_HEADER_LEN..].to_vec(),
+        };
+
+        let pending = state.pending as usize;
+        let history_len = core::cmp::min(
+            state.head,
+            core::cmp::max(MAX_DISTANCE, pending) as u64,
+        );
+        let valid = state.rem <= 8
+            && state.partial_len as usize <= state.partial.len()
+            && state.partial_len as u64 <= state.total_in
+            && pending <= WINDOW_CAP
+            && state.history.len() as u64 == history_len
+            && pending <= state.history.len()
+            && state.total_out.checked_add(pending as u64).is_some_and(|len| len <= state.head);
+        if valid {
+            Some(state)
+        } else {
+            None
+        }
+    }
+}
 
-        Ok(bytes_read)
+impl fmt::Debug for DecoderState {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("DecoderState")
+            .field("total_in", &self.total_in)
+            .field("total_out", &self.total_out)
+            .field("pending", &self.pending)
+            .field("eof", &self.eof)
+            .finish()
     }
 }
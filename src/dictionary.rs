@@ -0,0 +1,133 @@
+//! Preset dictionary training.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Largest dictionary the encoder can make use of; pointers reach no further
+/// back than this.
+const MAX_DICTIONARY: usize = 8191;
+
+/// Length of the substrings counted across samples.
+const KMER: usize = 8;
+
+/// Length of the pieces of samples the dictionary is assembled from, and
+/// the step between their starts.
+const SEGMENT: usize = 64;
+const SEGMENT_STEP: usize = 16;
+
+const HASH_BITS: u32 = 18;
+
+fn kmer_hash(kmer: &[u8]) -> usize {
+    let mut b = [0; 8];
+    b.copy_from_slice(kmer);
+    let v = u64::from_le_bytes(b);
+    (v.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (64 - HASH_BITS)) as usize
+}
+
+/// Build a preset dictionary of at most `max_size` bytes from a corpus of
+/// sample files.
+///
+/// Substrings are counted by how many samples contain them, and the pieces
+/// of samples covering the most common substrings are picked until the
+/// dictionary is full. The most useful pieces come last, closest to the data,
+/// where pointers into them are cheapest. Only the last 8191 bytes of a
+/// dictionary can be used, so `max_size` is capped at that.
+///
+/// The samples should resemble the data to be compressed; a dictionary helps
+/// most with many small files sharing structure.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{train_dictionary, Compress, Modern};
+///
+/// let samples: Vec<Vec<u8>> = (0..20)
+///     .map(|i| format!("item {} name=sword atk={} def=0 weight=12", i, i * 3).into_bytes())
+///     .collect();
+/// let refs: Vec<&[u8]> = samples.iter().map(|s| &s[..]).collect();
+/// let dict = train_dictionary(&refs, 1024);
+///
+/// let mut state = Compress::<Modern>::new();
+/// state.set_dictionary(&dict);
+/// let mut out = [0; 64];
+/// state.run(b"item 99 name=sword atk=297 def=0 weight=12", &mut out);
+/// let len = state.total_out() as usize;
+/// state.finish(&mut out[len..]);
+/// assert!(state.total_out() < 30);
+/// ```
+pub fn train_dictionary(samples: &[&[u8]], max_size: usize) -> Vec<u8> {
+    let max_size = core::cmp::min(max_size, MAX_DICTIONARY);
+
+    // number of samples containing each substring, approximated by hash
+    let mut counts = vec![0u32; 1 << HASH_BITS];
+    let mut last_seen = vec![u32::MAX; 1 << HASH_BITS];
+    for (i, sample) in samples.iter().enumerate() {
+        for kmer in sample.windows(KMER) {
+            let h = kmer_hash(kmer);
+            if last_seen[h] != i as u32 {
+                last_seen[h] = i as u32;
+                counts[h] += 1;
+            }
+        }
+    }
+    // substrings found in a single sample don't help the others
+    for c in counts.iter_mut() {
+        if *c < 2 {
+            *c = 0;
+        }
+    }
+
+    let score = |counts: &[u32], segment: &[u8]| -> u64 {
+        segment.windows(KMER).map(|k| counts[kmer_hash(k)] as u64).sum()
+    };
+
+    let mut segments = Vec::new();
+    for (i, sample) in samples.iter().enumerate() {
+        let mut start = 0;
+        while start + KMER <= sample.len() {
+            let end = core::cmp::min(start + SEGMENT, sample.len());
+            segments.push((i, start, end));
+            start += SEGMENT_STEP;
+        }
+    }
+
+    let mut heap: BinaryHeap<(u64, usize)> = segments.iter()
+        .enumerate()
+        .map(|(n, &(i, start, end))| (score(&counts, &samples[i][start..end]), n))
+        .filter(|&(s, _)| s > 0)
+        .collect();
+
+    // scores only drop as substrings get covered, so a popped segment whose
+    // rescored value still beats the next best is the best overall
+    let mut picked: Vec<&[u8]> = Vec::new();
+    let mut size = 0;
+    while let Some((_, n)) = heap.pop() {
+        let (i, start, end) = segments[n];
+        let segment = &samples[i][start..end];
+        let s = score(&counts, segment);
+        if s == 0 {
+            continue;
+        }
+        if heap.peek().is_some_and(|&(next, _)| s < next) {
+            heap.push((s, n));
+            continue;
+        }
+
+        let segment = &segment[..core::cmp::min(segment.len(), max_size - size)];
+        for kmer in segment.windows(KMER) {
+            counts[kmer_hash(kmer)] = 0;
+        }
+        picked.push(segment);
+        size += segment.len();
+        if size == max_size {
+            break;
+        }
+    }
+
+    let mut dict = Vec::with_capacity(size);
+    for segment in picked.iter().rev() {
+        dict.extend_from_slice(segment);
+    }
+    dict
+}
@@ -0,0 +1,151 @@
+//! Differential decoding, for working out unknown PRS variants.
+//!
+//! A PRS dialect that isn't supported yet usually decodes correctly for a
+//! while under one of the known variants and then goes wrong at a particular
+//! command. [`diff_variants`](fn.diff_variants.html) finds the first command
+//! two variants read differently, and
+//! [`diff_reference`](fn.diff_reference.html) finds the first command whose
+//! output disagrees with a known good decompression, e.g. from the game
+//! itself.
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::{Command, Legacy, Modern};
+//! use ages_prs::diff::diff_variants;
+//!
+//! // a literal, then a copy of 20 bytes encoded for Legacy with a size byte,
+//! // which the variants read differently
+//! let compressed = [0x15, b'a', 0xF8, 0xFF, 19, 0, 0];
+//!
+//! let divergence = diff_variants::<Legacy, Modern>(&compressed).unwrap();
+//! assert_eq!(divergence.output_len, 1);
+//! assert_eq!(divergence.first, Some(Command::Pointer { distance: 1, length: 20 }));
+//! assert_eq!(divergence.second, Some(Command::Pointer { distance: 1, length: 29 }));
+//! ```
+
+use crate::{Command, CommandIter, Variant};
+
+use alloc::vec::Vec;
+
+/// The first command two variants decode differently, found by
+/// [`diff_variants`](fn.diff_variants.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Divergence {
+    /// Offset in the input of the command.
+    pub offset: usize,
+    /// Length of the output both variants agree on before the command.
+    pub output_len: u64,
+    /// The command as read by the first variant, or `None` if the input ended
+    /// before it.
+    pub first: Option<Command>,
+    /// The command as read by the second variant, or `None` if the input
+    /// ended before it.
+    pub second: Option<Command>,
+}
+
+/// The first command whose output disagrees with a reference decompression,
+/// found by [`diff_reference`](fn.diff_reference.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Mismatch {
+    /// Offset in the input of the command.
+    pub offset: usize,
+    /// Offset in the output of the first byte that differs from the reference.
+    pub output_len: u64,
+    /// The command producing that byte. `None` if the input ended before the
+    /// output was as long as the reference.
+    pub command: Option<Command>,
+}
+
+fn output_len(cmd: &Command) -> u64 {
+    match *cmd {
+        Command::Literal(_) => 1,
+        Command::Pointer { length, .. } => length as u64,
+        Command::Eof => 0,
+    }
+}
+
+/// Walk the PRS stream at the start of `src` as both variant `A` and variant
+/// `B`, returning the first command they read differently.
+///
+/// Returns `None` if the variants agree on the whole stream.
+pub fn diff_variants<A, B>(src: &[u8]) -> Option<Divergence>
+where
+    A: Variant,
+    B: Variant,
+{
+    let mut first = CommandIter::<A>::new(src);
+    let mut second = CommandIter::<B>::new(src);
+    let mut len = 0;
+    loop {
+        let offset = first.position();
+        let a = first.next();
+        let b = second.next();
+        match (a, b) {
+            (None, None) => return None,
+            (Some(Ok((_, a))), Some(Ok((_, b)))) if a == b => {
+                len += output_len(&a);
+            },
+            // both ran out of input at the same place
+            (Some(Err(_)), Some(Err(_))) => return None,
+            (a, b) => {
+                return Some(Divergence {
+                    offset,
+                    output_len: len,
+                    first: a.and_then(|r| r.ok()).map(|(_, cmd)| cmd),
+                    second: b.and_then(|r| r.ok()).map(|(_, cmd)| cmd),
+                });
+            },
+        }
+    }
+}
+
+/// Decompress the PRS stream at the start of `src` as variant `V`, returning
+/// the first command whose output differs from `reference`.
+///
+/// A pointer copying from before the start of the output is reported as a
+/// mismatch at the output it would have started at, and so is the end of
+/// the stream if it comes before the end of `reference`. Returns `None` if
+/// the stream decompresses to exactly `reference`.
+pub fn diff_reference<V: Variant>(src: &[u8], reference: &[u8]) -> Option<Mismatch> {
+    let mut out = Vec::with_capacity(reference.len());
+    for item in CommandIter::<V>::new(src) {
+        let (offset, cmd) = match item {
+            Ok(item) => item,
+            Err(_) => break,
+        };
+        let start = out.len();
+        let mismatch = |at: usize| Mismatch {
+            offset,
+            output_len: at as u64,
+            command: Some(cmd),
+        };
+        match cmd {
+            Command::Literal(b) => out.push(b),
+            Command::Pointer { distance, length } => {
+                if distance == 0 || distance > start {
+                    return Some(mismatch(start));
+                }
+                for i in start..start + length {
+                    out.push(out[i - distance]);
+                }
+            },
+            Command::Eof => {
+                if start < reference.len() {
+                    return Some(mismatch(start));
+                }
+                return None;
+            },
+        }
+        if let Some(i) = (start..out.len()).find(|&i| reference.get(i) != Some(&out[i])) {
+            return Some(mismatch(i));
+        }
+    }
+    Some(Mismatch {
+        offset: src.len(),
+        output_len: out.len() as u64,
+        command: None,
+    })
+}
@@ -0,0 +1,146 @@
+//! Allocation-free PRS decoding for constrained targets.
+//!
+//! [`WindowDecoder`](struct.WindowDecoder.html) is fed the compressed stream
+//! one byte at a time and hands out decoded bytes through a callback. The
+//! only memory it needs besides its own few bytes of state is a window of
+//! [`WINDOW_SIZE`](constant.WINDOW_SIZE.html) bytes provided by the caller,
+//! which can live in a static or on the stack.
+
+use crate::Variant;
+use crate::decompress::{Cmd, CmdReader, DecompressError, Fault};
+
+/// Size of the window a `WindowDecoder` needs: the furthest distance a PRS
+/// pointer can copy from.
+pub const WINDOW_SIZE: usize = 8192;
+
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+
+/// Byte-at-a-time PRS decoder over a caller-provided window.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, Legacy};
+/// use ages_prs::embedded::{WindowDecoder, WINDOW_SIZE};
+///
+/// let compressed = compress::<Legacy>(b"Hello Hello Hello ");
+/// let mut window = [0; WINDOW_SIZE];
+/// let mut decoder = WindowDecoder::<Legacy>::new(&mut window);
+///
+/// let mut out = Vec::new();
+/// for &b in &compressed {
+///     decoder.push(b, |b| out.push(b)).unwrap();
+/// }
+/// decoder.finish().unwrap();
+/// assert_eq!(&out[..], b"Hello Hello Hello ");
+/// ```
+pub struct WindowDecoder<'w, V: Variant> {
+    window: &'w mut [u8; WINDOW_SIZE],
+    cmds: u8,
+    rem: u8,
+    /// bytes of the command being decoded
+    partial: [u8; 4],
+    partial_len: usize,
+    done: bool,
+    total_in: u64,
+    total_out: u64,
+    pd: core::marker::PhantomData<V>,
+}
+
+impl<'w, V: Variant> WindowDecoder<'w, V> {
+    /// Create a decoder for a new PRS stream using `window` as its history.
+    ///
+    /// The window's contents on entry don't matter.
+    pub fn new(window: &'w mut [u8; WINDOW_SIZE]) -> WindowDecoder<'w, V> {
+        WindowDecoder {
+            window,
+            cmds: 0,
+            rem: 0,
+            partial: [0; 4],
+            partial_len: 0,
+            done: false,
+            total_in: 0,
+            total_out: 0,
+            pd: core::marker::PhantomData,
+        }
+    }
+
+    /// Feed the next byte of the compressed stream, passing every byte it
+    /// decodes to `out`. Returns whether the end of the stream was reached.
+    ///
+    /// Bytes fed after the end of the stream are ignored. After an error the
+    /// decoder should not be used further.
+    pub fn push<F>(&mut self, byte: u8, mut out: F) -> Result<bool, DecompressError>
+    where
+        F: FnMut(u8),
+    {
+        if self.done {
+            return Ok(true);
+        }
+
+        // no command is longer than the staging buffer, so this always fits
+        self.partial[self.partial_len] = byte;
+        self.partial_len += 1;
+        self.total_in += 1;
+
+        let src = &self.partial[..self.partial_len];
+        let mut reader = CmdReader::<V>::resume(src, self.cmds, self.rem);
+        let cmd = match reader.next_cmd() {
+            Ok(cmd) => cmd,
+            // the command continues in the next byte
+            Err(_) => return Ok(false),
+        };
+        self.cmds = reader.cmds;
+        self.rem = reader.rem;
+        let cmd_start = self.total_in - self.partial_len as u64;
+        self.partial_len = 0;
+
+        match cmd {
+            None => self.done = true,
+            Some(Cmd::Literal(b)) => self.emit(b, &mut out),
+            Some(Cmd::Pointer(offset, size)) => {
+                if offset as u64 > self.total_out {
+                    return Err(Fault::InvalidPointer.at(cmd_start, self.total_out));
+                }
+                for _ in 0..size {
+                    let src = (self.total_out as usize).wrapping_sub(offset) & WINDOW_MASK;
+                    self.emit(self.window[src], &mut out);
+                }
+            },
+        }
+        Ok(self.done)
+    }
+
+    /// Check that the whole stream was fed, returning the decompressed size.
+    ///
+    /// Fails with `DecompressError::Eof` if the end of stream command has not
+    /// been reached.
+    pub fn finish(&self) -> Result<u64, DecompressError> {
+        if !self.done {
+            let cmd_start = self.total_in - self.partial_len as u64;
+            return Err(Fault::Eof.at(cmd_start, self.total_out));
+        }
+        Ok(self.total_out)
+    }
+
+    /// Whether the end of the stream was reached.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Number of compressed bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Number of decompressed bytes produced so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    fn emit<F: FnMut(u8)>(&mut self, b: u8, out: &mut F) {
+        self.window[self.total_out as usize & WINDOW_MASK] = b;
+        self.total_out += 1;
+        out(b);
+    }
+}
@@ -0,0 +1,297 @@
+//! C interface, enabled by the `ffi` feature.
+//!
+//! The functions here are declared in `include/ages_prs.h`. Build a shared
+//! library for linking from C or C++ with
+//!
+//! ```text
+//! cargo rustc --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! Functions return `AGES_PRS_OK` (zero) on success and a negative error
+//! code otherwise. Buffers returned by the library are owned by the caller
+//! and must be released with `ages_prs_free`.
+
+use crate::{
+    compress_bound,
+    compress_into,
+    decompress,
+    decompress_into,
+    decompressed_size,
+    CompressError,
+    DecompressError,
+    Legacy,
+    Modern,
+};
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+pub const AGES_PRS_LEGACY: u32 = 0;
+pub const AGES_PRS_MODERN: u32 = 1;
+
+pub const AGES_PRS_OK: i32 = 0;
+pub const AGES_PRS_ERR_NULL: i32 = -1;
+pub const AGES_PRS_ERR_VARIANT: i32 = -2;
+pub const AGES_PRS_ERR_EOF: i32 = -3;
+pub const AGES_PRS_ERR_INVALID_POINTER: i32 = -4;
+pub const AGES_PRS_ERR_BUFFER_TOO_SMALL: i32 = -5;
+pub const AGES_PRS_ERR_OTHER: i32 = -6;
+
+fn decompress_code(e: DecompressError) -> i32 {
+    match e {
+        DecompressError::Eof { .. } => AGES_PRS_ERR_EOF,
+        DecompressError::InvalidPointer { .. } => AGES_PRS_ERR_INVALID_POINTER,
+        DecompressError::BufferTooSmall { .. } => AGES_PRS_ERR_BUFFER_TOO_SMALL,
+        _ => AGES_PRS_ERR_OTHER,
+    }
+}
+
+fn compress_code(e: CompressError) -> i32 {
+    match e {
+        CompressError::BufferTooSmall => AGES_PRS_ERR_BUFFER_TOO_SMALL,
+        _ => AGES_PRS_ERR_OTHER,
+    }
+}
+
+/// Borrow a C buffer, allowing a null pointer for an empty one.
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if ptr.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(ptr, len))
+    }
+}
+
+unsafe fn output<'a>(ptr: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    if len == 0 {
+        Some(&mut [])
+    } else if ptr.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts_mut(ptr, len))
+    }
+}
+
+/// Hand a buffer over to the caller.
+unsafe fn give(buf: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) {
+    let len = buf.len();
+    let buf = Box::into_raw(buf.into_boxed_slice()) as *mut u8;
+    *out = buf;
+    *out_len = len;
+}
+
+/// Returns the largest possible compressed size of `len` bytes.
+#[no_mangle]
+pub extern "C" fn ages_prs_compress_bound(len: usize) -> usize {
+    // the bound is the same for every variant
+    compress_bound::<Legacy>(len)
+}
+
+/// Compress `src` into a new buffer stored in `out`, with its length in
+/// `out_len`.
+///
+/// # Safety
+///
+/// `src` must point to `src_len` readable bytes, or may be null if
+/// `src_len` is zero. `out` and `out_len` must be valid for writes. The
+/// buffer stored in `out` must be released with `ages_prs_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ages_prs_compress(
+    variant: u32,
+    src: *const u8,
+    src_len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    let src = match input(src, src_len) {
+        Some(src) => src,
+        None => return AGES_PRS_ERR_NULL,
+    };
+    if out.is_null() || out_len.is_null() {
+        return AGES_PRS_ERR_NULL;
+    }
+
+    let buf = match variant {
+        AGES_PRS_LEGACY => crate::compress::<Legacy>(src),
+        AGES_PRS_MODERN => crate::compress::<Modern>(src),
+        _ => return AGES_PRS_ERR_VARIANT,
+    };
+    give(buf, out, out_len);
+    AGES_PRS_OK
+}
+
+/// Compress `src` into `dst`, storing the compressed length in `written`.
+///
+/// A destination of `ages_prs_compress_bound(src_len)` bytes is always large
+/// enough.
+///
+/// # Safety
+///
+/// `src` must point to `src_len` readable bytes and `dst` to `dst_len`
+/// writable bytes; either may be null if its length is zero. `written` must
+/// be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ages_prs_compress_into(
+    variant: u32,
+    src: *const u8,
+    src_len: usize,
+    dst: *mut u8,
+    dst_len: usize,
+    written: *mut usize,
+) -> i32 {
+    let (src, dst) = match (input(src, src_len), output(dst, dst_len)) {
+        (Some(src), Some(dst)) if !written.is_null() => (src, dst),
+        _ => return AGES_PRS_ERR_NULL,
+    };
+
+    let r = match variant {
+        AGES_PRS_LEGACY => compress_into::<Legacy>(src, dst),
+        AGES_PRS_MODERN => compress_into::<Modern>(src, dst),
+        _ => return AGES_PRS_ERR_VARIANT,
+    };
+    match r {
+        Ok(len) => {
+            *written = len;
+            AGES_PRS_OK
+        },
+        Err(e) => compress_code(e),
+    }
+}
+
+/// Decompress `src` into a new buffer stored in `out`, with its length in
+/// `out_len`.
+///
+/// # Safety
+///
+/// `src` must point to `src_len` readable bytes, or may be null if
+/// `src_len` is zero. `out` and `out_len` must be valid for writes. The
+/// buffer stored in `out` must be released with `ages_prs_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ages_prs_decompress(
+    variant: u32,
+    src: *const u8,
+    src_len: usize,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    let src = match input(src, src_len) {
+        Some(src) => src,
+        None => return AGES_PRS_ERR_NULL,
+    };
+    if out.is_null() || out_len.is_null() {
+        return AGES_PRS_ERR_NULL;
+    }
+
+    let r = match variant {
+        AGES_PRS_LEGACY => decompress::<Legacy>(src),
+        AGES_PRS_MODERN => decompress::<Modern>(src),
+        _ => return AGES_PRS_ERR_VARIANT,
+    };
+    match r {
+        Ok(buf) => {
+            give(buf, out, out_len);
+            AGES_PRS_OK
+        },
+        Err(e) => decompress_code(e),
+    }
+}
+
+/// Decompress `src` into `dst`, storing the decompressed length in
+/// `written`.
+///
+/// # Safety
+///
+/// `src` must point to `src_len` readable bytes and `dst` to `dst_len`
+/// writable bytes; either may be null if its length is zero. `written` must
+/// be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ages_prs_decompress_into(
+    variant: u32,
+    src: *const u8,
+    src_len: usize,
+    dst: *mut u8,
+    dst_len: usize,
+    written: *mut usize,
+) -> i32 {
+    let (src, dst) = match (input(src, src_len), output(dst, dst_len)) {
+        (Some(src), Some(dst)) if !written.is_null() => (src, dst),
+        _ => return AGES_PRS_ERR_NULL,
+    };
+
+    let r = match variant {
+        AGES_PRS_LEGACY => decompress_into::<Legacy>(src, dst),
+        AGES_PRS_MODERN => decompress_into::<Modern>(src, dst),
+        _ => return AGES_PRS_ERR_VARIANT,
+    };
+    match r {
+        Ok(len) => {
+            *written = len;
+            AGES_PRS_OK
+        },
+        Err(e) => decompress_code(e),
+    }
+}
+
+/// Compute the decompressed size of `src` without decompressing it.
+///
+/// # Safety
+///
+/// `src` must point to `src_len` readable bytes, or may be null if
+/// `src_len` is zero. `size` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ages_prs_decompressed_size(
+    variant: u32,
+    src: *const u8,
+    src_len: usize,
+    size: *mut u64,
+) -> i32 {
+    let src = match input(src, src_len) {
+        Some(src) if !size.is_null() => src,
+        _ => return AGES_PRS_ERR_NULL,
+    };
+
+    let r = match variant {
+        AGES_PRS_LEGACY => decompressed_size::<Legacy>(src),
+        AGES_PRS_MODERN => decompressed_size::<Modern>(src),
+        _ => return AGES_PRS_ERR_VARIANT,
+    };
+    match r {
+        Ok(len) => {
+            *size = len;
+            AGES_PRS_OK
+        },
+        Err(e) => decompress_code(e),
+    }
+}
+
+/// Release a buffer returned by this library.
+///
+/// # Safety
+///
+/// `buf` and `len` must have been returned together by `ages_prs_compress`
+/// or `ages_prs_decompress`, and not released before. A null `buf` is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ages_prs_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Returns a static, NUL-terminated description of an error code.
+#[no_mangle]
+pub extern "C" fn ages_prs_error_message(code: i32) -> *const c_char {
+    let msg: &'static [u8] = match code {
+        AGES_PRS_OK => b"success\0",
+        AGES_PRS_ERR_NULL => b"null pointer argument\0",
+        AGES_PRS_ERR_VARIANT => b"unknown PRS variant\0",
+        AGES_PRS_ERR_EOF => b"unexpected end of PRS stream\0",
+        AGES_PRS_ERR_INVALID_POINTER => b"bad pointer copy in stream\0",
+        AGES_PRS_ERR_BUFFER_TOO_SMALL => b"destination buffer too small\0",
+        _ => b"unknown error\0",
+    };
+    msg.as_ptr() as *const c_char
+}
@@ -0,0 +1,239 @@
+//! Self-describing wrapper around a PRS stream.
+//!
+//! Raw PRS data carries no indication of its variant or size. For data that
+//! stays within an application's own pipeline, [`write_framed`](fn.write_framed.html)
+//! prefixes the stream with a small header recording both, and
+//! [`read_framed`](fn.read_framed.html) decompresses it without the caller
+//! having to know the variant. Games don't understand the header, so data
+//! meant for them should stay raw PRS.
+//!
+//! The header is 14 bytes:
+//!
+//! - the magic bytes [`MAGIC`](constant.MAGIC.html);
+//! - the variant id: 0 for Legacy and 1 for Modern;
+//! - a flags byte, with bit 0 set if long pointers store their offset big
+//!   endian (see [`BigEndian`](../struct.BigEndian.html)), bit 1 set if a
+//!   checksum follows the stream and the other bits zero;
+//! - the decompressed size as a little endian `u64`.
+//!
+//! Frames written by [`write_framed_crc32`](fn.write_framed_crc32.html) end
+//! with the CRC-32 of the decompressed data as a little endian `u32`, which
+//! `read_framed` checks, failing with
+//! [`ChecksumMismatch`](struct.ChecksumMismatch.html) if the data was
+//! corrupted.
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::Modern;
+//! use ages_prs::framed::{read_framed, write_framed};
+//!
+//! let mut framed = Vec::new();
+//! write_framed::<Modern, _>(&mut framed, b"Hello Hello Hello ").unwrap();
+//! assert_eq!(&read_framed(&framed[..]).unwrap()[..], b"Hello Hello Hello ");
+//! ```
+
+use crate::{bufread::PrsDecoder, compress, BigEndian, Legacy, Modern, Variant};
+
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+/// Magic bytes starting a framed PRS stream.
+pub const MAGIC: [u8; 4] = *b"PRSf";
+
+/// Length of the header preceding the PRS stream.
+pub const HEADER_LEN: usize = 14;
+
+const FLAG_BIG_ENDIAN: u8 = 1;
+const FLAG_CRC32: u8 = 2;
+
+/// Lookup table for the CRC-32 of ISO-HDLC, as used by zlib and PNG.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &b| {
+        CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Variant recorded in a frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameVariant {
+    Legacy,
+    Modern,
+}
+
+/// Contents of a frame header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameHeader {
+    /// Variant of the PRS stream following the header.
+    pub variant: FrameVariant,
+    /// Whether long pointers store their offset big endian.
+    pub big_endian_offsets: bool,
+    /// Whether a CRC-32 of the decompressed data follows the stream.
+    pub crc32: bool,
+    /// Length of the data once decompressed.
+    pub decompressed_size: u64,
+}
+
+/// Error reading a frame whose data doesn't match its checksum, returned by
+/// [`read_framed`](fn.read_framed.html) as an `InvalidData` error wrapping
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChecksumMismatch {
+    /// The checksum recorded in the frame.
+    pub expected: u32,
+    /// The checksum of the data decompressed from the frame.
+    pub actual: u32,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "framed PRS checksum mismatch: expected {:08x}, got {:08x}",
+            self.expected,
+            self.actual,
+        )
+    }
+}
+
+impl error::Error for ChecksumMismatch {}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Compress `data` as variant `V` and write it to `writer` behind a frame
+/// header.
+pub fn write_framed<V, W>(writer: W, data: &[u8]) -> io::Result<()>
+where
+    V: Variant,
+    W: Write,
+{
+    write_frame::<V, W>(writer, data, false)
+}
+
+/// Compress `data` as variant `V` and write it to `writer` behind a frame
+/// header, followed by the CRC-32 of `data`.
+///
+/// Reading the frame checks the data against the CRC, catching corruption
+/// that still decompresses to the recorded size.
+pub fn write_framed_crc32<V, W>(writer: W, data: &[u8]) -> io::Result<()>
+where
+    V: Variant,
+    W: Write,
+{
+    write_frame::<V, W>(writer, data, true)
+}
+
+fn write_frame<V, W>(mut writer: W, data: &[u8], checksum: bool) -> io::Result<()>
+where
+    V: Variant,
+    W: Write,
+{
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4] = V::FRAME_ID;
+    if V::BIG_ENDIAN_OFFSETS {
+        header[5] |= FLAG_BIG_ENDIAN;
+    }
+    if checksum {
+        header[5] |= FLAG_CRC32;
+    }
+    header[6..].copy_from_slice(&(data.len() as u64).to_le_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(&compress::<V>(data))?;
+    if checksum {
+        writer.write_all(&crc32(data).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read a frame header from `reader`, leaving it at the start of the PRS
+/// stream.
+///
+/// Fails with `InvalidData` if the header is not valid.
+pub fn read_header<R: Read>(mut reader: R) -> io::Result<FrameHeader> {
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(invalid("missing framed PRS magic"));
+    }
+    let variant = match header[4] {
+        Legacy::FRAME_ID => FrameVariant::Legacy,
+        Modern::FRAME_ID => FrameVariant::Modern,
+        _ => return Err(invalid("unknown framed PRS variant")),
+    };
+    if header[5] & !(FLAG_BIG_ENDIAN | FLAG_CRC32) != 0 {
+        return Err(invalid("unknown framed PRS flags"));
+    }
+    let mut size = [0; 8];
+    size.copy_from_slice(&header[6..]);
+    Ok(FrameHeader {
+        variant,
+        big_endian_offsets: header[5] & FLAG_BIG_ENDIAN != 0,
+        crc32: header[5] & FLAG_CRC32 != 0,
+        decompressed_size: u64::from_le_bytes(size),
+    })
+}
+
+/// Read a framed PRS stream from `reader` and decompress it.
+///
+/// Only the frame is consumed from `reader`. Fails with `InvalidData` if the
+/// header is not valid or the data doesn't decompress to the recorded size,
+/// and if the frame has a checksum, with `InvalidData` wrapping a
+/// [`ChecksumMismatch`](struct.ChecksumMismatch.html) if the data doesn't
+/// match it.
+pub fn read_framed<R: BufRead>(mut reader: R) -> io::Result<Vec<u8>> {
+    let header = read_header(&mut reader)?;
+    match (header.variant, header.big_endian_offsets) {
+        (FrameVariant::Legacy, false) => read_body::<Legacy, _>(reader, &header),
+        (FrameVariant::Modern, false) => read_body::<Modern, _>(reader, &header),
+        (FrameVariant::Legacy, true) => read_body::<BigEndian<Legacy>, _>(reader, &header),
+        (FrameVariant::Modern, true) => read_body::<BigEndian<Modern>, _>(reader, &header),
+    }
+}
+
+fn read_body<V, R>(reader: R, header: &FrameHeader) -> io::Result<Vec<u8>>
+where
+    V: Variant,
+    R: BufRead,
+{
+    let mut decoder = PrsDecoder::<_, V>::with_limit(reader, header.decompressed_size);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out)?;
+    if out.len() as u64 != header.decompressed_size {
+        return Err(invalid("framed PRS data shorter than its header"));
+    }
+    if header.crc32 {
+        let mut expected = [0; 4];
+        decoder.into_inner().read_exact(&mut expected)?;
+        let expected = u32::from_le_bytes(expected);
+        let actual = crc32(&out);
+        if actual != expected {
+            let e = ChecksumMismatch { expected, actual };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+    Ok(out)
+}
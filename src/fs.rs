@@ -0,0 +1,58 @@
+//! Compression and decompression of files, enabled by the `fs` feature.
+//!
+//! Input files are memory-mapped and handed to the buffer functions, which
+//! avoids both reading them into memory first and the overhead of the IO
+//! adapters.
+
+use crate::{compress, decompress, Variant};
+
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+/// Map `path` into memory and pass its contents to `f`.
+fn with_mapped<T, F>(path: &Path, f: F) -> io::Result<T>
+where
+    F: FnOnce(&[u8]) -> io::Result<T>,
+{
+    let file = File::open(path)?;
+    // some platforms refuse to map empty files
+    if file.metadata()?.len() == 0 {
+        return f(&[]);
+    }
+    // SAFETY: the map is only read while it is alive. Another process
+    // modifying the file in the meantime is undefined behavior, as documented
+    // on the public functions.
+    let map = unsafe { Mmap::map(&file)? };
+    f(&map)
+}
+
+/// Decompress the PRS file at `path` into a new `Vec`.
+///
+/// The file is memory-mapped, so it must not be modified while this runs.
+/// Decompression failures are reported as `InvalidData` errors wrapping a
+/// `DecompressError`.
+pub fn decompress_file<V, P>(path: P) -> io::Result<Vec<u8>>
+where
+    V: Variant,
+    P: AsRef<Path>,
+{
+    with_mapped(path.as_ref(), |src| Ok(decompress::<V>(src)?))
+}
+
+/// Compress the file at `src` into a PRS file at `dst`, replacing it if it
+/// exists.
+///
+/// The source file is memory-mapped, so it must not be modified while this
+/// runs.
+pub fn compress_file<V, P, Q>(src: P, dst: Q) -> io::Result<()>
+where
+    V: Variant,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let compressed = with_mapped(src.as_ref(), |src| Ok(compress::<V>(src)))?;
+    fs::write(dst, compressed)
+}
@@ -0,0 +1,160 @@
+//! PRS encoding and decoding over the `AsyncRead` and `AsyncWrite` traits of
+//! `futures-io`, for runtimes other than tokio such as async-std and smol.
+//!
+//! These work the same as the adapters in the
+//! [`tokio`](../tokio/index.html) module. Requires the `futures` feature.
+
+use crate::Variant;
+use crate::poll::{PollDecoder, PollEncoder};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+/// An async IO source for decoding a PRS stream.
+///
+/// The inner AsyncRead is read in chunks, so unlike `PrsDecoder`, data past
+/// the end of the PRS stream may have been read from it and discarded.
+pub struct AsyncPrsDecoder<R: AsyncRead + Unpin, V: Variant> {
+    inner: R,
+    decoder: PollDecoder<V>,
+}
+
+impl<R: AsyncRead + Unpin, V: Variant> AsyncPrsDecoder<R, V> {
+    /// Wraps an AsyncRead source, initializing the decoder state.
+    pub fn new(inner: R) -> AsyncPrsDecoder<R, V> {
+        AsyncPrsDecoder {
+            inner,
+            decoder: PollDecoder::new(),
+        }
+    }
+
+    /// Wraps an AsyncRead source, failing once more than `limit` bytes would
+    /// be decompressed from it.
+    ///
+    /// See [`crate::PrsDecoder::with_limit`](../struct.PrsDecoder.html#method.with_limit).
+    pub fn with_limit(inner: R, limit: u64) -> AsyncPrsDecoder<R, V> {
+        let mut decoder = AsyncPrsDecoder::new(inner);
+        decoder.decoder.state.set_limit(Some(limit));
+        decoder
+    }
+
+    /// Fail once more than `ratio` times as many bytes would be decompressed
+    /// as were read, or lift the limit with `None`.
+    ///
+    /// See [`crate::PrsDecoder::set_max_ratio`](../struct.PrsDecoder.html#method.set_max_ratio).
+    pub fn set_max_ratio(&mut self, ratio: Option<u32>) {
+        self.decoder.state.set_max_ratio(ratio);
+    }
+
+    /// Reference the inner AsyncRead.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Mutably reference the inner AsyncRead.
+    ///
+    /// Reading from it directly will corrupt the PRS stream being decoded.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner AsyncRead.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin, V: Variant> AsyncRead for AsyncPrsDecoder<R, V> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let inner = &mut this.inner;
+        this.decoder.poll_read(cx, buf, |cx, in_buf| {
+            Pin::new(&mut *inner).poll_read(cx, in_buf)
+        })
+    }
+}
+
+/// An async IO sink for compressing and encoding a stream to PRS.
+///
+/// The PRS stream is terminated by `poll_close`; nothing can finish it on
+/// drop, so the stream is left incomplete if the encoder is dropped without
+/// being closed.
+pub struct AsyncPrsEncoder<W: AsyncWrite + Unpin, V: Variant> {
+    inner: W,
+    encoder: PollEncoder<V>,
+}
+
+impl<W: AsyncWrite + Unpin, V: Variant> AsyncPrsEncoder<W, V> {
+    /// Wraps an AsyncWrite sink, initializing the encoder state.
+    pub fn new(inner: W) -> AsyncPrsEncoder<W, V> {
+        AsyncPrsEncoder {
+            inner,
+            encoder: PollEncoder::new(),
+        }
+    }
+
+    /// Reference the inner AsyncWrite.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Mutably reference the inner AsyncWrite.
+    ///
+    /// Writing to it directly will corrupt the PRS stream being encoded.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner AsyncWrite.
+    ///
+    /// Unless the encoder was closed first, the PRS stream written to it is
+    /// incomplete.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin, V: Variant> AsyncWrite for AsyncPrsEncoder<W, V> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let inner = &mut this.inner;
+        this.encoder.poll_write(cx, buf, |cx, out| {
+            Pin::new(&mut *inner).poll_write(cx, out)
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = &mut this.inner;
+        ready!(this.encoder.poll_flush(cx, |cx, out| {
+            Pin::new(&mut *inner).poll_write(cx, out)
+        }))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = &mut this.inner;
+        ready!(this.encoder.poll_finish(cx, |cx, out| {
+            Pin::new(&mut *inner).poll_write(cx, out)
+        }))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
@@ -15,7 +15,8 @@
 //!
 //! Compress and decompress a buffer:
 //!
-//! ```
+#![cfg_attr(feature = "std", doc = "```")]
+#![cfg_attr(not(feature = "std"), doc = "```ignore")]
 //! use std::io::{Cursor, Read, Write};
 //!
 //! use ages_prs::{ModernPrsDecoder, ModernPrsEncoder};
@@ -30,24 +31,171 @@
 //! decoder.read_to_end(&mut decomp).unwrap();
 //! assert_eq!(&decomp[..], &input[..]);
 //! ```
+//!
+//! # Features
+//!
+//! - `std` (default): the IO adapters built on `std::io`. Without it, the
+//!   crate is `no_std` and needs only `alloc`; the buffer functions and the
+//!   `Compress` and `Decompress` state machines remain available.
+//! - `tokio`: async adapters for tokio's IO traits.
+//! - `futures`: async adapters for the `futures-io` traits.
+//! - `ffi`: a C interface; see the [`ffi`](ffi/index.html) module.
+//! - `wasm`: JavaScript bindings through `wasm-bindgen`.
+//! - `python`: a Python extension module through `pyo3`; see the
+//!   [`python`](python/index.html) module.
+//! - `rayon`: parallel compression into the [`seekable`](seekable/index.html)
+//!   container.
+//! - `fs`: helpers compressing and decompressing memory-mapped files; see the
+//!   [`fs`](fs/index.html) module.
+//! - `arbitrary`: generation of valid PRS streams for fuzzing; see the
+//!   [`arbitrary`](arbitrary/index.html) module.
+//! - `simd`: vectorized match finding in the compressor, using SSE2 and AVX2
+//!   on x86_64 and NEON on aarch64. PRS streams are then matched with the
+//!   crate's own matcher instead of `libflate_lz77`, so the compressed output
+//!   differs from that without the feature.
+//! - `lz`: SEGA's related LZSS formats; see the [`lz00`](lz00/index.html) and
+//!   [`lz01`](lz01/index.html) modules.
+//! - `cnx`: the CNX format used by Sonic Team; see the [`cnx`](cnx/index.html)
+//!   module.
+//! - `tracing`: spans and events from the `PrsEncoder` and `PrsDecoder`s
+//!   through `tracing`, at debug level for the start and end of each stream
+//!   and every 16 MiB of input, and at trace level for each flush of the
+//!   encoder's output.
+//! - `bytes`: compression and decompression over the `Buf` and `BufMut`
+//!   traits of `bytes`; see the [`bytes`](bytes/index.html) module.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+mod analyze;
+mod batch;
 mod compress;
 mod decompress;
+mod dictionary;
+mod lz77;
+mod recompress;
+#[cfg(feature = "simd")]
+mod simd;
+#[cfg(feature = "std")]
+mod progress;
 mod variant;
 
-pub use self::compress::{PrsEncoder, IntoInnerError};
-pub use self::decompress::PrsDecoder;
+pub mod codec;
+pub mod conformance;
+pub mod diff;
+pub mod embedded;
+
+#[cfg(feature = "std")]
+pub mod bufread;
+#[cfg(feature = "std")]
+pub mod read;
+#[cfg(feature = "std")]
+pub mod write;
+#[cfg(feature = "std")]
+pub mod seekable;
+#[cfg(feature = "std")]
+pub mod framed;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(feature = "std")]
+mod transcode;
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "futures")]
+pub mod futures;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod poll;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "fs")]
+pub mod fs;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(feature = "lz")]
+mod lzss;
+#[cfg(feature = "lz")]
+pub mod lz00;
+#[cfg(feature = "lz")]
+pub mod lz01;
+#[cfg(feature = "cnx")]
+pub mod cnx;
+#[cfg(feature = "bytes")]
+pub mod bytes;
+
+#[cfg(feature = "std")]
+pub use self::compress::{CommandWriter, PrsEncoder, PrsEncoderBuilder, IntoInnerError};
+pub use self::compress::{
+    Compress,
+    CompressError,
+    EncodeStats,
+    Terminator,
+    compress,
+    compress_bound,
+    compress_into,
+    compress_to_buf,
+    estimate_compressed_size,
+};
+#[cfg(feature = "std")]
+pub use self::decompress::{Chunks, PrsDecoder};
+pub use self::decompress::{
+    Command,
+    CommandForm,
+    CommandIter,
+    Decompress,
+    DecompressError,
+    DecoderState,
+    InvalidPointerMode,
+    decompress,
+    decompress_exact,
+    decompress_into,
+    decompress_lossy,
+    decompress_prefix,
+    decompress_strict,
+    decompress_to_buf,
+    decompress_tolerant,
+    decompress_with,
+    decompress_with_limit,
+    decompressed_size,
+    validate,
+    PrsInfo,
+    Status,
+};
+
+#[cfg(feature = "std")]
+pub use self::stream::{compress_stream, decompress_stream, StreamStats};
+#[cfg(feature = "std")]
+pub use self::transcode::transcode;
+
+pub use self::recompress::{recompress, RecompressOptions, RecompressResult};
+
+pub use self::batch::PrsBatch;
+
+pub use self::analyze::{analyze, dump, records, PrsStats, Record, Records};
+
+pub use self::dictionary::train_dictionary;
 
 pub use self::variant::{
     Variant,
     Legacy,
     Modern,
+    BigEndian,
+    ZeroOffsetEof,
 };
 
+#[cfg(feature = "std")]
 pub type ModernPrsEncoder<W> = PrsEncoder<W, Modern>;
+#[cfg(feature = "std")]
 pub type LegacyPrsEncoder<W> = PrsEncoder<W, Legacy>;
+#[cfg(feature = "std")]
 pub type ModernPrsDecoder<R> = PrsDecoder<R, Modern>;
+#[cfg(feature = "std")]
 pub type LegacyPrsDecoder<R> = PrsDecoder<R, Legacy>;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test;
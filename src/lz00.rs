@@ -0,0 +1,133 @@
+//! Compression and decompression of LZ00, an encrypted form of
+//! [LZ01](../lz01/index.html) found alongside PRS in some SEGA games.
+//!
+//! LZ00 data starts with a 64 byte header:
+//!
+//! - the magic bytes [`MAGIC`](constant.MAGIC.html);
+//! - the length of the data, header included, as a little endian `u32`;
+//! - eight zero bytes;
+//! - the file name, padded with zeros to 32 bytes;
+//! - the decompressed size as a little endian `u32`;
+//! - the encryption key as a little endian `u32`;
+//! - eight zero bytes.
+//!
+//! The compressed data is the same as LZ01's, with each byte XORed with a
+//! keystream generated from the key. Any key works, as it is stored in the
+//! header.
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::lz00;
+//!
+//! let compressed = lz00::compress(b"Hello Hello Hello ", b"hello.txt", 0x1234_5678);
+//! let header = lz00::read_header(&compressed).unwrap();
+//! assert_eq!(header.name(), b"hello.txt");
+//! assert_eq!(&lz00::decompress(&compressed).unwrap()[..], b"Hello Hello Hello ");
+//! ```
+
+use crate::lzss::{self, invalid, read_u32, size};
+
+use std::io;
+
+/// Magic bytes starting LZ00 data.
+pub const MAGIC: [u8; 4] = *b"LZ00";
+
+/// Length of the header preceding the compressed data.
+pub const HEADER_LEN: usize = 0x40;
+
+/// Length of the file name field in the header.
+pub const NAME_LEN: usize = 32;
+
+/// Contents of an LZ00 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Header {
+    /// Length of the data, header included.
+    pub compressed_size: u32,
+    /// The file name field, padded with zeros.
+    pub name: [u8; NAME_LEN],
+    /// Length of the data once decompressed.
+    pub decompressed_size: u32,
+    /// Key the compressed data is encrypted with.
+    pub key: u32,
+}
+
+impl Header {
+    /// The file name, up to its first zero byte.
+    pub fn name(&self) -> &[u8] {
+        let end = self.name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+        &self.name[..end]
+    }
+}
+
+/// XOR `data` with the keystream for `key`, which both encrypts and decrypts.
+fn apply_keystream(data: &mut [u8], mut key: u32) {
+    for b in data {
+        key = key.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let t = (key >> 16) & 0x7FFF;
+        *b ^= (((t << 8) - t) >> 15) as u8;
+    }
+}
+
+/// Read the header at the start of `src`.
+///
+/// Fails with `InvalidData` if the header is not valid.
+pub fn read_header(src: &[u8]) -> io::Result<Header> {
+    if src.len() < HEADER_LEN {
+        return Err(invalid("LZ00 header is truncated"));
+    }
+    if src[..4] != MAGIC {
+        return Err(invalid("not LZ00 data"));
+    }
+    let mut name = [0; NAME_LEN];
+    name.copy_from_slice(&src[0x10..0x10 + NAME_LEN]);
+    let header = Header {
+        compressed_size: read_u32(src, 4),
+        name,
+        decompressed_size: read_u32(src, 0x30),
+        key: read_u32(src, 0x34),
+    };
+    if (header.compressed_size as usize) < HEADER_LEN {
+        return Err(invalid("LZ00 compressed size is smaller than its header"));
+    }
+    Ok(header)
+}
+
+/// Compress `data` to LZ00, recording `name` in the header and encrypting
+/// with `key`.
+///
+/// `name` is truncated to 31 bytes, leaving room for a terminating zero.
+///
+/// # Panics
+///
+/// Panics if `data` or its compressed form are 4 GiB or longer, as the
+/// header can't record their sizes.
+pub fn compress(data: &[u8], name: &[u8], key: u32) -> Vec<u8> {
+    let mut out = vec![0; HEADER_LEN];
+    out.extend_from_slice(&lzss::encode(data));
+    apply_keystream(&mut out[HEADER_LEN..], key);
+
+    let name = &name[..core::cmp::min(name.len(), NAME_LEN - 1)];
+    out[..4].copy_from_slice(&MAGIC);
+    let len = size(out.len());
+    out[4..8].copy_from_slice(&len.to_le_bytes());
+    out[0x10..0x10 + name.len()].copy_from_slice(name);
+    out[0x30..0x34].copy_from_slice(&size(data.len()).to_le_bytes());
+    out[0x34..0x38].copy_from_slice(&key.to_le_bytes());
+    out
+}
+
+/// Decompress the LZ00 data at the start of `src`.
+///
+/// Fails with `InvalidData` if the header is not valid or the data ends
+/// before the decompressed size given in the header is reached.
+pub fn decompress(src: &[u8]) -> io::Result<Vec<u8>> {
+    let header = read_header(src)?;
+    let mut data = src
+        .get(HEADER_LEN..header.compressed_size as usize)
+        .ok_or_else(|| invalid("LZ00 data is truncated"))?
+        .to_vec();
+    apply_keystream(&mut data, header.key);
+    lzss::decode(&data, header.decompressed_size as usize)
+}
@@ -0,0 +1,87 @@
+//! Compression and decompression of LZ01, an LZSS format found alongside PRS
+//! in some SEGA games.
+//!
+//! Unlike PRS, LZ01 data starts with a 16 byte header:
+//!
+//! - the magic bytes [`MAGIC`](constant.MAGIC.html);
+//! - the length of the data, header included, as a little endian `u32`;
+//! - the decompressed size as a little endian `u32`;
+//! - four zero bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use ages_prs::lz01;
+//!
+//! let compressed = lz01::compress(b"Hello Hello Hello ");
+//! assert_eq!(lz01::read_header(&compressed).unwrap().decompressed_size, 18);
+//! assert_eq!(&lz01::decompress(&compressed).unwrap()[..], b"Hello Hello Hello ");
+//! ```
+
+use crate::lzss::{self, invalid, read_u32, size};
+
+use std::io;
+
+/// Magic bytes starting LZ01 data.
+pub const MAGIC: [u8; 4] = *b"LZ01";
+
+/// Length of the header preceding the compressed data.
+pub const HEADER_LEN: usize = 16;
+
+/// Contents of an LZ01 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Header {
+    /// Length of the data, header included.
+    pub compressed_size: u32,
+    /// Length of the data once decompressed.
+    pub decompressed_size: u32,
+}
+
+/// Read the header at the start of `src`.
+///
+/// Fails with `InvalidData` if the header is not valid.
+pub fn read_header(src: &[u8]) -> io::Result<Header> {
+    if src.len() < HEADER_LEN {
+        return Err(invalid("LZ01 header is truncated"));
+    }
+    if src[..4] != MAGIC {
+        return Err(invalid("not LZ01 data"));
+    }
+    let header = Header {
+        compressed_size: read_u32(src, 4),
+        decompressed_size: read_u32(src, 8),
+    };
+    if (header.compressed_size as usize) < HEADER_LEN {
+        return Err(invalid("LZ01 compressed size is smaller than its header"));
+    }
+    Ok(header)
+}
+
+/// Compress `data` to LZ01.
+///
+/// # Panics
+///
+/// Panics if `data` or its compressed form are 4 GiB or longer, as the
+/// header can't record their sizes.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0; HEADER_LEN];
+    out.extend_from_slice(&lzss::encode(data));
+    out[..4].copy_from_slice(&MAGIC);
+    let len = size(out.len());
+    out[4..8].copy_from_slice(&len.to_le_bytes());
+    out[8..12].copy_from_slice(&size(data.len()).to_le_bytes());
+    out
+}
+
+/// Decompress the LZ01 data at the start of `src`.
+///
+/// Fails with `InvalidData` if the header is not valid or the data ends
+/// before the decompressed size given in the header is reached.
+pub fn decompress(src: &[u8]) -> io::Result<Vec<u8>> {
+    let header = read_header(src)?;
+    let data = src
+        .get(HEADER_LEN..header.compressed_size as usize)
+        .ok_or_else(|| invalid("LZ01 data is truncated"))?;
+    lzss::decode(data, header.decompressed_size as usize)
+}
@@ -0,0 +1,407 @@
+//! LZ77 match finding for the PRS encoder.
+//!
+//! PRS streams are matched with `libflate_lz77`. It forgets its input at
+//! every flush, so it can't match against a preset dictionary, and its
+//! minimum match length is fixed. Dictionaries and the other LZ77 formats
+//! use the hash chain matcher here instead, as does everything with the
+//! `simd` feature, since only this matcher's match length comparison is
+//! vectorized. The hash chain matcher works over a sliding window
+//! of the input. Positions are hashed by their first three bytes; each hash
+//! bucket holds the most recent position and older positions are linked
+//! through a ring as large as the window. Matching is greedy, taking the
+//! longest match found within the chain search limit.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use libflate_lz77::{DefaultLz77Encoder, DefaultLz77EncoderBuilder, Lz77Encode, MAX_LENGTH};
+
+/// Furthest distance a PRS pointer can copy from.
+pub(crate) const WINDOW_SIZE: usize = 8191;
+
+/// Shortest match worth emitting as a pointer.
+const MIN_MATCH: usize = 3;
+
+const HASH_BITS: u32 = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+const CHAIN_SIZE: usize = 8192;
+const CHAIN_MASK: usize = CHAIN_SIZE - 1;
+
+/// How many chain links are followed looking for a longer match.
+const MAX_CHAIN: usize = 64;
+
+/// Marks an empty hash bucket or the end of a chain.
+const NIL: usize = usize::MAX;
+
+/// Output of the matcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Code {
+    Literal(u8),
+    Pointer { length: u16, backward_distance: u16 },
+}
+
+/// Consumer of the matcher's output.
+pub(crate) trait Sink {
+    fn consume(&mut self, code: Code);
+}
+
+/// Passes the codes of `libflate_lz77` on to a `Sink`.
+struct Forward<'a, S>(&'a mut S);
+
+impl<S: Sink> libflate_lz77::Sink for Forward<'_, S> {
+    fn consume(&mut self, code: libflate_lz77::Code) {
+        self.0.consume(match code {
+            libflate_lz77::Code::Literal(b) => Code::Literal(b),
+            libflate_lz77::Code::Pointer { length, backward_distance } => {
+                Code::Pointer { length, backward_distance }
+            },
+        });
+    }
+}
+
+pub(crate) struct Lz77Encoder {
+    matcher: Matcher,
+}
+
+enum Matcher {
+    Libflate {
+        encoder: DefaultLz77Encoder,
+        max_length: u16,
+        /// how many of the next bytes of input must be encoded as literals
+        forced_literals: usize,
+        /// whether `encoder` may hold input not yet flushed
+        holding: bool,
+    },
+    HashChain(HashChain),
+}
+
+/// The matcher for PRS streams without a dictionary.
+fn plain(max_length: u16) -> Matcher {
+    if cfg!(feature = "simd") {
+        Matcher::HashChain(HashChain::new(max_length))
+    } else {
+        libflate(max_length)
+    }
+}
+
+fn libflate(max_length: u16) -> Matcher {
+    Matcher::Libflate {
+        encoder: DefaultLz77EncoderBuilder::new()
+            .window_size(WINDOW_SIZE as u16)
+            .max_length(core::cmp::min(MAX_LENGTH, max_length))
+            .build(),
+        max_length,
+        forced_literals: 0,
+        holding: false,
+    }
+}
+
+impl Lz77Encoder {
+    pub(crate) fn new(max_length: u16) -> Lz77Encoder {
+        Lz77Encoder { matcher: plain(max_length) }
+    }
+
+    /// Create a matcher for a format with a smaller window or longer minimum
+    /// match than PRS.
+    #[cfg(any(feature = "lz", feature = "cnx"))]
+    pub(crate) fn with_limits(min_length: usize, max_length: u16, window: usize) -> Lz77Encoder {
+        let chain = HashChain::with_limits(min_length, max_length, window);
+        Lz77Encoder { matcher: Matcher::HashChain(chain) }
+    }
+
+    /// Match against `dict` as if it preceded the input of every stream,
+    /// starting a new stream. An empty dictionary turns this off.
+    pub(crate) fn set_dictionary(&mut self, dict: &[u8]) {
+        let max_length = match self.matcher {
+            Matcher::Libflate { max_length, .. } => max_length,
+            Matcher::HashChain(ref chain) => chain.max_length as u16,
+        };
+        if dict.is_empty() {
+            self.matcher = plain(max_length);
+            return;
+        }
+        if let Matcher::Libflate { .. } = self.matcher {
+            self.matcher = Matcher::HashChain(HashChain::new(max_length));
+        }
+        if let Matcher::HashChain(ref mut chain) = self.matcher {
+            chain.set_dictionary(dict);
+        }
+    }
+
+    /// Forget all input, keeping the allocations and the dictionary.
+    pub(crate) fn reset(&mut self) {
+        match self.matcher {
+            // a flushed libflate encoder is empty, and its buffer is kept
+            Matcher::Libflate { holding: false, ref mut forced_literals, .. } => {
+                *forced_literals = 0;
+            },
+            Matcher::Libflate { max_length, .. } => self.matcher = libflate(max_length),
+            Matcher::HashChain(ref mut chain) => chain.reset(),
+        }
+    }
+
+    /// Add `input` and encode as much of it as can be matched without seeing
+    /// more input.
+    pub(crate) fn encode<S: Sink>(&mut self, input: &[u8], sink: &mut S) {
+        match self.matcher {
+            Matcher::Libflate {
+                ref mut encoder,
+                ref mut forced_literals,
+                ref mut holding,
+                ..
+            } => {
+                // only forced after a flush, so there is nothing to match them
+                // against anyway
+                let forced = core::cmp::min(*forced_literals, input.len());
+                for &b in &input[..forced] {
+                    sink.consume(Code::Literal(b));
+                }
+                *forced_literals -= forced;
+                *holding |= forced < input.len();
+                encoder.encode(&input[forced..], Forward(sink));
+            },
+            Matcher::HashChain(ref mut chain) => chain.encode(input, sink),
+        }
+    }
+
+    /// Encode the next `count` bytes of input as literals.
+    #[cfg(feature = "std")]
+    pub(crate) fn force_literals(&mut self, count: usize) {
+        match self.matcher {
+            Matcher::Libflate { ref mut forced_literals, .. } => *forced_literals += count,
+            Matcher::HashChain(ref mut chain) => chain.forced_literals += count,
+        }
+    }
+
+    /// Encode all input that has been added.
+    pub(crate) fn flush<S: Sink>(&mut self, sink: &mut S) {
+        match self.matcher {
+            Matcher::Libflate { ref mut encoder, ref mut holding, .. } => {
+                encoder.flush(Forward(sink));
+                *holding = false;
+            },
+            Matcher::HashChain(ref mut chain) => chain.flush(sink),
+        }
+    }
+}
+
+struct HashChain {
+    min_length: usize,
+    max_length: usize,
+    /// furthest distance a match may copy from, at most `WINDOW_SIZE`
+    window: usize,
+    /// input from absolute position `base` on: the window followed by the
+    /// input not yet encoded
+    buf: Vec<u8>,
+    base: usize,
+    /// absolute position of the next byte to encode
+    pos: usize,
+    /// most recent absolute position for each hash, allocated on first use
+    head: Vec<usize>,
+    /// previous position with the same hash, indexed by position
+    prev: Vec<usize>,
+    /// first position not yet linked into its hash chain for lack of
+    /// lookahead, at the end of the dictionary
+    unhashed: usize,
+    /// history every stream starts with
+    dictionary: Vec<u8>,
+    /// how many of the next positions must be encoded as literals
+    forced_literals: usize,
+}
+
+impl HashChain {
+    fn new(max_length: u16) -> HashChain {
+        HashChain {
+            min_length: MIN_MATCH,
+            max_length: max_length as usize,
+            window: WINDOW_SIZE,
+            buf: Vec::new(),
+            base: 0,
+            pos: 0,
+            head: Vec::new(),
+            prev: Vec::new(),
+            unhashed: 0,
+            dictionary: Vec::new(),
+            forced_literals: 0,
+        }
+    }
+
+    #[cfg(any(feature = "lz", feature = "cnx"))]
+    fn with_limits(min_length: usize, max_length: u16, window: usize) -> HashChain {
+        debug_assert!(min_length >= MIN_MATCH && window <= WINDOW_SIZE);
+        HashChain {
+            min_length,
+            window,
+            ..HashChain::new(max_length)
+        }
+    }
+
+    fn set_dictionary(&mut self, dict: &[u8]) {
+        let start = dict.len().saturating_sub(WINDOW_SIZE);
+        self.dictionary.clear();
+        self.dictionary.extend_from_slice(&dict[start..]);
+        self.reset();
+    }
+
+    /// Forget all input, keeping the allocations and the dictionary.
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.base = 0;
+        self.pos = 0;
+        self.unhashed = 0;
+        self.forced_literals = 0;
+        for h in self.head.iter_mut() {
+            *h = NIL;
+        }
+
+        if !self.dictionary.is_empty() {
+            self.alloc();
+            self.buf.extend_from_slice(&self.dictionary);
+            while self.pos < self.buf.len() {
+                self.insert();
+                self.pos += 1;
+            }
+            // the last positions need input to hash
+            self.unhashed = self.pos.saturating_sub(MIN_MATCH - 1);
+        }
+    }
+
+    fn encode<S: Sink>(&mut self, input: &[u8], sink: &mut S) {
+        self.buf.extend_from_slice(input);
+        // a match starting here could still continue into future input
+        let end = self.end().saturating_sub(self.max_length);
+        self.encode_to(end, sink);
+        self.slide();
+    }
+
+    /// Encode all input that has been added. Matches may still refer to it
+    /// afterwards.
+    fn flush<S: Sink>(&mut self, sink: &mut S) {
+        let end = self.end();
+        self.encode_to(end, sink);
+        self.slide();
+    }
+
+    fn end(&self) -> usize {
+        self.base + self.buf.len()
+    }
+
+    fn alloc(&mut self) {
+        if self.head.is_empty() {
+            self.head = vec![NIL; HASH_SIZE];
+            self.prev = vec![NIL; CHAIN_SIZE];
+        }
+    }
+
+    fn encode_to<S: Sink>(&mut self, end: usize, sink: &mut S) {
+        self.alloc();
+        while self.unhashed < self.pos && self.hash(self.unhashed).is_some() {
+            self.insert_at(self.unhashed);
+            self.unhashed += 1;
+        }
+        let start = self.pos;
+
+        while self.pos < end {
+            let (length, distance) = if self.forced_literals > 0 {
+                self.forced_literals -= 1;
+                (0, 0)
+            } else {
+                self.longest_match()
+            };
+            if length >= self.min_length {
+                sink.consume(Code::Pointer {
+                    length: length as u16,
+                    backward_distance: distance as u16,
+                });
+                for _ in 0..length {
+                    self.insert();
+                    self.pos += 1;
+                }
+            } else {
+                sink.consume(Code::Literal(self.buf[self.pos - self.base]));
+                self.insert();
+                self.pos += 1;
+            }
+        }
+        // catching up above finished if anything was encoded
+        if self.unhashed == start {
+            self.unhashed = self.pos;
+        }
+    }
+
+    /// Drop input that has moved out of the window.
+    fn slide(&mut self) {
+        // only move the buffer once a good amount can be dropped
+        let keep_from = self.pos.saturating_sub(WINDOW_SIZE);
+        if keep_from - self.base >= WINDOW_SIZE * 4 {
+            self.buf.drain(..keep_from - self.base);
+            self.base = keep_from;
+        }
+    }
+
+    fn hash(&self, pos: usize) -> Option<usize> {
+        let i = pos - self.base;
+        let bytes = self.buf.get(i..i + MIN_MATCH)?;
+        let v = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        Some((v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize)
+    }
+
+    /// Link the current position into its hash chain.
+    fn insert(&mut self) {
+        self.insert_at(self.pos);
+    }
+
+    fn insert_at(&mut self, pos: usize) {
+        if let Some(h) = self.hash(pos) {
+            self.prev[pos & CHAIN_MASK] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    /// Find the longest match for the current position, returning its length
+    /// and distance.
+    fn longest_match(&self) -> (usize, usize) {
+        let h = match self.hash(self.pos) {
+            Some(h) => h,
+            None => return (0, 0),
+        };
+        let cur = &self.buf[self.pos - self.base..];
+        let max_length = core::cmp::min(self.max_length, cur.len());
+
+        let mut best = (0, 0);
+        let mut cand = self.head[h];
+        for _ in 0..MAX_CHAIN {
+            if cand == NIL || cand >= self.pos || self.pos - cand > self.window {
+                break;
+            }
+            let distance = self.pos - cand;
+            let length = match_length(&self.buf[cand - self.base..], cur, max_length);
+            if length > best.0 {
+                best = (length, distance);
+                if length == max_length {
+                    break;
+                }
+            }
+            let next = self.prev[cand & CHAIN_MASK];
+            // stale links point forward; the chain ends there
+            if next >= cand {
+                break;
+            }
+            cand = next;
+        }
+        best
+    }
+}
+
+#[cfg(feature = "simd")]
+use crate::simd::match_length;
+
+/// Length of the common prefix of `a` and `b`, up to `max`.
+#[cfg(not(feature = "simd"))]
+fn match_length(a: &[u8], b: &[u8], max: usize) -> usize {
+    a.iter()
+        .zip(b)
+        .take(max)
+        .take_while(|(x, y)| x == y)
+        .count()
+}
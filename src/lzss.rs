@@ -0,0 +1,127 @@
+//! The LZSS coding shared by the LZ00 and LZ01 formats.
+//!
+//! Commands are grouped by eight behind a flag byte, read from the least
+//! significant bit, where a set bit is a literal byte and a clear bit a two
+//! byte pointer. Pointers don't hold a distance but an absolute position in a
+//! 4096 byte ring buffer of the output, which starts zero filled with the
+//! first byte written at `RING_START`. The position is the first byte and the
+//! top four bits of the second, and the low four bits are the length less
+//! three.
+
+use crate::lz77::{Code, Lz77Encoder, Sink};
+
+use std::convert::TryFrom;
+use std::io;
+
+const RING_SIZE: usize = 4096;
+const RING_MASK: usize = RING_SIZE - 1;
+const RING_START: usize = 0xFEE;
+
+const MIN_LENGTH: usize = 3;
+const MAX_LENGTH: u16 = 18;
+
+/// Most output a byte of input can produce: a pointer's 18 bytes for its two,
+/// ignoring the flag bytes.
+const MAX_RATIO: usize = 9;
+
+pub(crate) fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn read_u32(src: &[u8], at: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&src[at..at + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// A length as recorded in a header.
+pub(crate) fn size(len: usize) -> u32 {
+    u32::try_from(len).expect("LZ sizes must fit in 32 bits")
+}
+
+/// Decode `src` until `len` bytes have been output.
+pub(crate) fn decode(src: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let mut ring = [0; RING_SIZE];
+    let mut r = RING_START;
+    let mut out = Vec::with_capacity(core::cmp::min(len, src.len().saturating_mul(MAX_RATIO)));
+    let mut bytes = src.iter().copied();
+    let mut next = || bytes.next().ok_or_else(|| invalid("LZSS data ends early"));
+
+    // the high byte counts down the flags left
+    let mut flags: u32 = 0;
+    while out.len() < len {
+        flags >>= 1;
+        if flags & 0x100 == 0 {
+            flags = next()? as u32 | 0xFF00;
+        }
+
+        if flags & 1 != 0 {
+            let b = next()?;
+            out.push(b);
+            ring[r] = b;
+            r = (r + 1) & RING_MASK;
+        } else {
+            let b1 = next()? as usize;
+            let b2 = next()? as usize;
+            let mut offset = b1 | ((b2 & 0xF0) << 4);
+            let length = (b2 & 0x0F) + MIN_LENGTH;
+            for _ in 0..core::cmp::min(length, len - out.len()) {
+                let b = ring[offset];
+                out.push(b);
+                ring[r] = b;
+                r = (r + 1) & RING_MASK;
+                offset = (offset + 1) & RING_MASK;
+            }
+        }
+    }
+    Ok(out)
+}
+
+struct LzssSink {
+    out: Vec<u8>,
+    /// index in `out` of the current flag byte
+    flags: usize,
+    /// flag bits used in the current flag byte
+    bit: u8,
+    /// length of the output so far
+    written: usize,
+}
+
+impl Sink for LzssSink {
+    fn consume(&mut self, code: Code) {
+        if self.bit == 8 {
+            self.flags = self.out.len();
+            self.out.push(0);
+            self.bit = 0;
+        }
+
+        match code {
+            Code::Literal(b) => {
+                self.out[self.flags] |= 1 << self.bit;
+                self.out.push(b);
+                self.written += 1;
+            },
+            Code::Pointer { length, backward_distance } => {
+                let offset = (RING_START + self.written - backward_distance as usize) & RING_MASK;
+                self.out.push(offset as u8);
+                self.out.push(((offset >> 4) & 0xF0) as u8 | (length as usize - MIN_LENGTH) as u8);
+                self.written += length as usize;
+            },
+        }
+        self.bit += 1;
+    }
+}
+
+/// Encode all of `data`.
+pub(crate) fn encode(data: &[u8]) -> Vec<u8> {
+    let mut sink = LzssSink {
+        out: Vec::new(),
+        flags: 0,
+        bit: 8,
+        written: 0,
+    };
+    let mut matcher = Lz77Encoder::with_limits(MIN_LENGTH, MAX_LENGTH, RING_SIZE);
+    matcher.encode(data, &mut sink);
+    matcher.flush(&mut sink);
+    sink.out
+}
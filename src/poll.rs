@@ -0,0 +1,200 @@
+//! Driving the state machines from poll functions, shared by the async
+//! adapters. The inner IO is polled through closures so that each runtime's
+//! traits only need a thin wrapper.
+
+use crate::Variant;
+use crate::compress::Compress;
+use crate::decompress::{Decompress, Status};
+
+use std::io;
+use std::task::{ready, Context, Poll};
+
+/// Capacity of the buffers between the state machines and the inner IO.
+const BUF_CAPACITY: usize = 8192;
+
+/// Decoder state along with the input read from the inner IO.
+pub(crate) struct PollDecoder<V: Variant> {
+    pub(crate) state: Decompress<V>,
+    /// data read from the inner IO, allocated on first use
+    in_buf: Box<[u8]>,
+    in_pos: usize,
+    in_len: usize,
+}
+
+impl<V: Variant> PollDecoder<V> {
+    pub(crate) fn new() -> PollDecoder<V> {
+        PollDecoder {
+            state: Decompress::new(),
+            in_buf: Box::default(),
+            in_pos: 0,
+            in_len: 0,
+        }
+    }
+
+    /// Decode into `out`, reading the inner IO with `poll_inner` whenever the
+    /// buffered input runs out.
+    pub(crate) fn poll_read<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        out: &mut [u8],
+        mut poll_inner: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnMut(&mut Context<'_>, &mut [u8]) -> Poll<io::Result<usize>>,
+    {
+        if out.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        loop {
+            let total_in = self.state.total_in();
+            let total_out = self.state.total_out();
+            let input = &self.in_buf[self.in_pos..self.in_len];
+            let status = self.state.run(input, out)?;
+            self.in_pos += (self.state.total_in() - total_in) as usize;
+            let produced = (self.state.total_out() - total_out) as usize;
+
+            if produced > 0 || status != Status::NeedsInput {
+                return Poll::Ready(Ok(produced));
+            }
+
+            if self.in_buf.is_empty() {
+                self.in_buf = vec![0; BUF_CAPACITY].into_boxed_slice();
+            }
+            let len = ready!(poll_inner(cx, &mut self.in_buf))?;
+            if len == 0 {
+                return Poll::Ready(Err(self.state.eof_error().into()));
+            }
+            self.in_pos = 0;
+            self.in_len = len;
+        }
+    }
+}
+
+/// Encoder state along with the output not yet written to the inner IO.
+pub(crate) struct PollEncoder<V: Variant> {
+    state: Compress<V>,
+    /// compressed data not yet written to the inner IO, allocated on first use
+    out_buf: Box<[u8]>,
+    out_pos: usize,
+    out_len: usize,
+    /// whether the whole stream has been moved into `out_buf`
+    finished: bool,
+}
+
+impl<V: Variant> PollEncoder<V> {
+    pub(crate) fn new() -> PollEncoder<V> {
+        PollEncoder {
+            state: Compress::new(),
+            out_buf: Box::default(),
+            out_pos: 0,
+            out_len: 0,
+            finished: false,
+        }
+    }
+
+    /// Compress `buf`, writing the output to the inner IO with `poll_inner`.
+    pub(crate) fn poll_write<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        mut poll_inner: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        if self.finished && !buf.is_empty() {
+            return Poll::Ready(Err(io::Error::other(
+                "write after the PRS stream was closed"
+            )));
+        }
+        loop {
+            ready!(self.poll_dump(cx, &mut poll_inner))?;
+            let total_in = self.state.total_in();
+            self.compress(|state, out| state.run(buf, out));
+            let consumed = (self.state.total_in() - total_in) as usize;
+            // output still waiting in the state machine holds back the input
+            if consumed > 0 || buf.is_empty() {
+                return Poll::Ready(Ok(consumed));
+            }
+        }
+    }
+
+    /// Write out all compressed data that is ready. The inner IO still needs
+    /// to be flushed by the caller.
+    pub(crate) fn poll_flush<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut poll_inner: F,
+    ) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        loop {
+            ready!(self.poll_dump(cx, &mut poll_inner))?;
+            self.compress(|state, out| state.run(&[], out));
+            if self.out_len == 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// Terminate the stream and write out the rest of it. The inner IO still
+    /// needs to be closed by the caller.
+    pub(crate) fn poll_finish<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut poll_inner: F,
+    ) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        loop {
+            ready!(self.poll_dump(cx, &mut poll_inner))?;
+            if self.finished {
+                return Poll::Ready(Ok(()));
+            }
+            let status = self.compress(|state, out| state.finish(out));
+            self.finished = status == Status::Done;
+        }
+    }
+
+    /// Run the state machine into the empty output buffer.
+    fn compress<F>(&mut self, f: F) -> Status
+    where
+        F: FnOnce(&mut Compress<V>, &mut [u8]) -> Status,
+    {
+        if self.out_buf.is_empty() {
+            self.out_buf = vec![0; BUF_CAPACITY].into_boxed_slice();
+        }
+
+        let total_out = self.state.total_out();
+        let status = f(&mut self.state, &mut self.out_buf);
+        self.out_pos = 0;
+        self.out_len = (self.state.total_out() - total_out) as usize;
+        status
+    }
+
+    /// Write out the buffered compressed data.
+    fn poll_dump<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll_inner: &mut F,
+    ) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        while self.out_pos < self.out_len {
+            let buf = &self.out_buf[self.out_pos..self.out_len];
+            let n = ready!(poll_inner(cx, buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write the buffered data"
+                )));
+            }
+            self.out_pos += n;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
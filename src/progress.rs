@@ -0,0 +1,91 @@
+//! Progress reporting for the IO adapters.
+//!
+//! With the `tracing` feature, this is also where the adapters' spans and
+//! their events for the start and end of a stream and its progress come
+//! from.
+
+/// Input consumed between progress events.
+#[cfg(feature = "tracing")]
+const TRACE_INTERVAL: u64 = 16 << 20;
+
+/// Callback receiving the number of bytes consumed and produced so far.
+pub(crate) struct Progress {
+    callback: Option<Box<dyn FnMut(u64, u64) + Send + Sync>>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    /// input consumed when the next progress event is due
+    #[cfg(feature = "tracing")]
+    next_event: u64,
+    /// whether the end of the stream has been traced
+    #[cfg(feature = "tracing")]
+    finished: bool,
+}
+
+impl Progress {
+    /// Start tracking a stream. `kind` names the adapter in its span.
+    pub(crate) fn new(kind: &'static str) -> Progress {
+        #[cfg(not(feature = "tracing"))]
+        let _ = kind;
+        let mut progress = Progress {
+            callback: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!("prs", kind),
+            #[cfg(feature = "tracing")]
+            next_event: 0,
+            #[cfg(feature = "tracing")]
+            finished: false,
+        };
+        progress.start();
+        progress
+    }
+
+    pub(crate) fn set<F>(&mut self, f: F)
+    where
+        F: FnMut(u64, u64) + Send + Sync + 'static,
+    {
+        self.callback = Some(Box::new(f));
+    }
+
+    /// Note the start of a new stream, after a reset.
+    pub(crate) fn start(&mut self) {
+        #[cfg(feature = "tracing")]
+        {
+            self.next_event = TRACE_INTERVAL;
+            self.finished = false;
+            tracing::debug!(parent: &self.span, "stream started");
+        }
+    }
+
+    pub(crate) fn report(&mut self, total_in: u64, total_out: u64) {
+        if let Some(f) = self.callback.as_mut() {
+            f(total_in, total_out);
+        }
+        #[cfg(feature = "tracing")]
+        {
+            if total_in >= self.next_event {
+                self.next_event = (total_in / TRACE_INTERVAL + 1) * TRACE_INTERVAL;
+                tracing::debug!(parent: &self.span, total_in, total_out, "progress");
+            }
+        }
+    }
+
+    /// Note the end of the stream, once all of it has been consumed or
+    /// produced.
+    pub(crate) fn finish(&mut self, total_in: u64, total_out: u64) {
+        #[cfg(feature = "tracing")]
+        {
+            if !self.finished {
+                self.finished = true;
+                tracing::debug!(parent: &self.span, total_in, total_out, "stream finished");
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (total_in, total_out);
+    }
+
+    /// The span events about the stream belong to.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+}
@@ -0,0 +1,70 @@
+//! Python bindings, enabled by the `python` feature.
+//!
+//! Builds an extension module named `ages_prs` with
+//! [maturin](https://www.maturin.rs/) using the `pyproject.toml` at the root
+//! of the repository. The module exposes two functions:
+//!
+//! ```text
+//! compress(data: bytes, variant: str) -> bytes
+//! decompress(data: bytes, variant: str) -> bytes
+//! ```
+//!
+//! where `variant` is `"legacy"` or `"modern"`. Errors raise `ValueError`.
+
+use crate::{Legacy, Modern};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+enum PyVariant {
+    Legacy,
+    Modern,
+}
+
+fn variant(name: &str) -> PyResult<PyVariant> {
+    match name {
+        "legacy" => Ok(PyVariant::Legacy),
+        "modern" => Ok(PyVariant::Modern),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown PRS variant {:?}, expected \"legacy\" or \"modern\"",
+            name,
+        ))),
+    }
+}
+
+/// Compress a buffer.
+#[pyfunction]
+fn compress<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    variant: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let out = match self::variant(variant)? {
+        PyVariant::Legacy => crate::compress::<Legacy>(data),
+        PyVariant::Modern => crate::compress::<Modern>(data),
+    };
+    Ok(PyBytes::new(py, &out))
+}
+
+/// Decompress a PRS buffer.
+#[pyfunction]
+fn decompress<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    variant: &str,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let out = match self::variant(variant)? {
+        PyVariant::Legacy => crate::decompress::<Legacy>(data),
+        PyVariant::Modern => crate::decompress::<Modern>(data),
+    };
+    let out = out.map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyBytes::new(py, &out))
+}
+
+#[pymodule]
+fn ages_prs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compress, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! PRS encoding over `Read` sources.
+//!
+//! [`crate::PrsEncoder`](../struct.PrsEncoder.html) compresses whatever is
+//! written to it. The encoder in this module works the other way around:
+//! it wraps a `Read` of uncompressed data and compresses it as it is read
+//! from, which suits consumers that pull their data.
+
+use crate::Variant;
+use crate::compress::Compress;
+use crate::decompress::Status;
+
+use std::io::{self, Read};
+
+/// Size of the chunks read from the inner Read
+const IN_BUF_CAPACITY: usize = 8192;
+
+/// An IO source for reading a PRS stream compressed from a `Read`.
+///
+/// The stream is terminated once the inner Read reaches its end.
+pub struct PrsEncoder<R: Read, V: Variant> {
+    inner: R,
+    state: Compress<V>,
+    /// data read from `inner`, allocated on first use
+    in_buf: Box<[u8]>,
+    in_pos: usize,
+    in_len: usize,
+    /// whether `inner` has reached its end
+    eof: bool,
+}
+
+impl<R: Read, V: Variant> PrsEncoder<R, V> {
+    /// Wraps a Read source, initializing the encoder state.
+    pub fn new(inner: R) -> PrsEncoder<R, V> {
+        PrsEncoder {
+            inner,
+            state: Compress::new(),
+            in_buf: Box::default(),
+            in_pos: 0,
+            in_len: 0,
+            eof: false,
+        }
+    }
+
+    /// Reference the inner Read.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Mutably reference the inner Read.
+    ///
+    /// Reading from it directly will skip that data in the PRS stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner Read.
+    ///
+    /// Any data read from it but not yet compressed is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Start encoding a new PRS stream from `inner`, returning the previous
+    /// Read.
+    ///
+    /// Whatever remained of the previous PRS stream is discarded.
+    pub fn reset(&mut self, inner: R) -> R {
+        self.state.reset();
+        self.in_pos = 0;
+        self.in_len = 0;
+        self.eof = false;
+        std::mem::replace(&mut self.inner, inner)
+    }
+
+    /// Read the next chunk of the inner Read.
+    fn fill(&mut self) -> io::Result<()> {
+        if self.in_buf.is_empty() {
+            self.in_buf = vec![0; IN_BUF_CAPACITY].into_boxed_slice();
+        }
+
+        loop {
+            match self.inner.read(&mut self.in_buf) {
+                Ok(n) => {
+                    self.in_pos = 0;
+                    self.in_len = n;
+                    self.eof = n == 0;
+                    return Ok(());
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Read, V: Variant> Read for PrsEncoder<R, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if self.in_pos == self.in_len && !self.eof {
+                self.fill()?;
+            }
+
+            let total_in = self.state.total_in();
+            let total_out = self.state.total_out();
+            let status = if self.eof {
+                self.state.finish(buf)
+            } else {
+                self.state.run(&self.in_buf[self.in_pos..self.in_len], buf)
+            };
+            self.in_pos += (self.state.total_in() - total_in) as usize;
+            let produced = (self.state.total_out() - total_out) as usize;
+
+            if produced > 0 || status == Status::Done {
+                return Ok(produced);
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Re-encoding of existing PRS streams.
+
+use crate::compress::compress_terminated;
+use crate::{decompress, decompress_strict, DecompressError, Terminator, Variant};
+
+use alloc::vec::Vec;
+
+/// Settings for [`recompress`](fn.recompress.html).
+///
+/// The default accepts anything after the original stream and ends the new
+/// stream with just the end of stream command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecompressOptions {
+    terminator: Terminator,
+    strict: bool,
+}
+
+impl RecompressOptions {
+    /// Start from the default settings.
+    pub fn new() -> RecompressOptions {
+        RecompressOptions::default()
+    }
+
+    /// Set how the new stream is ended. See
+    /// [`Terminator`](struct.Terminator.html).
+    pub fn terminator(mut self, terminator: Terminator) -> RecompressOptions {
+        self.terminator = terminator;
+        self
+    }
+
+    /// Set whether the original must hold nothing but the stream and zero
+    /// padding, as checked by
+    /// [`decompress_strict`](fn.decompress_strict.html).
+    pub fn strict(mut self, strict: bool) -> RecompressOptions {
+        self.strict = strict;
+        self
+    }
+}
+
+/// A re-encoded PRS stream, returned by [`recompress`](fn.recompress.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecompressResult {
+    /// Length of the original buffer.
+    pub old_size: usize,
+    /// Length of the new stream.
+    pub new_size: usize,
+    /// The new stream.
+    pub data: Vec<u8>,
+}
+
+impl RecompressResult {
+    /// Whether the new stream is shorter than the original.
+    pub fn is_smaller(&self) -> bool {
+        self.new_size < self.old_size
+    }
+
+    /// Number of bytes the new stream saves over the original, negative if
+    /// it is longer.
+    pub fn saved(&self) -> i64 {
+        self.old_size as i64 - self.new_size as i64
+    }
+}
+
+/// Decompress `compressed` and compress the data again, returning the new
+/// stream along with the old and new sizes.
+///
+/// Streams written by other encoders often compress better with this one.
+/// The new stream decompresses to the same data, but check
+/// [`is_smaller`](struct.RecompressResult.html#method.is_smaller) before
+/// replacing the original, since it isn't always shorter. The old size is
+/// that of the whole buffer, including anything following the stream.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{decompress, recompress, Modern, RecompressOptions};
+///
+/// // a stream of nothing but literals, as a naive encoder would write
+/// let mut original = Vec::new();
+/// for chunk in [b'a'; 64].chunks(8) {
+///     original.push(0xFF);
+///     original.extend_from_slice(chunk);
+/// }
+/// original.extend_from_slice(&[0x02, 0x00, 0x00]);
+///
+/// let result = recompress::<Modern>(&original, RecompressOptions::new()).unwrap();
+/// assert!(result.is_smaller());
+/// assert_eq!(decompress::<Modern>(&result.data).unwrap(), [b'a'; 64]);
+/// ```
+pub fn recompress<V: Variant>(
+    compressed: &[u8],
+    options: RecompressOptions,
+) -> Result<RecompressResult, DecompressError> {
+    let data = if options.strict {
+        decompress_strict::<V>(compressed)?
+    } else {
+        decompress::<V>(compressed)?
+    };
+    let new = compress_terminated::<V>(&data, &options.terminator);
+    Ok(RecompressResult {
+        old_size: compressed.len(),
+        new_size: new.len(),
+        data: new,
+    })
+}
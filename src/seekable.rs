@@ -0,0 +1,382 @@
+//! Seekable container of independently compressed PRS blocks.
+//!
+//! A PRS stream can only be decoded from its start. For random access into
+//! large data, [`SeekablePrsWriter`](struct.SeekablePrsWriter.html) splits
+//! its input into blocks of a fixed uncompressed size and compresses each to
+//! its own PRS stream, then appends an index of the block sizes.
+//! [`SeekablePrsReader`](struct.SeekablePrsReader.html) reads the index and
+//! implements `Read + Seek` over the uncompressed data, decompressing only
+//! the block that holds the current position.
+//!
+//! The container is not understood by games; it is meant for archives kept
+//! within an application's own tooling. Its layout is:
+//!
+//! - the PRS blocks, back to back;
+//! - for each block, its compressed and uncompressed sizes as little endian
+//!   `u32`s;
+//! - the number of blocks as a little endian `u32`;
+//! - the magic bytes [`MAGIC`](constant.MAGIC.html).
+//!
+//! Since the index is found from the end, the container may follow other data
+//! in the same file.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+//!
+//! use ages_prs::Modern;
+//! use ages_prs::seekable::{SeekablePrsReader, SeekablePrsWriter};
+//!
+//! let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+//! let mut writer = SeekablePrsWriter::<_, Modern>::with_block_size(Vec::new(), 4096);
+//! writer.write_all(&data).unwrap();
+//! let container = writer.finish().unwrap();
+//!
+//! let mut reader = SeekablePrsReader::<_, Modern>::new(Cursor::new(container)).unwrap();
+//! let mut buf = [0; 16];
+//! reader.seek(SeekFrom::Start(50_000)).unwrap();
+//! reader.read_exact(&mut buf).unwrap();
+//! assert_eq!(&buf[..], &data[50_000..50_016]);
+//! ```
+
+use crate::{compress, decompress_into, Variant};
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Magic bytes ending a seekable container.
+pub const MAGIC: [u8; 4] = *b"PRSk";
+
+/// Uncompressed size of each block unless chosen otherwise.
+pub const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Size of the block count and magic at the end of the container.
+const FOOTER_LEN: u64 = 8;
+
+/// Size of one index entry.
+const ENTRY_LEN: u64 = 8;
+
+/// An IO sink writing a seekable container.
+///
+/// Data is compressed a block at a time as blocks fill up. The final block
+/// and the index are written by [`finish`](#method.finish); dropping the
+/// writer leaves the container without an index.
+pub struct SeekablePrsWriter<W: Write, V: Variant> {
+    inner: W,
+    block_size: usize,
+    /// uncompressed data of the block being filled
+    block: Vec<u8>,
+    /// compressed and uncompressed size of each block written
+    index: Vec<(u32, u32)>,
+    _pd: core::marker::PhantomData<V>,
+}
+
+impl<W: Write, V: Variant> SeekablePrsWriter<W, V> {
+    /// Wraps a Write sink, using blocks of
+    /// [`DEFAULT_BLOCK_SIZE`](constant.DEFAULT_BLOCK_SIZE.html).
+    pub fn new(inner: W) -> SeekablePrsWriter<W, V> {
+        SeekablePrsWriter::with_block_size(inner, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Wraps a Write sink, compressing every `block_size` bytes of input as
+    /// a separate block.
+    ///
+    /// Smaller blocks make seeking cheaper but compress worse, since matches
+    /// can't reach into other blocks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero or doesn't fit in a `u32`.
+    pub fn with_block_size(inner: W, block_size: usize) -> SeekablePrsWriter<W, V> {
+        assert!(block_size > 0, "block size must not be zero");
+        assert!(block_size <= u32::MAX as usize, "block size too large");
+        SeekablePrsWriter {
+            inner,
+            block_size,
+            block: Vec::new(),
+            index: Vec::new(),
+            _pd: core::marker::PhantomData,
+        }
+    }
+
+    /// Reference the inner Write.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Mutably reference the inner Write.
+    ///
+    /// Writing to it directly will corrupt the container.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Write the final block and the index, returning the inner Write.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+
+        let mut tail = Vec::with_capacity(
+            self.index.len() * ENTRY_LEN as usize + FOOTER_LEN as usize
+        );
+        for &(compressed, uncompressed) in &self.index {
+            tail.extend_from_slice(&compressed.to_le_bytes());
+            tail.extend_from_slice(&uncompressed.to_le_bytes());
+        }
+        let count = u32::try_from(self.index.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "too many blocks")
+        })?;
+        tail.extend_from_slice(&count.to_le_bytes());
+        tail.extend_from_slice(&MAGIC);
+        self.inner.write_all(&tail)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let compressed = compress::<V>(&self.block);
+        self.write_compressed(&compressed, self.block.len())?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Write a block compressed from `len` bytes.
+    fn write_compressed(&mut self, compressed: &[u8], len: usize) -> io::Result<()> {
+        let compressed_len = u32::try_from(compressed.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "block too large")
+        })?;
+        self.inner.write_all(compressed)?;
+        self.index.push((compressed_len, len as u32));
+        Ok(())
+    }
+}
+
+impl<W: Write, V: Variant> Write for SeekablePrsWriter<W, V> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = core::cmp::min(buf.len(), self.block_size - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+        if self.block.len() == self.block_size {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the inner Write. The block being filled is not compressed
+    /// early, since that would shorten it.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Compress `src` into a seekable container, compressing its blocks in
+/// parallel on the rayon thread pool. Requires the `rayon` feature.
+///
+/// The result is the same as writing `src` to a
+/// [`SeekablePrsWriter`](struct.SeekablePrsWriter.html) with the same block
+/// size.
+///
+/// # Panics
+///
+/// Panics if `block_size` is zero or doesn't fit in a `u32`.
+#[cfg(feature = "rayon")]
+pub fn compress_parallel<V: Variant>(src: &[u8], block_size: usize) -> Vec<u8> {
+    use rayon::prelude::*;
+
+    let mut writer = SeekablePrsWriter::<_, V>::with_block_size(Vec::new(), block_size);
+    let blocks: Vec<Vec<u8>> = src.par_chunks(block_size)
+        .map(compress::<V>)
+        .collect();
+    for (block, chunk) in blocks.iter().zip(src.chunks(block_size)) {
+        writer.write_compressed(block, chunk.len())
+            .expect("block compressed beyond the container's size limit");
+    }
+    writer.finish().expect("writing to a Vec failed")
+}
+
+/// Location of a block in the container.
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    /// offset of the PRS stream in the inner reader
+    offset: u64,
+    compressed_len: u32,
+    /// uncompressed position of the block's first byte
+    start: u64,
+    len: u32,
+}
+
+/// An IO source reading the uncompressed data of a seekable container.
+pub struct SeekablePrsReader<R: Read + Seek, V: Variant> {
+    inner: R,
+    blocks: Vec<Block>,
+    len: u64,
+    /// uncompressed position
+    pos: u64,
+    /// index and decompressed contents of the block last read
+    cached: Option<usize>,
+    data: Vec<u8>,
+    compressed: Vec<u8>,
+    _pd: core::marker::PhantomData<V>,
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<R: Read + Seek, V: Variant> SeekablePrsReader<R, V> {
+    /// Wraps a Read + Seek source ending in a seekable container, reading
+    /// its index.
+    ///
+    /// Fails with `InvalidData` if no valid index is found.
+    pub fn new(mut inner: R) -> io::Result<SeekablePrsReader<R, V>> {
+        let end = inner.seek(SeekFrom::End(0))?;
+        if end < FOOTER_LEN {
+            return Err(invalid("too short for a seekable PRS container"));
+        }
+        let mut footer = [0; FOOTER_LEN as usize];
+        inner.seek(SeekFrom::Start(end - FOOTER_LEN))?;
+        inner.read_exact(&mut footer)?;
+        if footer[4..] != MAGIC {
+            return Err(invalid("missing seekable PRS container magic"));
+        }
+
+        let count = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+        let index_len = count as u64 * ENTRY_LEN;
+        let index_start = (end - FOOTER_LEN)
+            .checked_sub(index_len)
+            .ok_or_else(|| invalid("seekable PRS index out of bounds"))?;
+        let mut index = vec![0; index_len as usize];
+        inner.seek(SeekFrom::Start(index_start))?;
+        inner.read_exact(&mut index)?;
+
+        let mut blocks = Vec::with_capacity(count as usize);
+        let mut offset = 0;
+        let mut start = 0;
+        for entry in index.chunks_exact(ENTRY_LEN as usize) {
+            let compressed_len = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let len = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            blocks.push(Block { offset, compressed_len, start, len });
+            offset += compressed_len as u64;
+            start += len as u64;
+        }
+
+        // the blocks end where the index starts
+        let base = index_start
+            .checked_sub(offset)
+            .ok_or_else(|| invalid("seekable PRS blocks out of bounds"))?;
+        for block in &mut blocks {
+            block.offset += base;
+        }
+
+        Ok(SeekablePrsReader {
+            inner,
+            blocks,
+            len: start,
+            pos: 0,
+            cached: None,
+            data: Vec::new(),
+            compressed: Vec::new(),
+            _pd: core::marker::PhantomData,
+        })
+    }
+
+    /// Total length of the uncompressed data.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the uncompressed data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of blocks in the container.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Reference the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Mutably reference the inner reader.
+    ///
+    /// Every block is read after seeking to it, so moving its position does
+    /// not affect this reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decompress block `i` into `data` unless it is already there.
+    fn load(&mut self, i: usize) -> io::Result<()> {
+        if self.cached == Some(i) {
+            return Ok(());
+        }
+        self.cached = None;
+
+        let block = self.blocks[i];
+        self.compressed.resize(block.compressed_len as usize, 0);
+        self.inner.seek(SeekFrom::Start(block.offset))?;
+        self.inner.read_exact(&mut self.compressed)?;
+
+        self.data.resize(block.len as usize, 0);
+        let len = decompress_into::<V>(&self.compressed, &mut self.data)?;
+        if len != self.data.len() {
+            return Err(invalid("seekable PRS block shorter than its index entry"));
+        }
+        self.cached = Some(i);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek, V: Variant> Read for SeekablePrsReader<R, V> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.len {
+            return Ok(0);
+        }
+
+        // the last block starting at or before the position; empty blocks
+        // are skipped by taking the last of equal starts
+        let i = self.blocks.partition_point(|b| b.start <= self.pos) - 1;
+        self.load(i)?;
+
+        let offset = (self.pos - self.blocks[i].start) as usize;
+        let available = &self.data[offset..];
+        let len = core::cmp::min(available.len(), buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek, V: Variant> Seek for SeekablePrsReader<R, V> {
+    /// Seek in the uncompressed data. Seeking past the end is allowed, and
+    /// reads there return nothing.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.pos = pos;
+                return Ok(pos);
+            },
+            SeekFrom::End(offset) => (self.len, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            },
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Vectorized match length comparison for the LZ77 matcher, enabled by the
+//! `simd` feature.
+//!
+//! SSE2 and NEON are part of the x86_64 and aarch64 baselines and are always
+//! used there. AVX2 is used when the CPU supports it, which is detected at
+//! runtime and so needs the `std` feature as well. Other targets compare a
+//! word at a time.
+
+/// Length of the common prefix of `a` and `b`, up to `max`.
+pub(crate) fn match_length(a: &[u8], b: &[u8], max: usize) -> usize {
+    let max = core::cmp::min(max, core::cmp::min(a.len(), b.len()));
+    let (a, b) = (&a[..max], &b[..max]);
+
+    #[cfg(all(target_arch = "x86_64", feature = "std"))]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 support was just checked
+            return unsafe { x86::match_length_avx2(a, b) };
+        }
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        x86::match_length_sse2(a, b)
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        neon::match_length(a, b)
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        match_length_words(a, b, 0)
+    }
+}
+
+/// Finish comparing equal-length `a` and `b` from `start` a word at a time.
+fn match_length_words(a: &[u8], b: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while i + 8 <= a.len() {
+        let mut x = [0; 8];
+        let mut y = [0; 8];
+        x.copy_from_slice(&a[i..i + 8]);
+        y.copy_from_slice(&b[i..i + 8]);
+        let diff = u64::from_le_bytes(x) ^ u64::from_le_bytes(y);
+        if diff != 0 {
+            return i + (diff.trailing_zeros() / 8) as usize;
+        }
+        i += 8;
+    }
+    while i < a.len() && a[i] == b[i] {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use core::arch::x86_64::*;
+
+    pub(super) fn match_length_sse2(a: &[u8], b: &[u8]) -> usize {
+        let mut i = 0;
+        while i + 16 <= a.len() {
+            // SAFETY: SSE2 is part of the x86_64 baseline and both loads are
+            // in bounds
+            let mask = unsafe {
+                let x = _mm_loadu_si128(a.as_ptr().add(i) as *const __m128i);
+                let y = _mm_loadu_si128(b.as_ptr().add(i) as *const __m128i);
+                _mm_movemask_epi8(_mm_cmpeq_epi8(x, y)) as u32
+            };
+            if mask != 0xFFFF {
+                return i + (!mask).trailing_zeros() as usize;
+            }
+            i += 16;
+        }
+        super::match_length_words(a, b, i)
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[cfg(feature = "std")]
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn match_length_avx2(a: &[u8], b: &[u8]) -> usize {
+        let mut i = 0;
+        while i + 32 <= a.len() {
+            let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+            let y = _mm256_loadu_si256(b.as_ptr().add(i) as *const __m256i);
+            let mask = _mm256_movemask_epi8(_mm256_cmpeq_epi8(x, y)) as u32;
+            if mask != u32::MAX {
+                return i + (!mask).trailing_zeros() as usize;
+            }
+            i += 32;
+        }
+        match_length_sse2(a.get_unchecked(i..), b.get_unchecked(i..)) + i
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use core::arch::aarch64::*;
+
+    pub(super) fn match_length(a: &[u8], b: &[u8]) -> usize {
+        let mut i = 0;
+        while i + 16 <= a.len() {
+            // SAFETY: NEON is part of the aarch64 baseline and both loads are
+            // in bounds
+            let all_equal = unsafe {
+                let x = vld1q_u8(a.as_ptr().add(i));
+                let y = vld1q_u8(b.as_ptr().add(i));
+                vminvq_u8(vceqq_u8(x, y)) == 0xFF
+            };
+            if !all_equal {
+                break;
+            }
+            i += 16;
+        }
+        super::match_length_words(a, b, i)
+    }
+}
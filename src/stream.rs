@@ -0,0 +1,113 @@
+//! One-shot compression and decompression between a Read and a Write.
+
+use crate::{Decompress, DecompressError, PrsEncoder, Status, Variant};
+
+use std::io::{self, Read, Write};
+
+/// Size of the buffers `decompress_stream` passes data through.
+const BUF_SIZE: usize = 8192;
+
+/// Amounts of data moved by [`compress_stream`](fn.compress_stream.html)
+/// and [`decompress_stream`](fn.decompress_stream.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct StreamStats {
+    /// Number of bytes read from the reader.
+    pub input_len: u64,
+    /// Number of bytes written to the writer.
+    pub output_len: u64,
+}
+
+/// Compress everything `reader` yields into a PRS stream of variant `V`
+/// written to `writer`, returning how much was read and written.
+///
+/// Data passes through in bounded chunks, so memory use doesn't depend on the
+/// size of the input. The stream is finished and `writer` flushed before
+/// returning.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress_stream, decompress, Modern};
+///
+/// let mut compressed = Vec::new();
+/// let stats = compress_stream::<Modern>(&b"Hello Hello Hello "[..], &mut compressed).unwrap();
+/// assert_eq!(stats.input_len, 18);
+/// assert_eq!(stats.output_len, compressed.len() as u64);
+/// assert_eq!(&decompress::<Modern>(&compressed).unwrap()[..], b"Hello Hello Hello ");
+/// ```
+pub fn compress_stream<V: Variant>(
+    mut reader: impl Read,
+    writer: impl Write,
+) -> io::Result<StreamStats> {
+    let mut encoder = PrsEncoder::<_, V>::new(writer);
+    io::copy(&mut reader, &mut encoder)?;
+    let (mut writer, stats) = encoder.finish().map_err(|e| e.into_error())?;
+    writer.flush()?;
+    Ok(StreamStats {
+        input_len: stats.input_len,
+        output_len: stats.output_len,
+    })
+}
+
+/// Decompress the PRS stream of variant `V` read from `reader` into
+/// `writer`, returning how much of the stream was read and how much was
+/// written.
+///
+/// Data passes through in bounded chunks, so memory use doesn't depend on the
+/// size of the stream. `reader` is read in chunks as well, so it may be read
+/// past the end of the stream; `input_len` counts only the bytes of the
+/// stream. Failures of `reader` and `writer` are reported as
+/// `DecompressError::Io`. `writer` is flushed before returning.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress_stream, Legacy};
+///
+/// let compressed = compress::<Legacy>(b"Hello Hello Hello ");
+/// let mut out = Vec::new();
+/// let stats = decompress_stream::<Legacy>(&compressed[..], &mut out).unwrap();
+/// assert_eq!(stats.input_len, compressed.len() as u64);
+/// assert_eq!(&out[..], b"Hello Hello Hello ");
+/// ```
+pub fn decompress_stream<V: Variant>(
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<StreamStats, DecompressError> {
+    let mut state = Decompress::<V>::new();
+    let mut input = [0; BUF_SIZE];
+    let mut output = [0; BUF_SIZE];
+    let mut start = 0;
+    let mut end = 0;
+    loop {
+        let (total_in, total_out) = (state.total_in(), state.total_out());
+        let status = state.run(&input[start..end], &mut output)?;
+        start += (state.total_in() - total_in) as usize;
+        let produced = (state.total_out() - total_out) as usize;
+        writer.write_all(&output[..produced])?;
+
+        match status {
+            Status::Done => break,
+            Status::NeedsOutput => {},
+            Status::NeedsInput => {
+                end = loop {
+                    match reader.read(&mut input) {
+                        Ok(n) => break n,
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                        Err(e) => return Err(e.into()),
+                    }
+                };
+                start = 0;
+                if end == 0 {
+                    return Err(state.eof_error());
+                }
+            },
+        }
+    }
+    writer.flush()?;
+    Ok(StreamStats {
+        input_len: state.total_in(),
+        output_len: state.total_out(),
+    })
+}
@@ -1,14 +1,98 @@
 use crate::{
+    BigEndian,
+    decompress_exact,
+    decompress_tolerant,
+    InvalidPointerMode,
+    decompress_strict,
+    decompress_to_buf,
+    compress_to_buf,
+    decompress_stream,
+    compress_stream,
+    Terminator,
+    analyze,
+    dump,
+    records,
+    CommandForm,
+    Command,
+    CommandIter,
+    CommandWriter,
+    compress_bound,
+    estimate_compressed_size,
+    train_dictionary,
+    transcode,
+    compress_into,
+    Compress,
+    Decompress,
+    DecoderState,
+    Status,
+    CompressError,
+    decompress_into,
+    decompress_lossy,
+    decompress_prefix,
+    decompress_with,
+    decompress_with_limit,
+    decompressed_size,
+    validate,
+    DecompressError,
     PrsEncoder,
+    PrsEncoderBuilder,
     PrsDecoder,
     Variant,
     Legacy,
     Modern,
 };
 
-use std::io::{Cursor, Read, Write};
+use std::io::{BufRead, BufReader, Cursor, IoSlice, Read, Seek, SeekFrom, Write};
 
-static TEST_DATA: &'static [u8] = include_bytes!("./test.txt");
+static TEST_DATA: &[u8] = include_bytes!("./test.txt");
+
+/// Deterministic incompressible filler for worst-case tests.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state: u32 = 0x1234_5678;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+/// Write sink accepting at most a few bytes per call.
+#[derive(Debug)]
+struct TrickleWriter(Vec<u8>);
+
+impl Write for TrickleWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = std::cmp::min(buf.len(), 3);
+        self.0.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write sink failing with `WouldBlock` while `blocked` is set.
+#[derive(Debug)]
+struct BlockingWriter {
+    out: Vec<u8>,
+    blocked: bool,
+}
+
+impl Write for BlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.blocked {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        self.out.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
 fn compress<V, B>(buf: B) -> Vec<u8>
 where
@@ -72,3 +156,2251 @@ fn test_compress_decompress_modern() {
     assert!(compressed.len() < data.len());
     assert!(decompressed == data);
 }
+
+#[test]
+fn test_compress_bound() {
+    assert_eq!(compress::<Legacy, _>(&[]).len(), compress_bound::<Legacy>(0));
+
+    for &len in &[1, 7, 8, 9, 100, 4096, 65536] {
+        let data = noise(len);
+        assert!(compress::<Legacy, _>(&data).len() <= compress_bound::<Legacy>(len));
+        assert!(compress::<Modern, _>(&data).len() <= compress_bound::<Modern>(len));
+    }
+}
+
+#[test]
+fn test_decompress_into() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+
+    assert_eq!(
+        decompressed_size::<Modern>(&compressed).unwrap(),
+        TEST_DATA.len() as u64,
+    );
+
+    let mut dst = vec![0; TEST_DATA.len()];
+    let len = decompress_into::<Modern>(&compressed, &mut dst).unwrap();
+    assert_eq!(len, TEST_DATA.len());
+    assert!(dst == TEST_DATA);
+
+    let mut small = vec![0; TEST_DATA.len() - 1];
+    match decompress_into::<Modern>(&compressed, &mut small) {
+        Err(DecompressError::BufferTooSmall { .. }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_decompress_with() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+
+    let mut out = Vec::new();
+    let mut chunks = 0;
+    let len = decompress_with::<Modern, _>(&compressed, |chunk| {
+        assert!(!chunk.is_empty() && chunk.len() <= 8192);
+        out.extend_from_slice(chunk);
+        chunks += 1;
+    }).unwrap();
+    assert_eq!(len, TEST_DATA.len() as u64);
+    assert!(out == TEST_DATA);
+    assert!(chunks > 1);
+
+    // output before the error is still handed over
+    let mut out = Vec::new();
+    let truncated = &compressed[..compressed.len() / 2];
+    match decompress_with::<Modern, _>(truncated, |chunk| out.extend_from_slice(chunk)) {
+        Err(DecompressError::Eof { output_len, .. }) => {
+            assert!(out.len() as u64 <= output_len);
+            assert!(!out.is_empty());
+            assert!(out[..] == TEST_DATA[..out.len()]);
+        },
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_to_buf() {
+    let mut compressed = b"stale".to_vec();
+    compress_to_buf::<Modern>(TEST_DATA, &mut compressed);
+    assert!(compressed == crate::compress::<Modern>(TEST_DATA));
+
+    let mut out = b"stale".to_vec();
+    decompress_to_buf::<Modern>(&compressed, &mut out).unwrap();
+    assert!(out == TEST_DATA);
+
+    // smaller entries reuse the allocations
+    let (comp_ptr, out_ptr) = (compressed.as_ptr(), out.as_ptr());
+    for data in &[&TEST_DATA[..100], b"", &TEST_DATA[1000..5000]] {
+        compress_to_buf::<Modern>(data, &mut compressed);
+        assert_eq!(compressed.as_ptr(), comp_ptr);
+        decompress_to_buf::<Modern>(&compressed, &mut out).unwrap();
+        assert_eq!(out.as_ptr(), out_ptr);
+        assert!(out == *data);
+    }
+
+    match decompress_to_buf::<Modern>(&compressed[..compressed.len() - 3], &mut out) {
+        Err(DecompressError::Eof { output_len, .. }) => {
+            assert_eq!(out.len() as u64, output_len);
+        },
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_batch() {
+    use crate::PrsBatch;
+
+    let files: Vec<&[u8]> = vec![TEST_DATA, b"", &TEST_DATA[..100], b"a", &TEST_DATA[5000..]];
+    let mut batch = PrsBatch::<Legacy>::default();
+    let mut compressed = Vec::new();
+    for file in &files {
+        let stream = batch.compress_one(file);
+        assert!(*stream == crate::compress::<Legacy>(file)[..]);
+        compressed.push(stream.to_vec());
+    }
+    for (stream, file) in compressed.iter().zip(&files).rev() {
+        assert!(batch.decompress_one(stream).unwrap() == *file);
+    }
+
+    assert!(batch.decompress_one(&compressed[0][..10]).is_err());
+    assert!(batch.decompress_one(&compressed[3]).unwrap() == b"a");
+
+    // resetting the matcher while it still holds input drops that input
+    let mut state = Compress::<Legacy>::new();
+    let mut buf = vec![0; compress_bound::<Legacy>(TEST_DATA.len())];
+    state.run(b"discarded", &mut buf);
+    state.reset();
+    state.run(TEST_DATA, &mut buf);
+    let len = state.total_out() as usize;
+    assert_eq!(state.finish(&mut buf[len..]), Status::Done);
+    assert!(buf[..state.total_out() as usize] == compressed[0][..]);
+}
+
+#[test]
+fn test_recompress() {
+    use crate::{recompress, RecompressOptions};
+
+    // a stream of literals only
+    let mut writer = CommandWriter::<_, Modern>::new(Vec::new());
+    for &b in &TEST_DATA[..5000] {
+        writer.put_literal(b).unwrap();
+    }
+    let mut original = writer.finish().unwrap();
+
+    let result = recompress::<Modern>(&original, RecompressOptions::new()).unwrap();
+    assert_eq!(result.old_size, original.len());
+    assert_eq!(result.new_size, result.data.len());
+    assert!(result.is_smaller());
+    assert_eq!(result.saved(), (original.len() - result.data.len()) as i64);
+    assert!(result.data == compress::<Modern, _>(&TEST_DATA[..5000]));
+
+    // already compressed by this encoder
+    let result = recompress::<Modern>(&result.data, RecompressOptions::new()).unwrap();
+    assert!(!result.is_smaller());
+    assert_eq!(result.saved(), 0);
+
+    let options = RecompressOptions::new().terminator(Terminator::new().align(16));
+    let result = recompress::<Modern>(&original, options).unwrap();
+    assert_eq!(result.new_size % 16, 0);
+    assert!(crate::decompress::<Modern>(&result.data).unwrap() == TEST_DATA[..5000]);
+
+    original.push(1);
+    assert!(recompress::<Modern>(&original, RecompressOptions::new()).is_ok());
+    match recompress::<Modern>(&original, RecompressOptions::new().strict(true)) {
+        Err(DecompressError::TrailingData { .. }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+    assert!(recompress::<Modern>(&original[..100], RecompressOptions::new()).is_err());
+}
+
+#[test]
+fn test_encoder_abort() {
+    // leaves the last cmd byte partly used, with either matcher
+    let data = noise(100_003);
+
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    encoder.write_all(&data).unwrap();
+    let (written, buffered) = encoder.into_parts();
+    assert!(!written.is_empty());
+    assert!(!buffered.is_empty());
+
+    // the written part is a prefix of the finished stream
+    let finished = compress::<Modern, _>(&data);
+    assert!(finished.len() > written.len() + buffered.len());
+    assert!(finished[..written.len()] == written[..]);
+
+    // aborting doesn't write an EOF command
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    encoder.write_all(b"abc").unwrap();
+    assert!(encoder.abort().is_empty());
+}
+
+#[test]
+fn test_into_inner_retry() {
+    let data = noise(1000);
+    let writer = BlockingWriter { out: Vec::new(), blocked: false };
+    let mut encoder = PrsEncoder::<_, Modern>::new(writer);
+    encoder.write_all(&data).unwrap();
+    encoder.get_mut().blocked = true;
+
+    let err = encoder.into_inner().unwrap_err();
+    assert_eq!(err.error().kind(), std::io::ErrorKind::WouldBlock);
+    let (_, mut encoder) = err.into_parts();
+    encoder.get_mut().blocked = false;
+    let writer = encoder.into_inner().unwrap();
+    assert!(writer.out == compress::<Modern, _>(&data));
+}
+
+#[test]
+fn test_encoder_try_finish() {
+    let data = noise(1000);
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    encoder.write_all(&data).unwrap();
+    encoder.try_finish().unwrap();
+    encoder.try_finish().unwrap();
+    assert_eq!(
+        encoder.write(b"more").unwrap_err().kind(),
+        std::io::ErrorKind::InvalidInput,
+    );
+    assert!(encoder.into_inner().unwrap() == compress::<Modern, _>(&data));
+
+    // without finishing on drop, the stream is left unterminated
+    let mut out = Vec::new();
+    {
+        let mut encoder = PrsEncoder::<_, Modern>::new(&mut out);
+        encoder.set_finish_on_drop(false);
+        encoder.write_all(&data).unwrap();
+    }
+    assert!(crate::decompress::<Modern>(&out).is_err());
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = PrsEncoder::<_, Modern>::new(&mut out);
+        encoder.write_all(&data).unwrap();
+    }
+    assert!(crate::decompress::<Modern>(&out).unwrap() == data);
+}
+
+#[test]
+fn test_encoder_would_block() {
+    // writes large enough that the matcher encodes them right away
+    let data = noise(200_000);
+    let (head, tail) = data.split_at(100_000);
+    let writer = BlockingWriter { out: Vec::new(), blocked: true };
+    let mut encoder = PrsEncoder::<_, Modern>::new(writer);
+
+    // the first write is buffered, then the encoder refuses more input
+    // until the buffered data can be written out
+    assert_eq!(encoder.write(head).unwrap(), head.len());
+    let err = encoder.write(tail).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+    assert_eq!(encoder.flush().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+
+    encoder.get_mut().blocked = false;
+    encoder.write_all(tail).unwrap();
+    let writer = encoder.into_inner().unwrap();
+
+    // the same stream as the same writes never blocking
+    let mut expected = PrsEncoder::<_, Modern>::new(Vec::new());
+    expected.write_all(head).unwrap();
+    expected.write_all(tail).unwrap();
+    assert!(writer.out == expected.into_inner().unwrap());
+}
+
+#[test]
+fn test_encoder_pending_bytes() {
+    let data = noise(200_000);
+    let writer = BlockingWriter { out: Vec::new(), blocked: true };
+    let mut encoder = PrsEncoder::<_, Modern>::new(writer);
+    assert_eq!(encoder.pending_bytes(), 0);
+    assert_eq!(encoder.buffered_command_bits(), 0);
+
+    // large enough that the matcher encodes it right away
+    encoder.write_all(&data[..100_000]).unwrap();
+    assert!(encoder.pending_bytes() > 0);
+    assert_eq!(encoder.total_out(), 0);
+
+    // once unblocked, everything up to the current command byte goes out
+    encoder.get_mut().blocked = false;
+    encoder.flush().unwrap();
+    assert!(encoder.buffered_command_bits() < 8);
+    assert_eq!(encoder.pending_bytes() == 0, encoder.buffered_command_bits() == 0);
+    let written = encoder.total_out();
+
+    encoder.try_finish().unwrap();
+    assert_eq!(encoder.pending_bytes(), 0);
+    assert_eq!(encoder.buffered_command_bits(), 0);
+    assert!(encoder.total_out() > written);
+}
+
+#[test]
+fn test_encoder_flush_sync() {
+    let messages: Vec<&[u8]> = TEST_DATA.chunks(700).take(4).collect();
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+
+    let mut sent = Vec::new();
+    for message in &messages {
+        encoder.write_all(message).unwrap();
+        encoder.flush_sync().unwrap();
+        sent.extend_from_slice(message);
+
+        // everything sent so far decodes, short of the end of stream
+        let (out, err) = decompress_lossy::<Modern>(encoder.get_ref());
+        assert!(out == sent);
+        assert!(matches!(err, Some(DecompressError::Eof { .. })));
+    }
+
+    encoder.write_all(b"goodbye").unwrap();
+    sent.extend_from_slice(b"goodbye");
+    let compressed = encoder.into_inner().unwrap();
+    assert!(crate::decompress::<Modern>(&compressed).unwrap() == sent);
+
+    // one literal leaves 7 cmd bits, so 7 bytes are needed to end the stream
+    let mut encoder = PrsEncoder::<_, Legacy>::new(Vec::new());
+    encoder.write_all(b"a").unwrap();
+    encoder.flush_sync().unwrap();
+    assert_eq!(encoder.get_ref().len(), 2);
+    encoder.write_all(b"aaaaaa").unwrap();
+    let err = encoder.try_finish().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    encoder.write_all(b"aaaaaaaa").unwrap();
+    let compressed = encoder.into_inner().unwrap();
+    assert!(crate::decompress::<Legacy>(&compressed).unwrap() == [b'a'; 15]);
+}
+
+#[test]
+fn test_codec() {
+    use crate::codec::{Codec, PrsCompress, PrsDecompress};
+
+    let codecs: Vec<Box<dyn PrsDecompress>> = vec![
+        Box::new(Codec::<Legacy>::new()),
+        Box::new(Codec::<Modern>::new()),
+    ];
+    let compressed = [
+        Codec::<Legacy>::new().compress(TEST_DATA),
+        Codec::<Modern>::new().compress(TEST_DATA),
+    ];
+    for (codec, compressed) in codecs.iter().zip(&compressed) {
+        assert!(codec.decompress(compressed).unwrap() == TEST_DATA);
+    }
+
+    let limited = Codec::<Modern>::with_limit(100);
+    assert!(matches!(
+        limited.decompress(&compressed[1]),
+        Err(DecompressError::LimitExceeded { .. })
+    ));
+    let mut out = Vec::new();
+    assert!(limited.decoder(Box::new(&compressed[1][..])).read_to_end(&mut out).is_err());
+}
+
+#[test]
+fn test_codec_streams() {
+    use crate::codec::{Codec, PrsCompress, PrsDecompress, Raw};
+
+    trait Both: PrsCompress + PrsDecompress {}
+    impl<T: PrsCompress + PrsDecompress> Both for T {}
+
+    let mut codecs: Vec<Box<dyn Both>> = Vec::new();
+    codecs.push(Box::new(Codec::<Legacy>::new()));
+    codecs.push(Box::new(Codec::<Modern>::new()));
+    codecs.push(Box::new(Raw));
+    #[cfg(feature = "lz")]
+    codecs.push(Box::new(crate::codec::Lz01));
+    #[cfg(feature = "cnx")]
+    codecs.push(Box::new(crate::codec::Cnx { extension: *b"txt" }));
+
+    for codec in &codecs {
+        let mut compressed = Vec::new();
+        let mut encoder = codec.encoder(Box::new(&mut compressed));
+        for chunk in TEST_DATA.chunks(1000) {
+            encoder.write_all(chunk).unwrap();
+        }
+        encoder.finish().unwrap();
+        assert!(compressed == codec.compress(TEST_DATA));
+
+        let mut decoder = codec.decoder(Box::new(&compressed[..]));
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        assert!(out == TEST_DATA);
+        assert!(codec.decompress(&compressed).unwrap() == TEST_DATA);
+    }
+}
+
+#[test]
+fn test_conformance() {
+    use crate::conformance::{run, Fixture};
+
+    let compressed = compress::<Legacy, _>(TEST_DATA);
+    let mut wrong = TEST_DATA.to_vec();
+    wrong[5000] ^= 1;
+    let fixtures = vec![
+        Fixture::new("good", &compressed, TEST_DATA),
+        Fixture::new("wrong", &compressed, &wrong),
+        Fixture::new("truncated", &compressed[..100], TEST_DATA),
+    ];
+
+    let report = run::<Legacy, _>(fixtures.clone());
+    assert_eq!(report.results.len(), 3);
+    assert_eq!(report.passed(), 1);
+    assert!(!report.all_passed());
+    assert!(report.results[0].passed());
+
+    let wrong = &report.results[1];
+    assert_eq!(wrong.mismatch.unwrap().output_len, 5000);
+    assert!(wrong.error.is_none());
+    let truncated = &report.results[2];
+    assert!(truncated.mismatch.unwrap().command.is_none());
+    assert!(matches!(truncated.error, Some(DecompressError::Eof { .. })));
+
+    let listing = report.to_string();
+    assert!(listing.starts_with("PASS good\nFAIL wrong: output differs at 5000"));
+    assert!(listing.ends_with("1 of 3 passed"));
+
+    assert!(run::<Legacy, _>(fixtures.into_iter().take(1)).all_passed());
+}
+
+#[test]
+fn test_diff() {
+    use crate::diff::{diff_reference, diff_variants};
+
+    let mut writer = CommandWriter::<_, Legacy>::new(Vec::new());
+    for &b in b"abcdefgh" {
+        writer.put_literal(b).unwrap();
+    }
+    writer.put_pointer(8, 4).unwrap();
+    writer.put_pointer(300, 2).unwrap_err();
+    writer.put_pointer(8, 12).unwrap();
+    let compressed = writer.finish().unwrap();
+
+    let divergence = diff_variants::<Legacy, Modern>(&compressed).unwrap();
+    assert_eq!(divergence.output_len, 12);
+    assert_eq!(divergence.first, Some(Command::Pointer { distance: 8, length: 12 }));
+    assert_eq!(divergence.second, Some(Command::Pointer { distance: 8, length: 21 }));
+    assert_eq!(diff_variants::<Legacy, Legacy>(&compressed), None);
+    assert_eq!(diff_variants::<Legacy, Modern>(&compress::<Modern, _>(b"abcabc")), None);
+
+    let data = crate::decompress::<Legacy>(&compressed).unwrap();
+    assert_eq!(diff_reference::<Legacy>(&compressed, &data), None);
+
+    let mut reference = data.clone();
+    reference[14] ^= 1;
+    let mismatch = diff_reference::<Legacy>(&compressed, &reference).unwrap();
+    assert_eq!(mismatch.offset, compressed.len() - 5);
+    assert_eq!(mismatch.output_len, 14);
+    assert_eq!(mismatch.command, Some(Command::Pointer { distance: 8, length: 12 }));
+
+    // output running short of the reference
+    reference[14] ^= 1;
+    reference.push(0);
+    let mismatch = diff_reference::<Legacy>(&compressed, &reference).unwrap();
+    assert_eq!(mismatch.output_len, 24);
+    assert_eq!(mismatch.command, Some(Command::Eof));
+    let mismatch = diff_reference::<Legacy>(&compressed[..4], &reference).unwrap();
+    assert_eq!(mismatch.output_len, 3);
+    assert_eq!(mismatch.command, None);
+}
+
+#[test]
+fn test_estimate_compressed_size() {
+    for data in [&b""[..], b"a", TEST_DATA, &noise(20_000)] {
+        assert_eq!(
+            estimate_compressed_size::<Legacy>(data),
+            compress::<Legacy, _>(data).len(),
+        );
+        assert_eq!(
+            estimate_compressed_size::<Modern>(data),
+            compress::<Modern, _>(data).len(),
+        );
+    }
+}
+
+#[test]
+fn test_max_ratio() {
+    let zeros = vec![0; 1 << 20];
+    let compressed = compress::<Legacy, _>(&zeros);
+
+    let mut decoder = PrsDecoder::<_, Legacy>::new(&compressed[..]);
+    decoder.set_max_ratio(Some(10));
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    let err = DecompressError::from(err);
+    assert!(matches!(err, DecompressError::RatioExceeded { .. }));
+
+    let mut decoder = PrsDecoder::<_, Legacy>::new(&compressed[..]);
+    decoder.set_max_ratio(Some(100));
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == zeros);
+
+    // short streams get some slack
+    let mut decoder = PrsDecoder::<_, Legacy>::new(&compressed[..40]);
+    decoder.set_max_ratio(Some(1));
+    let err = DecompressError::from(decoder.read_to_end(&mut Vec::new()).unwrap_err());
+    assert!(matches!(err, DecompressError::Eof { .. }));
+}
+
+#[test]
+fn test_decoder_chunks() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..]);
+    let chunks: Vec<Vec<u8>> = decoder.chunks(1000).map(|c| c.unwrap()).collect();
+    assert!(chunks[..chunks.len() - 1].iter().all(|c| c.len() == 1000));
+    assert!(chunks.concat() == TEST_DATA);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..compressed.len() / 2]);
+    let mut chunks = decoder.chunks(1 << 20);
+    assert!(chunks.next().unwrap().is_err());
+    assert!(chunks.next().is_none());
+}
+
+#[test]
+fn test_decoder_size_hint() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+
+    let mut decoder = PrsDecoder::<_, Modern>::with_size_hint(
+        &compressed[..],
+        TEST_DATA.len() as u64,
+    );
+    let mut out = Vec::new();
+    assert_eq!(decoder.read_to_end(&mut out).unwrap(), TEST_DATA.len());
+    assert!(out == TEST_DATA);
+    assert_eq!(out.capacity(), TEST_DATA.len());
+
+    // wrong hints only affect allocation
+    for hint in [0, 10, 1 << 20, u64::MAX] {
+        let mut decoder = PrsDecoder::<_, Modern>::with_size_hint(&compressed[..], hint);
+        let mut out = b"prefix".to_vec();
+        decoder.read_to_end(&mut out).unwrap();
+        assert!(out[..6] == b"prefix"[..] && out[6..] == TEST_DATA[..]);
+    }
+
+    // after some data was read through BufRead
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..]);
+    decoder.fill_buf().unwrap();
+    decoder.consume(5);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == TEST_DATA[5..]);
+}
+
+#[test]
+fn test_decoder_clone() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..]);
+    let mut head = vec![0; 1000];
+    decoder.read_exact(&mut head).unwrap();
+    decoder.fill_buf().unwrap();
+    let mut fork = decoder.clone();
+    let mut rest = Vec::new();
+    decoder.read_to_end(&mut rest).unwrap();
+    let mut fork_rest = Vec::new();
+    fork.read_to_end(&mut fork_rest).unwrap();
+    assert!(rest == fork_rest && rest == TEST_DATA[1000..]);
+
+    let mut decoder = crate::bufread::PrsDecoder::<_, Modern>::new(&compressed[..]);
+    decoder.read_exact(&mut head).unwrap();
+    let mut fork = decoder.clone();
+    let mut fork_rest = Vec::new();
+    fork.read_to_end(&mut fork_rest).unwrap();
+    assert!(fork_rest == TEST_DATA[1000..]);
+
+    let mut state = Decompress::<Modern>::new();
+    let mut out = vec![0; TEST_DATA.len()];
+    state.run(&compressed[..compressed.len() / 2], &mut out).unwrap();
+    let produced = state.total_out() as usize;
+    let mut fork = state.clone();
+    assert_eq!(
+        fork.run(&compressed[compressed.len() / 2..], &mut out[produced..]).unwrap(),
+        Status::Done,
+    );
+    assert!(out == TEST_DATA);
+}
+
+#[test]
+fn test_decoder_seek() {
+    // long enough for the decoder to take checkpoints
+    let data: Vec<u8> = (0..2_500_000u32).map(|i| ((i / 3) ^ (i >> 11)) as u8).collect();
+    let mut stream = b"head".to_vec();
+    stream.extend(compress::<Legacy, _>(&data));
+
+    let mut inner = Cursor::new(&stream);
+    inner.seek(SeekFrom::Start(4)).unwrap();
+    let mut decoder = PrsDecoder::<_, Legacy>::new(inner);
+    let mut buf = [0; 100];
+    for &pos in &[2_200_000, 1_500_000, 10, 2_400_000, 1_048_576, 0] {
+        assert_eq!(decoder.seek(SeekFrom::Start(pos)).unwrap(), pos);
+        decoder.read_exact(&mut buf).unwrap();
+        assert!(buf[..] == data[pos as usize..pos as usize + 100]);
+    }
+
+    decoder.fill_buf().unwrap();
+    assert_eq!(decoder.seek(SeekFrom::Current(-50)).unwrap(), 50);
+    decoder.read_exact(&mut buf).unwrap();
+    assert!(buf[..] == data[50..150]);
+
+    let len = data.len() as u64;
+    assert_eq!(decoder.seek(SeekFrom::End(-100)).unwrap(), len - 100);
+    decoder.read_exact(&mut buf).unwrap();
+    assert!(buf[..] == data[data.len() - 100..]);
+    assert_eq!(decoder.seek(SeekFrom::Start(len + 10)).unwrap(), len);
+    assert!(decoder.seek(SeekFrom::Current(-(len as i64) - 1)).is_err());
+}
+
+#[test]
+fn test_decoder_checkpoint() {
+    let data = TEST_DATA.repeat(4);
+    let compressed = compress::<Modern, _>(&data);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(Cursor::new(&compressed));
+    let mut head = vec![0; 20_000];
+    decoder.read_exact(&mut head).unwrap();
+    decoder.fill_buf().unwrap();
+    let saved = decoder.checkpoint().to_bytes();
+
+    let state = DecoderState::from_bytes(&saved).unwrap();
+    assert_eq!(state.total_out(), 20_000);
+    let mut inner = Cursor::new(&compressed);
+    inner.seek(SeekFrom::Start(state.total_in())).unwrap();
+    let mut resumed = PrsDecoder::<_, Modern>::new(inner);
+    resumed.restore(&state);
+    let mut rest = Vec::new();
+    resumed.read_to_end(&mut rest).unwrap();
+    assert!(rest == data[20_000..]);
+
+    let mut decoder = crate::bufread::PrsDecoder::<_, Modern>::new(&compressed[..]);
+    decoder.read_exact(&mut head).unwrap();
+    let state = decoder.checkpoint();
+    let mut resumed = crate::bufread::PrsDecoder::<_, Modern>::new(
+        &compressed[state.total_in() as usize..],
+    );
+    resumed.restore(&state);
+    let mut rest = Vec::new();
+    resumed.read_to_end(&mut rest).unwrap();
+    assert!(rest == data[20_000..]);
+
+    assert!(DecoderState::from_bytes(&saved[..saved.len() - 1]).is_none());
+    assert!(DecoderState::from_bytes(&[]).is_none());
+    let mut bad = saved.clone();
+    bad[0] = 2;
+    assert!(DecoderState::from_bytes(&bad).is_none());
+}
+
+#[test]
+fn test_analyze() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+    let stats = analyze::<Modern>(&compressed);
+    assert!(stats.complete);
+    assert_eq!(stats.compressed_size, compressed.len());
+    assert_eq!(stats.decompressed_size, TEST_DATA.len() as u64);
+    assert_eq!(stats.lengths.iter().sum::<u64>(), stats.pointers());
+    assert_eq!(stats.distances.iter().sum::<u64>(), stats.pointers());
+    let copied: u64 = stats.lengths.iter().enumerate().map(|(n, &c)| n as u64 * c).sum();
+    assert_eq!(stats.literals + copied, TEST_DATA.len() as u64);
+    assert!(stats.short_pointers > 0 && stats.long_pointers > 0 && stats.sized_pointers > 0);
+
+    // a literal, a short pointer, a long pointer, a long pointer with a size
+    // byte, then the end of the stream
+    let stream = [0x41, b'a', 0xFF, 0x01, 0x00, 0x05, 0xF8, 0xFF, 0x00, 0x00, 0x00];
+    let stats = analyze::<Legacy>(&stream);
+    assert_eq!(
+        (stats.literals, stats.short_pointers, stats.long_pointers, stats.sized_pointers),
+        (1, 1, 1, 1),
+    );
+    assert_eq!(stats.distances[1], 2);
+    assert_eq!(stats.distances[8192], 1);
+
+    let stats = analyze::<Modern>(&compressed[..compressed.len() - 2]);
+    assert!(!stats.complete);
+    assert_eq!(stats.compressed_size, compressed.len() - 2);
+}
+
+#[test]
+fn test_dump() {
+    // a literal, a short pointer, a long pointer, a long pointer with a size
+    // byte, then the end of the stream
+    let stream = [0x41, b'a', 0xFF, 0x01, 0x00, 0x05, 0xF8, 0xFF, 0x00, 0x00, 0x00];
+    let listed: Vec<_> = records::<Legacy>(&stream)
+        .map(|r| {
+            let r = r.unwrap();
+            (r.offset, r.flag_offset, r.flag_bit, r.output_len, r.form)
+        })
+        .collect();
+    assert_eq!(listed, [
+        (0, 0, 0, 0, CommandForm::Literal),
+        (2, 0, 1, 1, CommandForm::ShortPointer),
+        (3, 0, 5, 3, CommandForm::LongPointer),
+        (5, 0, 7, 6, CommandForm::SizedPointer),
+        (9, 5, 1, 7, CommandForm::Eof),
+    ]);
+
+    assert_eq!(dump::<Legacy>(&stream, true), "\
+        00000000 00000000.0 00000000 literal 61\n\
+        00000002 00000000.1 00000001 short distance=1 length=2\n\
+        00000003 00000000.5 00000003 long distance=8192 length=3\n\
+        00000005 00000000.7 00000006 sized distance=1 length=1\n\
+        00000009 00000005.1 00000007 eof\n");
+
+    let listing = dump::<Legacy>(&stream[..8], false);
+    assert!(listing.starts_with("00000000 00000000.0 00000000 literal\n"));
+    assert!(listing.lines().last().unwrap().starts_with("error: "));
+}
+
+#[test]
+fn test_encoder_builder() {
+    let dict = &TEST_DATA[..4096];
+    let terminator = Terminator::new().fill_bits(true).trailing_zeros(2);
+
+    let mut expected = PrsEncoder::<_, Modern>::with_dictionary(Vec::new(), dict);
+    expected.set_terminator(terminator);
+    expected.write_all(TEST_DATA).unwrap();
+    let expected = expected.into_inner().unwrap();
+
+    let builder = PrsEncoderBuilder::<Modern>::new()
+        .dictionary(dict)
+        .terminator(terminator);
+    for _ in 0..2 {
+        let mut encoder = builder.build(Vec::new());
+        encoder.write_all(TEST_DATA).unwrap();
+        assert!(encoder.into_inner().unwrap() == expected);
+    }
+
+    // the defaults match PrsEncoder::new
+    let mut encoder = PrsEncoderBuilder::<Legacy>::default().build(Vec::new());
+    encoder.write_all(TEST_DATA).unwrap();
+    assert!(encoder.into_inner().unwrap() == compress::<Legacy, _>(TEST_DATA));
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = builder.clone().finish_on_drop(false).build(&mut out);
+        encoder.write_all(b"Hello").unwrap();
+    }
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_max_output() {
+    // large enough that the matcher encodes some of it before the end
+    let data = noise(200_000);
+    let expected = compress::<Modern, _>(&data);
+    let limit = expected.len() as u64;
+
+    let mut encoder = PrsEncoder::<_, Modern>::with_max_output(Vec::new(), limit);
+    encoder.write_all(&data).unwrap();
+    assert!(encoder.into_inner().unwrap() == expected);
+
+    // a stream too large fails on a write, before the end of the input
+    let mut encoder = PrsEncoderBuilder::<Modern>::new()
+        .max_output(limit / 2)
+        .build(Vec::new());
+    let mut failed = None;
+    for (i, chunk) in data.chunks(256).enumerate() {
+        if let Err(e) = encoder.write_all(chunk) {
+            failed = Some((i, e));
+            break;
+        }
+    }
+    let (i, e) = failed.expect("the limit was not enforced");
+    assert!(i < data.len() / 256);
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(matches!(
+        e.get_ref().and_then(|e| e.downcast_ref::<CompressError>()),
+        Some(CompressError::LimitExceeded)
+    ));
+    assert!(encoder.flush().is_err());
+    assert!(encoder.try_finish().is_err());
+    assert!(encoder.get_ref().len() as u64 <= limit / 2);
+    let (out, _) = encoder.into_parts();
+    assert!(out.len() as u64 <= limit / 2);
+
+    // one byte short fails once the end of the stream is written
+    let mut encoder = PrsEncoder::<_, Modern>::with_max_output(Vec::new(), limit - 1);
+    encoder.write_all(&data).unwrap();
+    assert!(encoder.try_finish().is_err());
+    assert!((encoder.into_parts().0.len() as u64) < limit);
+}
+
+#[test]
+fn test_terminator() {
+    let plain = compress::<Legacy, _>(b"Hello");
+
+    let mut encoder = PrsEncoder::<_, Legacy>::new(Vec::new());
+    encoder.set_terminator(Terminator::new().trailing_zeros(3));
+    encoder.write_all(b"Hello").unwrap();
+    let padded = encoder.into_inner().unwrap();
+    assert_eq!(padded[..plain.len()], plain[..]);
+    assert_eq!(padded[plain.len()..], [0, 0, 0]);
+
+    // 5 literals and the end of stream command leave one flag bit over
+    let mut encoder = PrsEncoder::<_, Legacy>::new(Vec::new());
+    encoder.set_terminator(Terminator::new().fill_bits(true).align(16));
+    encoder.write_all(b"Hello").unwrap();
+    let aligned = encoder.into_inner().unwrap();
+    assert_eq!(aligned.len(), 16);
+    assert_eq!(aligned[0], plain[0] | 0x80);
+    assert_eq!(aligned[1..plain.len()], plain[1..]);
+    assert!(aligned[plain.len()..].iter().all(|&b| b == 0));
+    assert_eq!(crate::decompress::<Legacy>(&aligned).unwrap(), b"Hello");
+
+    // alignment counts what was already written out
+    let data = noise(100_000);
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    encoder.set_terminator(Terminator::new().align(4096));
+    encoder.write_all(&data).unwrap();
+    let aligned = encoder.into_inner().unwrap();
+    assert_eq!(aligned.len() % 4096, 0);
+    assert_eq!(crate::decompress::<Modern>(&aligned).unwrap(), data);
+}
+
+#[test]
+fn test_compress_stream() {
+    let data = noise(100_000);
+    let mut out = TrickleWriter(Vec::new());
+    let stats = compress_stream::<Modern>(&data[..], &mut out).unwrap();
+    assert_eq!(stats.input_len, data.len() as u64);
+    assert_eq!(stats.output_len, out.0.len() as u64);
+    assert_eq!(crate::decompress::<Modern>(&out.0).unwrap(), data);
+
+    let stats = compress_stream::<Legacy>(&b""[..], Vec::new()).unwrap();
+    assert_eq!(stats.input_len, 0);
+    assert_eq!(stats.output_len, 3);
+}
+
+#[test]
+fn test_decompress_stream() {
+    let data = noise(100_000);
+    let mut compressed = compress::<Modern, _>(&data);
+    let len = compressed.len();
+    compressed.extend_from_slice(b"trailing");
+
+    let mut out = TrickleWriter(Vec::new());
+    let stats = decompress_stream::<Modern>(&compressed[..], &mut out).unwrap();
+    assert_eq!(stats.input_len, len as u64);
+    assert_eq!(stats.output_len, data.len() as u64);
+    assert!(out.0 == data);
+
+    match decompress_stream::<Modern>(&compressed[..len - 1], Vec::new()) {
+        Err(DecompressError::Eof { .. }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+    let mut full = [0; 10];
+    match decompress_stream::<Modern>(&compressed[..], &mut full[..]) {
+        Err(DecompressError::Io(ref e)) if e.kind() == std::io::ErrorKind::WriteZero => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_decompress_strict() {
+    let compressed = compress::<Legacy, _>(TEST_DATA);
+    assert!(decompress_strict::<Legacy>(&compressed).unwrap() == TEST_DATA);
+
+    let mut padded = compressed.clone();
+    padded.resize(compressed.len() + 16, 0);
+    assert!(decompress_strict::<Legacy>(&padded).unwrap() == TEST_DATA);
+
+    padded[compressed.len() + 5] = 0xFF;
+    match decompress_strict::<Legacy>(&padded) {
+        Err(DecompressError::TrailingData { offset, output_len }) => {
+            assert_eq!(offset as usize, compressed.len() + 5);
+            assert_eq!(output_len as usize, TEST_DATA.len());
+        },
+        r => panic!("unexpected result {:?}", r.map(|_| ())),
+    }
+    let e: std::io::Error = decompress_strict::<Legacy>(&padded).unwrap_err().into();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_invalid_pointer_mode() {
+    // "ab", then a copy of 4 bytes from 5 bytes back
+    let stream = [0b1001_0011, b'a', b'b', 0xFB, 0x00, 0x00];
+    assert!(crate::decompress::<Legacy>(&stream).is_err());
+    let zero_filled = decompress_tolerant::<Legacy>(&stream, InvalidPointerMode::ZeroFill).unwrap();
+    assert_eq!(zero_filled, b"ab\0\0\0a");
+    let clamped = decompress_tolerant::<Legacy>(&stream, InvalidPointerMode::Clamp).unwrap();
+    assert_eq!(clamped, b"ababab");
+
+    for &(mode, expected) in &[
+        (InvalidPointerMode::ZeroFill, &zero_filled),
+        (InvalidPointerMode::Clamp, &clamped),
+    ] {
+        let mut decoder = PrsDecoder::<_, Legacy>::new(&stream[..]);
+        decoder.set_invalid_pointer_mode(mode);
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        assert_eq!(&out, expected);
+    }
+}
+
+#[test]
+fn test_variant_params() {
+    assert_eq!(Legacy::MIN_LONG_COPY_LENGTH, 1);
+    assert_eq!(Legacy::MAX_COPY_LENGTH, 256);
+    assert_eq!(Modern::MIN_LONG_COPY_LENGTH, 10);
+    assert_eq!(Modern::MAX_COPY_LENGTH, 265);
+    assert_eq!(Modern::MAX_COPY_DISTANCE, 8191);
+    assert_eq!(Modern::MAX_SHORT_COPY_DISTANCE, 255);
+
+    // the limits are exactly what CommandWriter accepts
+    let mut writer = CommandWriter::<_, Modern>::new(Vec::new());
+    for _ in 0..8191 {
+        writer.put_literal(0).unwrap();
+    }
+    writer.put_pointer(8191, Modern::MAX_COPY_LENGTH as usize).unwrap();
+    assert!(writer.put_pointer(8192, 3).is_err());
+    assert!(writer.put_pointer(255, Modern::MIN_COPY_LENGTH as usize).is_ok());
+    assert!(writer.put_pointer(256, Modern::MIN_COPY_LENGTH as usize).is_err());
+    assert!(writer.put_pointer(1, Modern::MAX_COPY_LENGTH as usize + 1).is_err());
+}
+
+#[test]
+fn test_decompress_overlapping_copies() {
+    // runs with short periods compress to overlapping pointers
+    let mut data = Vec::new();
+    for period in 1..12 {
+        let pattern = &noise(period)[..];
+        for _ in 0..(700 / period) {
+            data.extend_from_slice(pattern);
+        }
+    }
+    let compressed = compress::<Modern, _>(&data);
+    assert!(crate::decompress::<Modern>(&compressed).unwrap() == data);
+
+    let mut dst = vec![0; data.len()];
+    decompress_into::<Modern>(&compressed, &mut dst).unwrap();
+    assert!(dst == data);
+}
+
+#[test]
+fn test_decompress_errors() {
+    let compressed = compress::<Legacy, _>(TEST_DATA);
+    match crate::decompress::<Legacy>(&compressed[..compressed.len() - 1]) {
+        Err(DecompressError::Eof { offset, output_len }) => {
+            assert!(offset >= compressed.len() as u64 - 3);
+            assert_eq!(output_len, TEST_DATA.len() as u64);
+        },
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    // short ptr copying from before the start of the output
+    match crate::decompress::<Legacy>(&[0x01, 0x41, 0xFD]) {
+        Err(DecompressError::InvalidPointer { offset, output_len }) => {
+            assert_eq!(offset, 2);
+            assert_eq!(output_len, 1);
+        },
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    let mut decoder = PrsDecoder::<_, Legacy>::new(&[0x01, 0x41, 0xFD][..]);
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    match DecompressError::from(err) {
+        DecompressError::InvalidPointer { offset: 2, output_len: 1 } => {},
+        e => panic!("unexpected error {:?}", e),
+    }
+
+    let err = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+    match DecompressError::from(err) {
+        DecompressError::Io(e) => {
+            assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied);
+        },
+        e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn test_decoder_error_after_output() {
+    // eight literals, then a short ptr copying from 256 bytes back
+    let mut stream = vec![0xFF];
+    stream.extend_from_slice(b"abcdefgh");
+    stream.extend_from_slice(&[0x00, 0x00]);
+
+    let mut decoder = PrsDecoder::<_, Legacy>::new(&stream[..]);
+    let mut buf = [0; 4096];
+    assert_eq!(decoder.read(&mut buf).unwrap(), 8);
+    assert_eq!(&buf[..8], b"abcdefgh");
+    let err = decoder.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    match DecompressError::from(err) {
+        DecompressError::InvalidPointer { offset: 9, output_len: 8 } => {},
+        e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn test_bufread_decoder_error_consumes() {
+    // the output before an error is returned, and the inner BufRead is left
+    // just past the failing command
+    let stream = [0x83, b'a', b'b', 0xFA, 0, 0, b'x'];
+    let mut decoder = crate::bufread::PrsDecoder::<_, Legacy>::new(&stream[..]);
+    let mut buf = [0; 64];
+    assert_eq!(decoder.read(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"ab");
+    assert_eq!(*decoder.get_ref(), &stream[4..]);
+    assert_eq!(decoder.read(&mut buf).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(*decoder.get_ref(), &stream[4..]);
+
+    // a short ptr copying from 6 bytes back, before any output
+    let stream = [0x00, 0xFA, b'x'];
+    let mut decoder = crate::bufread::PrsDecoder::<_, Legacy>::new(&stream[..]);
+    assert_eq!(decoder.read(&mut buf).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(decoder.total_in(), 2);
+    assert_eq!(*decoder.get_ref(), &stream[2..]);
+}
+
+#[test]
+fn test_decompress_error_after_output() {
+    // two literals, then a short ptr copying from 6 bytes back, all in one
+    // call before anything is drained
+    let stream = [0x83, b'a', b'b', 0xFA, 0, 0];
+
+    let mut state = Decompress::<Legacy>::new();
+    let mut out = [0; 64];
+    assert_eq!(state.run(&stream, &mut out).unwrap(), Status::NeedsOutput);
+    assert_eq!(state.total_out(), 2);
+    assert_eq!(&out[..2], b"ab");
+    let rest = &stream[state.total_in() as usize..];
+    match state.run(rest, &mut out) {
+        Err(DecompressError::InvalidPointer { offset: 3, output_len: 2 }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    // the output still comes first when it takes several calls
+    let mut state = Decompress::<Legacy>::new();
+    let mut out = Vec::new();
+    let mut byte = [0; 1];
+    let err = loop {
+        let rest = &stream[state.total_in() as usize..];
+        let total_out = state.total_out();
+        match state.run(rest, &mut byte) {
+            Ok(_) => out.extend_from_slice(&byte[..(state.total_out() - total_out) as usize]),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(out, b"ab");
+    assert!(matches!(err, DecompressError::InvalidPointer { offset: 3, .. }));
+}
+
+#[test]
+fn test_compress_into() {
+    let expected = compress::<Legacy, _>(TEST_DATA);
+
+    let mut dst = vec![0; compress_bound::<Legacy>(TEST_DATA.len())];
+    let len = compress_into::<Legacy>(TEST_DATA, &mut dst).unwrap();
+    assert!(dst[..len] == expected[..]);
+    assert!(crate::compress::<Legacy>(TEST_DATA) == expected);
+
+    let mut small = vec![0; expected.len() - 1];
+    match compress_into::<Legacy>(TEST_DATA, &mut small) {
+        Err(CompressError::BufferTooSmall) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_validate() {
+    let mut compressed = compress::<Legacy, _>(TEST_DATA);
+    let len = compressed.len();
+    compressed.extend_from_slice(&[1, 2, 3]);
+
+    let info = validate::<Legacy>(&compressed).unwrap();
+    assert_eq!(info.compressed_size, len);
+    assert_eq!(info.decompressed_size, TEST_DATA.len() as u64);
+    assert!(info.commands > 0 && info.commands < TEST_DATA.len() as u64);
+
+    assert!(validate::<Legacy>(&compressed[..len - 1]).is_err());
+}
+
+#[test]
+fn test_decompress_prefix() {
+    let first = compress::<Legacy, _>(TEST_DATA);
+    let second = compress::<Legacy, _>(b"second");
+    let mut archive = first.clone();
+    archive.extend_from_slice(&second);
+
+    let (data, consumed) = decompress_prefix::<Legacy>(&archive).unwrap();
+    assert!(data == TEST_DATA);
+    assert_eq!(consumed, first.len());
+    let (data, consumed) = decompress_prefix::<Legacy>(&archive[consumed..]).unwrap();
+    assert_eq!(&data[..], b"second");
+    assert_eq!(consumed, second.len());
+
+    assert!(decompress_prefix::<Legacy>(&first[..first.len() - 1]).is_err());
+}
+
+#[test]
+fn test_multi_decoder() {
+    use crate::bufread::MultiPrsDecoder;
+
+    let mut data = compress::<Modern, _>(TEST_DATA);
+    data.extend_from_slice(&compress::<Modern, _>(b""));
+    data.extend_from_slice(&compress::<Modern, _>(b"second"));
+
+    let mut out = Vec::new();
+    let reader = BufReader::with_capacity(7, &data[..]);
+    MultiPrsDecoder::<_, Modern>::new(reader).read_to_end(&mut out).unwrap();
+    assert_eq!(&out[..TEST_DATA.len()], TEST_DATA);
+    assert_eq!(&out[TEST_DATA.len()..], b"second");
+
+    data.push(0);
+    let mut decoder = MultiPrsDecoder::<_, Modern>::new(&data[..]);
+    assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn test_dictionary() {
+    let dict = &TEST_DATA[..TEST_DATA.len() / 2];
+    let data = &TEST_DATA[TEST_DATA.len() / 4..];
+
+    let mut encoder = PrsEncoder::<_, Modern>::with_dictionary(Vec::new(), dict);
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.into_inner().unwrap();
+    assert!(compressed.len() < compress::<Modern, _>(data).len());
+
+    // the dictionary fills in history that is not part of the output
+    assert!(crate::decompress::<Modern>(&compressed).is_err());
+
+    let mut out = Vec::new();
+    let mut decoder = PrsDecoder::<_, Modern>::with_dictionary(&compressed[..], dict);
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == data);
+
+    let mut out = Vec::new();
+    let mut decoder = crate::bufread::PrsDecoder::<_, Modern>::with_dictionary(&compressed[..], dict);
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == data);
+
+    // both state machines keep the dictionary across resets
+    let mut state = Compress::<Modern>::new();
+    state.set_dictionary(dict);
+    let mut buf = vec![0; compress_bound::<Modern>(data.len())];
+    state.run(data, &mut buf);
+    state.finish(&mut buf[state.total_out() as usize..]);
+    state.reset();
+    state.run(data, &mut buf);
+    let len = state.total_out() as usize;
+    assert_eq!(state.finish(&mut buf[len..]), Status::Done);
+    assert!(buf[..state.total_out() as usize] == compressed[..]);
+
+    // and an empty dictionary goes back to plain compression
+    state.set_dictionary(&[]);
+    state.run(data, &mut buf);
+    let len = state.total_out() as usize;
+    assert_eq!(state.finish(&mut buf[len..]), Status::Done);
+    assert!(buf[..state.total_out() as usize] == compress::<Modern, _>(data)[..]);
+
+    let mut state = Decompress::<Modern>::new();
+    state.set_dictionary(dict);
+    state.reset();
+    let mut out = vec![0; data.len()];
+    assert_eq!(state.run(&compressed, &mut out).unwrap(), Status::Done);
+    assert!(out == data);
+}
+
+#[test]
+fn test_train_dictionary() {
+    let samples: Vec<&[u8]> = TEST_DATA.chunks(300).collect();
+    let (target, samples) = samples.split_last().unwrap();
+
+    let dict = train_dictionary(samples, 2048);
+    assert!(!dict.is_empty() && dict.len() <= 2048);
+    assert!(train_dictionary(samples, 100_000).len() <= 8191);
+    assert!(train_dictionary(&[], 2048).is_empty());
+
+    let mut encoder = PrsEncoder::<_, Legacy>::with_dictionary(Vec::new(), &dict);
+    encoder.write_all(target).unwrap();
+    let compressed = encoder.into_inner().unwrap();
+    assert!(compressed.len() < compress::<Legacy, _>(target).len());
+}
+
+#[test]
+fn test_command_iter() {
+    let compressed = compress::<Legacy, _>(TEST_DATA);
+    let info = validate::<Legacy>(&compressed).unwrap();
+
+    let mut output_len = 0;
+    let mut commands = 0;
+    let mut last = None;
+    for r in CommandIter::<Legacy>::new(&compressed) {
+        let (offset, cmd) = r.unwrap();
+        assert!(offset < compressed.len());
+        match cmd {
+            Command::Literal(_) => output_len += 1,
+            Command::Pointer { distance, length } => {
+                assert!(distance <= output_len);
+                output_len += length;
+            },
+            Command::Eof => {},
+        }
+        commands += 1;
+        last = Some(cmd);
+    }
+    assert_eq!(last, Some(Command::Eof));
+    assert_eq!(commands - 1, info.commands);
+    assert_eq!(output_len as u64, info.decompressed_size);
+
+    let truncated = &compressed[..compressed.len() - 1];
+    let last = CommandIter::<Legacy>::new(truncated).last().unwrap();
+    assert!(matches!(last, Err(DecompressError::Eof { .. })));
+}
+
+#[test]
+fn test_command_writer() {
+    // the encoder picks the same encodings, so its commands round-trip exactly
+    let compressed = compress::<Modern, _>(TEST_DATA);
+    let mut writer = CommandWriter::<_, Modern>::new(Vec::new());
+    for r in CommandIter::<Modern>::new(&compressed) {
+        match r.unwrap().1 {
+            Command::Literal(b) => writer.put_literal(b).unwrap(),
+            Command::Pointer { distance, length } => {
+                writer.put_pointer(distance, length).unwrap()
+            },
+            Command::Eof => {},
+        }
+    }
+    assert_eq!(writer.finish().unwrap(), compressed);
+
+    // short copies from far back only fit Legacy's size byte
+    let data = noise(300);
+    let mut writer = CommandWriter::<_, Legacy>::new(Vec::new());
+    for &b in &data {
+        writer.put_literal(b).unwrap();
+    }
+    writer.put_pointer(300, 1).unwrap();
+    writer.put_pointer(300, 2).unwrap();
+    let out = decompress::<Legacy, _>(writer.finish().unwrap());
+    assert_eq!(&out[300..], &[data[0], data[1], data[2]]);
+
+    let mut writer = CommandWriter::<_, Modern>::new(Vec::new());
+    writer.put_literal(0).unwrap();
+    assert!(writer.put_pointer(1, 1).is_err());
+    assert!(writer.put_pointer(2, 4).is_err());
+    assert!(writer.put_pointer(0, 4).is_err());
+    assert!(writer.put_pointer(1, 300).is_err());
+}
+
+#[test]
+fn test_transcode() {
+    let mut legacy = compress::<Legacy, _>(TEST_DATA);
+    legacy.extend_from_slice(b"trailer");
+
+    let mut reader = &legacy[..];
+    let mut modern = Vec::new();
+    let stats = transcode::<Legacy, Modern, _, _>(&mut reader, &mut modern).unwrap();
+    assert_eq!(reader, b"trailer");
+    assert_eq!(stats.input_len, TEST_DATA.len() as u64);
+    assert_eq!(stats.output_len, modern.len() as u64);
+    assert_eq!(decompress::<Modern, _>(&modern), TEST_DATA);
+
+    let mut back = Vec::new();
+    transcode::<Modern, Legacy, _, _>(&modern[..], &mut back).unwrap();
+    assert_eq!(back, &legacy[..legacy.len() - 7]);
+
+    let truncated = &modern[..modern.len() - 1];
+    assert!(transcode::<Modern, Legacy, _, _>(truncated, Vec::new()).is_err());
+}
+
+#[test]
+fn test_seekable() {
+    use crate::seekable::{SeekablePrsReader, SeekablePrsWriter};
+
+    let mut data = Vec::new();
+    for _ in 0..10 {
+        data.extend_from_slice(TEST_DATA);
+    }
+
+    // the container can follow other data
+    let mut writer = SeekablePrsWriter::<_, Legacy>::with_block_size(b"head".to_vec(), 1000);
+    writer.write_all(&data).unwrap();
+    let container = writer.finish().unwrap();
+
+    let mut reader = SeekablePrsReader::<_, Legacy>::new(Cursor::new(&container)).unwrap();
+    assert_eq!(reader.len(), data.len() as u64);
+    assert_eq!(reader.block_count(), data.len().div_ceil(1000));
+
+    let mut out = Vec::new();
+    reader.read_to_end(&mut out).unwrap();
+    assert!(out == data);
+
+    for &pos in &[0, 999, 1000, 1001, 4321, data.len() - 10] {
+        let mut buf = [0; 10];
+        reader.seek(SeekFrom::Start(pos as u64)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[pos..pos + 10]);
+    }
+    reader.seek(SeekFrom::End(-3)).unwrap();
+    reader.seek(SeekFrom::Current(-2)).unwrap();
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).unwrap();
+    assert_eq!(&buf[..], &data[data.len() - 5..]);
+    assert!(reader.seek(SeekFrom::Current(-(data.len() as i64) - 1)).is_err());
+
+    let empty = SeekablePrsWriter::<_, Legacy>::new(Vec::new()).finish().unwrap();
+    let reader = SeekablePrsReader::<_, Legacy>::new(Cursor::new(empty)).unwrap();
+    assert!(reader.is_empty());
+
+    assert!(SeekablePrsReader::<_, Legacy>::new(Cursor::new(&container[..10])).is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_compress_parallel() {
+    use crate::seekable::{compress_parallel, SeekablePrsWriter};
+
+    let mut data = Vec::new();
+    for _ in 0..10 {
+        data.extend_from_slice(TEST_DATA);
+    }
+
+    let mut writer = SeekablePrsWriter::<_, Modern>::with_block_size(Vec::new(), 4096);
+    writer.write_all(&data).unwrap();
+    assert_eq!(compress_parallel::<Modern>(&data, 4096), writer.finish().unwrap());
+}
+
+#[test]
+fn test_framed() {
+    use crate::framed::{read_framed, read_header, write_framed, FrameVariant};
+
+    let mut framed = Vec::new();
+    write_framed::<Legacy, _>(&mut framed, TEST_DATA).unwrap();
+    framed.extend_from_slice(b"trailer");
+
+    let header = read_header(&framed[..]).unwrap();
+    assert_eq!(header.variant, FrameVariant::Legacy);
+    assert!(!header.big_endian_offsets);
+    assert_eq!(header.decompressed_size, TEST_DATA.len() as u64);
+
+    let mut reader = &framed[..];
+    assert!(read_framed(&mut reader).unwrap() == TEST_DATA);
+    assert_eq!(reader, b"trailer");
+
+    let mut bad = framed.clone();
+    bad[4] = 7;
+    assert!(read_framed(&bad[..]).is_err());
+
+    // a size that doesn't match the stream
+    let mut bad = framed.clone();
+    bad[6] ^= 1;
+    assert!(read_framed(&bad[..]).is_err());
+
+    let mut framed = Vec::new();
+    write_framed::<BigEndian<Modern>, _>(&mut framed, TEST_DATA).unwrap();
+    let header = read_header(&framed[..]).unwrap();
+    assert_eq!(header.variant, FrameVariant::Modern);
+    assert!(header.big_endian_offsets);
+    assert!(read_framed(&framed[..]).unwrap() == TEST_DATA);
+}
+
+#[test]
+fn test_framed_crc32() {
+    use crate::framed::{
+        read_framed,
+        read_header,
+        write_framed_crc32,
+        ChecksumMismatch,
+        HEADER_LEN,
+    };
+
+    let mut framed = Vec::new();
+    write_framed_crc32::<Modern, _>(&mut framed, b"123456789").unwrap();
+    assert!(read_header(&framed[..]).unwrap().crc32);
+    // the standard check value of CRC-32
+    assert_eq!(framed[framed.len() - 4..], 0xCBF4_3926u32.to_le_bytes());
+    framed.extend_from_slice(b"trailer");
+
+    let mut reader = &framed[..];
+    assert_eq!(read_framed(&mut reader).unwrap(), b"123456789");
+    assert_eq!(reader, b"trailer");
+
+    let mut framed = Vec::new();
+    write_framed_crc32::<Legacy, _>(&mut framed, TEST_DATA).unwrap();
+    assert!(read_framed(&framed[..]).unwrap() == TEST_DATA);
+
+    // a literal changed in place still decompresses to the recorded size
+    let mut bad = framed.clone();
+    bad[HEADER_LEN + 1] ^= 0x20;
+    let e = read_framed(&bad[..]).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    let mismatch = e.get_ref().unwrap().downcast_ref::<ChecksumMismatch>().unwrap();
+    assert_eq!(mismatch.expected.to_le_bytes(), framed[framed.len() - 4..]);
+    assert!(mismatch.actual != mismatch.expected);
+
+    // a missing checksum
+    assert!(read_framed(&framed[..framed.len() - 2]).is_err());
+}
+
+#[test]
+fn test_big_endian_offsets() {
+    let data = TEST_DATA.repeat(2);
+    let le = compress::<Legacy, _>(&data);
+    let be = compress::<BigEndian<Legacy>, _>(&data);
+    assert_eq!(le.len(), be.len());
+    assert!(le != be);
+    assert!(crate::decompress::<BigEndian<Legacy>>(&be).unwrap() == data);
+    let be = compress::<BigEndian<Modern>, _>(&data);
+    assert!(crate::decompress::<BigEndian<Modern>>(&be).unwrap() == data);
+
+    // a literal and a long pointer copying 3 bytes from 1 back, offset word
+    // 0xFFF9 in either order
+    assert_eq!(crate::decompress::<Legacy>(&[0x15, b'a', 0xF9, 0xFF, 0, 0]).unwrap(), b"aaaa");
+    let be = [0x15, b'a', 0xFF, 0xF9, 0, 0];
+    assert_eq!(crate::decompress::<BigEndian<Legacy>>(&be).unwrap(), b"aaaa");
+    assert!(crate::decompress::<Legacy>(&be).is_err());
+}
+
+#[test]
+fn test_zero_offset_eof() {
+    use crate::ZeroOffsetEof;
+
+    let data = TEST_DATA.repeat(2);
+    let compressed = compress::<Legacy, _>(&data);
+    assert!(compress::<ZeroOffsetEof<Legacy>, _>(&data) == compressed);
+    assert!(crate::decompress::<ZeroOffsetEof<Legacy>>(&compressed).unwrap() == data);
+
+    // a literal and a long pointer with a zero offset field, copying 3 bytes
+    // from 8192 back, then a literal and the end of the stream
+    let stream = [0x2D, b'a', 0x01, 0x00, b'b', 0x00, 0x00];
+    match crate::decompress::<Legacy>(&stream) {
+        Err(DecompressError::InvalidPointer { offset: 2, output_len: 1 }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+    let (out, consumed) = decompress_prefix::<ZeroOffsetEof<Legacy>>(&stream).unwrap();
+    assert_eq!(out, b"a");
+    assert_eq!(consumed, 4);
+    let be = [0x2D, b'a', 0x00, 0x01, b'b', 0x00, 0x00];
+    let (out, _) = decompress_prefix::<ZeroOffsetEof<BigEndian<Modern>>>(&be).unwrap();
+    assert_eq!(out, b"a");
+
+    let mut decoder = PrsDecoder::<_, ZeroOffsetEof<Legacy>>::new(&stream[..]);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, b"a");
+    assert_eq!(decoder.into_inner(), &stream[4..]);
+}
+
+#[test]
+fn test_progress() {
+    use std::sync::{Arc, Mutex};
+
+    let mut data = Vec::new();
+    for _ in 0..10 {
+        data.extend_from_slice(TEST_DATA);
+    }
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut encoder = PrsEncoder::<_, Legacy>::new(Vec::new());
+    let s = seen.clone();
+    encoder.on_progress(move |i, o| s.lock().unwrap().push((i, o)));
+    for chunk in data.chunks(1000) {
+        encoder.write_all(chunk).unwrap();
+    }
+    let compressed = encoder.into_inner().unwrap();
+    let seen = std::mem::take(&mut *seen.lock().unwrap());
+    assert!(seen.windows(2).all(|w| w[0].0 <= w[1].0 && w[0].1 <= w[1].1));
+    assert_eq!(seen.last(), Some(&(data.len() as u64, compressed.len() as u64)));
+
+    let last = Arc::new(Mutex::new((0, 0)));
+    let mut decoder = PrsDecoder::<_, Legacy>::new(&compressed[..]);
+    let l = last.clone();
+    decoder.on_progress(move |i, o| *l.lock().unwrap() = (i, o));
+    decoder.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(*last.lock().unwrap(), (compressed.len() as u64, data.len() as u64));
+
+    let last = Arc::new(Mutex::new((0, 0)));
+    let mut decoder = crate::bufread::PrsDecoder::<_, Legacy>::new(&compressed[..]);
+    let l = last.clone();
+    decoder.on_progress(move |i, o| *l.lock().unwrap() = (i, o));
+    decoder.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(*last.lock().unwrap(), (compressed.len() as u64, data.len() as u64));
+}
+
+#[test]
+fn test_decompress_exact() {
+    for data in &[Vec::new(), TEST_DATA.to_vec(), noise(70_000), vec![0; 1 << 20]] {
+        let compressed = compress::<Modern, _>(data);
+        let out = decompress_exact::<Modern>(&compressed).unwrap();
+        assert!(out == *data);
+        assert_eq!(out.capacity(), data.len());
+    }
+    let compressed = compress::<Legacy, _>(TEST_DATA);
+    assert!(decompress_exact::<Legacy>(&compressed[..compressed.len() - 1]).is_err());
+}
+
+#[test]
+fn test_buffer_capacity() {
+    let mut encoder = PrsEncoder::<_, Modern>::with_capacity(Vec::new(), 1 << 16);
+    encoder.write_all(TEST_DATA).unwrap();
+    let compressed = encoder.into_inner().unwrap();
+    assert!(compressed == compress::<Modern, _>(TEST_DATA));
+
+    let mut encoder = PrsEncoderBuilder::<Modern>::new().capacity(4096).build(Vec::new());
+    encoder.write_all(TEST_DATA).unwrap();
+    assert!(encoder.into_inner().unwrap() == compressed);
+
+    for &capacity in &[0, 1, 100, 1 << 16] {
+        let mut decoder = PrsDecoder::<_, Modern>::with_capacity(&compressed[..], capacity);
+        let available = decoder.fill_buf().unwrap().len();
+        assert_eq!(available, std::cmp::max(capacity, 1).min(TEST_DATA.len()));
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        assert!(out == TEST_DATA);
+    }
+}
+
+#[test]
+fn test_stream_totals() {
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    encoder.write_all(TEST_DATA).unwrap();
+    assert_eq!(encoder.total_in(), TEST_DATA.len() as u64);
+    assert_eq!(encoder.total_out(), encoder.get_ref().len() as u64);
+    encoder.try_finish().unwrap();
+    let compressed_len = encoder.total_out();
+    let compressed = encoder.into_inner().unwrap();
+    assert_eq!(compressed_len, compressed.len() as u64);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..]);
+    let mut buf = [0; 100];
+    decoder.read_exact(&mut buf).unwrap();
+    assert_eq!(decoder.total_out(), 100);
+    // data buffered by fill_buf isn't counted until consumed
+    let available = decoder.fill_buf().unwrap().len() as u64;
+    assert!(available > 0);
+    assert_eq!(decoder.total_out(), 100);
+    decoder.consume(10);
+    assert_eq!(decoder.total_out(), 110);
+    decoder.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(decoder.total_in(), compressed.len() as u64);
+    assert_eq!(decoder.total_out(), TEST_DATA.len() as u64);
+
+    let mut decoder = crate::bufread::PrsDecoder::<_, Modern>::new(&compressed[..]);
+    decoder.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(decoder.total_in(), compressed.len() as u64);
+    assert_eq!(decoder.total_out(), TEST_DATA.len() as u64);
+}
+
+#[test]
+fn test_shared_slice() {
+    use std::sync::Arc;
+    use crate::bufread::{PrsDecoder, SharedSlice};
+
+    let entries: Vec<&[u8]> = TEST_DATA.chunks(3000).collect();
+    let mut archive = Vec::new();
+    let mut ranges = Vec::new();
+    for entry in &entries {
+        let start = archive.len();
+        archive.extend_from_slice(&compress::<Modern, _>(entry));
+        ranges.push(start..archive.len());
+    }
+    let archive: Arc<[u8]> = archive.into();
+
+    let workers: Vec<_> = ranges
+        .iter()
+        .map(|range| {
+            let mut decoder = PrsDecoder::<_, Modern>::from_shared(archive.clone(), range.clone());
+            std::thread::spawn(move || {
+                let mut out = Vec::new();
+                decoder.read_to_end(&mut out).unwrap();
+                (out, decoder.into_inner().position())
+            })
+        })
+        .collect();
+    for ((worker, entry), range) in workers.into_iter().zip(&entries).zip(&ranges) {
+        let (out, position) = worker.join().unwrap();
+        assert!(out == *entry);
+        assert_eq!(position, range.end);
+    }
+
+    // the range bounds the stream
+    let mut decoder = PrsDecoder::<_, Modern>::from_shared(archive.clone(), 0..ranges[0].end - 1);
+    assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+
+    let mut slice = SharedSlice::with_range(archive.clone(), 2..6);
+    let mut buf = Vec::new();
+    slice.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, &archive[2..6]);
+    assert!(slice.remaining().is_empty());
+    assert_eq!(SharedSlice::new(archive.clone()).remaining(), &archive[..]);
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn test_fs() {
+    use crate::fs::{compress_file, decompress_file};
+
+    let dir = std::env::temp_dir().join(format!("ages-prs-test-fs-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let src = dir.join("data.txt");
+    let dst = dir.join("data.prs");
+    let empty = dir.join("empty");
+    std::fs::write(&src, TEST_DATA).unwrap();
+    std::fs::write(&empty, b"").unwrap();
+
+    compress_file::<Modern, _, _>(&src, &dst).unwrap();
+    assert!(decompress_file::<Modern, _>(&dst).unwrap() == TEST_DATA);
+    assert!(decompress_file::<Modern, _>(&src).is_err());
+    compress_file::<Modern, _, _>(&empty, &dst).unwrap();
+    assert!(decompress_file::<Modern, _>(&dst).unwrap().is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_arbitrary_streams() {
+    use arbitrary::{Arbitrary, Unstructured};
+    use crate::arbitrary::CommandStream;
+
+    for seed in 0..50 {
+        let bytes = noise(500 + seed * 37);
+        let mut u = Unstructured::new(&bytes);
+        let stream = CommandStream::arbitrary(&mut u).unwrap();
+        let expected = stream.decompressed();
+        assert_eq!(crate::decompress::<Legacy>(&stream.to_prs::<Legacy>()).unwrap(), expected);
+        assert_eq!(crate::decompress::<Modern>(&stream.to_prs::<Modern>()).unwrap(), expected);
+    }
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn test_simd_match_length() {
+    let a = noise(300);
+    for len in 0..300 {
+        let mut b = a.clone();
+        if len < b.len() {
+            b[len] ^= 0x40;
+        }
+        for &max in &[0, 1, 15, 16, 17, 31, 32, 33, 100, 265, 400] {
+            let expected = std::cmp::min(len, max);
+            assert_eq!(crate::simd::match_length(&a, &b, max), expected);
+        }
+        assert_eq!(crate::simd::match_length(&a[..len], &b, 265), std::cmp::min(len, 265));
+    }
+}
+
+#[test]
+fn test_decompress_limit() {
+    let data = vec![0; 100_000];
+    let compressed = compress::<Modern, _>(&data);
+
+    assert_eq!(decompress_with_limit::<Modern>(&compressed, 100_000).unwrap(), data);
+    match decompress_with_limit::<Modern>(&compressed, 99_999) {
+        Err(DecompressError::LimitExceeded { .. }) => {},
+        r => panic!("unexpected result {:?}", r.map(|v| v.len())),
+    }
+
+    let mut out = Vec::new();
+    let mut decoder = PrsDecoder::<_, Modern>::with_limit(&compressed[..], 50_000);
+    let err = decoder.read_to_end(&mut out).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_decompress_lossy() {
+    let compressed = compress::<Legacy, _>(TEST_DATA);
+
+    let (out, err) = decompress_lossy::<Legacy>(&compressed);
+    assert!(out == TEST_DATA);
+    assert!(err.is_none());
+
+    let (out, err) = decompress_lossy::<Legacy>(&compressed[..compressed.len() / 2]);
+    assert!(!out.is_empty());
+    assert!(TEST_DATA.starts_with(&out));
+    match err {
+        Some(DecompressError::Eof { output_len, .. }) => {
+            assert_eq!(output_len, out.len() as u64);
+        },
+        e => panic!("unexpected error {:?}", e),
+    }
+}
+
+#[test]
+fn test_decoder_into_inner() {
+    let mut compressed = compress::<Modern, _>(TEST_DATA);
+    compressed.extend_from_slice(b"trailer");
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(Cursor::new(&compressed[..]));
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == TEST_DATA);
+
+    let mut rest = Vec::new();
+    decoder.into_inner().read_to_end(&mut rest).unwrap();
+    assert_eq!(&rest[..], b"trailer");
+}
+
+#[test]
+fn test_encoder_get_ref() {
+    let mut encoder = PrsEncoder::<_, Legacy>::new(Cursor::new(Vec::new()));
+    for _ in 0..100 {
+        encoder.write_all(TEST_DATA).unwrap();
+    }
+    encoder.flush().unwrap();
+    let pos = encoder.get_ref().position();
+    assert!(pos > 0);
+    assert_eq!(pos as usize, encoder.get_mut().get_ref().len());
+}
+
+#[test]
+fn test_encoder_finish_stats() {
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    encoder.write_all(TEST_DATA).unwrap();
+    let (out, stats) = encoder.finish().unwrap();
+
+    assert_eq!(stats.input_len, TEST_DATA.len() as u64);
+    assert_eq!(stats.output_len, out.len() as u64);
+    assert!(stats.literals > 0);
+    assert!(stats.short_pointers + stats.long_pointers > 0);
+    assert!(stats.ratio() < 1.0);
+    assert!(decompress::<Modern, _>(&out) == TEST_DATA);
+}
+
+#[test]
+fn test_encoder_reset() {
+    let mut encoder = PrsEncoder::<_, Legacy>::new(Vec::new());
+    encoder.write_all(TEST_DATA).unwrap();
+    let first = encoder.reset(Vec::new()).unwrap();
+    encoder.write_all(&TEST_DATA[..100]).unwrap();
+    let second = encoder.into_inner().unwrap();
+
+    assert!(decompress::<Legacy, _>(&first) == TEST_DATA);
+    assert!(decompress::<Legacy, _>(&second) == TEST_DATA[..100]);
+}
+
+#[test]
+fn test_decoder_reset() {
+    let first = compress::<Modern, _>(TEST_DATA);
+    let second = compress::<Modern, _>(&TEST_DATA[..100]);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(&first[..]);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == TEST_DATA);
+
+    decoder.reset(&second[..]);
+    out.clear();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == TEST_DATA[..100]);
+}
+
+#[test]
+fn test_bufread_decoder() {
+    let mut data = Vec::with_capacity(TEST_DATA.len() * 20);
+    for _ in 0..20 {
+        data.extend_from_slice(TEST_DATA);
+    }
+    let mut compressed = compress::<Legacy, _>(&data);
+    compressed.extend_from_slice(b"trailer");
+
+    // tiny reader buffers split commands across fill_buf calls
+    for &capacity in &[1, 2, 3, 7, 8192] {
+        let reader = BufReader::with_capacity(capacity, &compressed[..]);
+        let mut decoder = crate::bufread::PrsDecoder::<_, Legacy>::new(reader);
+        let mut out = Vec::new();
+        let mut chunk = [0; 333];
+        loop {
+            match decoder.read(&mut chunk).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&chunk[..n]),
+            }
+        }
+        assert!(out == data);
+
+        let mut rest = Vec::new();
+        decoder.into_inner().read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..], b"trailer");
+    }
+
+    let truncated = &compressed[..compressed.len() / 2];
+    let mut decoder = crate::bufread::PrsDecoder::<_, Legacy>::new(truncated);
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_decoder_bufread() {
+    let compressed = compress::<Modern, _>(TEST_DATA);
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..]);
+
+    // mixing BufRead and Read calls sees the data once, in order
+    let mut first_line = String::new();
+    decoder.read_line(&mut first_line).unwrap();
+    let mut head = [0; 10];
+    decoder.read_exact(&mut head).unwrap();
+    let peeked = decoder.fill_buf().unwrap().len();
+    assert!(peeked > 0);
+    let mut rest = Vec::new();
+    decoder.read_to_end(&mut rest).unwrap();
+
+    let mut out = first_line.into_bytes();
+    out.extend_from_slice(&head);
+    out.extend_from_slice(&rest);
+    assert!(out == TEST_DATA);
+}
+
+#[test]
+fn test_decoder_constant_memory() {
+    // a long run decodes through the fixed window in one large read
+    let data = vec![7; 1 << 20];
+    let compressed = compress::<Modern, _>(&data);
+    let mut decoder = PrsDecoder::<_, Modern>::new(&compressed[..]);
+    let mut out = vec![0; data.len() + 1];
+    let mut len = 0;
+    loop {
+        match decoder.read(&mut out[len..]).unwrap() {
+            0 => break,
+            n => len += n,
+        }
+    }
+    assert_eq!(len, data.len());
+    assert!(out[..len] == data[..]);
+}
+
+#[test]
+fn test_encoder_partial_writes() {
+    let mut encoder = PrsEncoder::<_, Legacy>::new(TrickleWriter(Vec::new()));
+    for chunk in TEST_DATA.chunks(100) {
+        encoder.write_all(chunk).unwrap();
+    }
+    let out = encoder.into_inner().unwrap().0;
+    assert!(out == compress::<Legacy, _>(TEST_DATA));
+}
+
+#[test]
+fn test_encoder_write_vectored() {
+    let (a, b) = TEST_DATA.split_at(TEST_DATA.len() / 3);
+    let mut encoder = PrsEncoder::<_, Modern>::new(Vec::new());
+    let len = encoder.write_vectored(&[IoSlice::new(a), IoSlice::new(b)]).unwrap();
+    assert_eq!(len, TEST_DATA.len());
+    let out = encoder.into_inner().unwrap();
+    assert!(out == compress::<Modern, _>(TEST_DATA));
+}
+
+#[test]
+fn test_read_encoder() {
+    let mut data = Vec::with_capacity(TEST_DATA.len() * 20);
+    for _ in 0..20 {
+        data.extend_from_slice(TEST_DATA);
+    }
+
+    let mut encoder = crate::read::PrsEncoder::<_, Legacy>::new(&data[..]);
+    let mut compressed = Vec::new();
+    let mut chunk = [0; 333];
+    loop {
+        match encoder.read(&mut chunk).unwrap() {
+            0 => break,
+            n => compressed.extend_from_slice(&chunk[..n]),
+        }
+    }
+    assert!(decompress::<Legacy, _>(&compressed) == data);
+
+    encoder.reset(&TEST_DATA[..100]);
+    compressed.clear();
+    encoder.read_to_end(&mut compressed).unwrap();
+    assert!(decompress::<Legacy, _>(&compressed) == TEST_DATA[..100]);
+}
+
+#[test]
+fn test_write_decoder() {
+    let mut data = Vec::with_capacity(TEST_DATA.len() * 20);
+    for _ in 0..20 {
+        data.extend_from_slice(TEST_DATA);
+    }
+    let compressed = compress::<Modern, _>(&data);
+
+    let inner = TrickleWriter(Vec::new());
+    let mut decoder = crate::write::PrsDecoder::<_, Modern>::new(inner);
+    for chunk in compressed.chunks(7) {
+        decoder.write_all(chunk).unwrap();
+    }
+    // nothing is accepted past the end of the stream
+    assert_eq!(decoder.write(b"trailer").unwrap(), 0);
+    let out = decoder.finish().unwrap().0;
+    assert!(out == data);
+
+    let truncated = &compressed[..compressed.len() / 2];
+    let mut decoder = crate::write::PrsDecoder::<_, Modern>::new(Vec::new());
+    decoder.write_all(truncated).unwrap();
+    let err = decoder.try_finish().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_compress_decompress_state() {
+    let mut data = Vec::with_capacity(TEST_DATA.len() * 20);
+    for _ in 0..20 {
+        data.extend_from_slice(TEST_DATA);
+    }
+
+    // small output buffers force the state machines to resume often
+    let mut state = Compress::<Legacy>::new();
+    let mut compressed = Vec::new();
+    let mut chunk = [0; 5];
+    for input in data.chunks(1000) {
+        let mut input = input;
+        loop {
+            let total_in = state.total_in();
+            let total_out = state.total_out();
+            let status = state.run(input, &mut chunk);
+            input = &input[(state.total_in() - total_in) as usize..];
+            compressed.extend_from_slice(&chunk[..(state.total_out() - total_out) as usize]);
+            if status == Status::NeedsInput {
+                break;
+            }
+        }
+    }
+    loop {
+        let total_out = state.total_out();
+        let status = state.finish(&mut chunk);
+        compressed.extend_from_slice(&chunk[..(state.total_out() - total_out) as usize]);
+        if status == Status::Done {
+            break;
+        }
+    }
+    assert_eq!(state.total_out(), compressed.len() as u64);
+
+    let mut state = Decompress::<Legacy>::new();
+    let mut out = Vec::new();
+    let mut input = &compressed[..];
+    loop {
+        let total_in = state.total_in();
+        let total_out = state.total_out();
+        let status = state.run(&input[..input.len().min(3)], &mut chunk).unwrap();
+        input = &input[(state.total_in() - total_in) as usize..];
+        out.extend_from_slice(&chunk[..(state.total_out() - total_out) as usize]);
+        if status == Status::Done {
+            break;
+        }
+    }
+    assert!(out == data);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_tokio_encoder_decoder() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use ::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    use crate::tokio::{AsyncPrsDecoder, AsyncPrsEncoder};
+
+    // the in-memory inner IO is always ready, so polling never stalls
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut encoder = AsyncPrsEncoder::<_, Modern>::new(Vec::new());
+    for chunk in TEST_DATA.chunks(100) {
+        match Pin::new(&mut encoder).poll_write(&mut cx, chunk) {
+            Poll::Ready(r) => assert_eq!(r.unwrap(), chunk.len()),
+            Poll::Pending => panic!("write stalled"),
+        }
+    }
+    assert!(Pin::new(&mut encoder).poll_shutdown(&mut cx).is_ready());
+    let compressed = encoder.into_inner();
+    assert!(decompress::<Modern, _>(&compressed) == TEST_DATA);
+
+    let mut decoder = AsyncPrsDecoder::<_, Modern>::new(&compressed[..]);
+    let mut out = Vec::new();
+    let mut chunk = [0; 333];
+    loop {
+        let mut buf = ReadBuf::new(&mut chunk);
+        match Pin::new(&mut decoder).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(r) => r.unwrap(),
+            Poll::Pending => panic!("read stalled"),
+        }
+        if buf.filled().is_empty() {
+            break;
+        }
+        out.extend_from_slice(buf.filled());
+    }
+    assert!(out == TEST_DATA);
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn test_futures_encoder_decoder() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use futures_io::{AsyncRead, AsyncWrite};
+    use crate::futures::{AsyncPrsDecoder, AsyncPrsEncoder};
+
+    // the in-memory inner IO is always ready, so polling never stalls
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut encoder = AsyncPrsEncoder::<_, Legacy>::new(Vec::new());
+    for chunk in TEST_DATA.chunks(100) {
+        match Pin::new(&mut encoder).poll_write(&mut cx, chunk) {
+            Poll::Ready(r) => assert_eq!(r.unwrap(), chunk.len()),
+            Poll::Pending => panic!("write stalled"),
+        }
+    }
+    assert!(Pin::new(&mut encoder).poll_close(&mut cx).is_ready());
+    let compressed = encoder.into_inner();
+    assert!(decompress::<Legacy, _>(&compressed) == TEST_DATA);
+
+    let mut decoder = AsyncPrsDecoder::<_, Legacy>::new(&compressed[..]);
+    let mut out = Vec::new();
+    let mut chunk = [0; 333];
+    loop {
+        let n = match Pin::new(&mut decoder).poll_read(&mut cx, &mut chunk) {
+            Poll::Ready(r) => r.unwrap(),
+            Poll::Pending => panic!("read stalled"),
+        };
+        if n == 0 {
+            break;
+        }
+        out.extend_from_slice(&chunk[..n]);
+    }
+    assert!(out == TEST_DATA);
+}
+
+#[test]
+fn test_window_decoder() {
+    use crate::embedded::{WindowDecoder, WINDOW_SIZE};
+
+    let mut data = Vec::with_capacity(TEST_DATA.len() * 20);
+    for _ in 0..20 {
+        data.extend_from_slice(TEST_DATA);
+    }
+    let mut compressed = compress::<Modern, _>(&data);
+    compressed.extend_from_slice(b"trailer");
+
+    let mut window = [0; WINDOW_SIZE];
+    let mut decoder = WindowDecoder::<Modern>::new(&mut window);
+    let mut out = Vec::new();
+    for &b in &compressed {
+        decoder.push(b, |b| out.push(b)).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), data.len() as u64);
+    assert_eq!(decoder.total_in(), compressed.len() as u64 - 7);
+    assert!(out == data);
+
+    let mut decoder = WindowDecoder::<Legacy>::new(&mut window);
+    let mut err = None;
+    for &b in &[0x01, 0x41, 0xFD] {
+        if let Err(e) = decoder.push(b, |_| {}) {
+            err = Some(e);
+        }
+    }
+    match err {
+        Some(DecompressError::InvalidPointer { offset: 2, output_len: 1 }) => {},
+        e => panic!("unexpected error {:?}", e),
+    }
+
+    let mut decoder = WindowDecoder::<Legacy>::new(&mut window);
+    decoder.push(0x01, |_| {}).unwrap();
+    match decoder.finish() {
+        Err(DecompressError::Eof { offset: 0, output_len: 0 }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn test_ffi() {
+    use crate::ffi::*;
+
+    let mut compressed = std::ptr::null_mut();
+    let mut compressed_len = 0;
+    let r = unsafe {
+        ages_prs_compress(
+            AGES_PRS_MODERN,
+            TEST_DATA.as_ptr(),
+            TEST_DATA.len(),
+            &mut compressed,
+            &mut compressed_len,
+        )
+    };
+    assert_eq!(r, AGES_PRS_OK);
+
+    let mut out = vec![0; TEST_DATA.len()];
+    let mut written = 0;
+    let r = unsafe {
+        ages_prs_decompress_into(
+            AGES_PRS_MODERN,
+            compressed,
+            compressed_len,
+            out.as_mut_ptr(),
+            out.len(),
+            &mut written,
+        )
+    };
+    assert_eq!(r, AGES_PRS_OK);
+    assert_eq!(written, TEST_DATA.len());
+    assert!(out == TEST_DATA);
+
+    let r = unsafe {
+        ages_prs_decompress_into(
+            AGES_PRS_MODERN,
+            compressed,
+            compressed_len,
+            out.as_mut_ptr(),
+            out.len() - 1,
+            &mut written,
+        )
+    };
+    assert_eq!(r, AGES_PRS_ERR_BUFFER_TOO_SMALL);
+    unsafe { ages_prs_free(compressed, compressed_len) };
+
+    let r = unsafe {
+        ages_prs_compress(7, std::ptr::null(), 0, &mut compressed, &mut compressed_len)
+    };
+    assert_eq!(r, AGES_PRS_ERR_VARIANT);
+}
+
+#[cfg(feature = "lz")]
+#[test]
+fn test_lz01() {
+    use crate::lz01;
+
+    for data in &[&b""[..], TEST_DATA, &noise(5000)[..], &vec![0; 10_000][..]] {
+        let compressed = lz01::compress(data);
+        let header = lz01::read_header(&compressed).unwrap();
+        assert_eq!(header.compressed_size as usize, compressed.len());
+        assert_eq!(header.decompressed_size as usize, data.len());
+        assert!(lz01::decompress(&compressed).unwrap() == *data);
+    }
+    assert!(lz01::compress(TEST_DATA).len() < TEST_DATA.len() / 2);
+
+    // three literals, a copy of them by ring position, and a copy of the
+    // zeros the ring starts with
+    let mut src = b"LZ01\x16\0\0\0\x09\0\0\0\0\0\0\0".to_vec();
+    src.extend_from_slice(&[0x07, b'a', b'b', b'c', 0xEE, 0xF0, 0x00, 0x00]);
+    src[4] = src.len() as u8;
+    assert_eq!(lz01::decompress(&src).unwrap(), b"abcabc\0\0\0");
+
+    assert!(lz01::decompress(&src[..src.len() - 1]).is_err());
+    src[4] -= 1;
+    assert!(lz01::decompress(&src).is_err());
+    assert!(lz01::decompress(b"LZ00").is_err());
+}
+
+#[cfg(feature = "lz")]
+#[test]
+fn test_lz00() {
+    use crate::lz00;
+
+    let compressed = lz00::compress(TEST_DATA, b"test.txt", 0xDEAD_BEEF);
+    let header = lz00::read_header(&compressed).unwrap();
+    assert_eq!(header.compressed_size as usize, compressed.len());
+    assert_eq!(header.decompressed_size as usize, TEST_DATA.len());
+    assert_eq!(header.key, 0xDEAD_BEEF);
+    assert_eq!(header.name(), b"test.txt");
+    assert!(lz00::decompress(&compressed).unwrap() == TEST_DATA);
+
+    // the payload is LZ01's, encrypted
+    let lz01 = crate::lz01::compress(TEST_DATA);
+    assert_eq!(compressed.len() - lz00::HEADER_LEN, lz01.len() - crate::lz01::HEADER_LEN);
+    assert!(compressed[lz00::HEADER_LEN..] != lz01[crate::lz01::HEADER_LEN..]);
+
+    let long_name = [b'x'; 40];
+    let compressed = lz00::compress(b"", &long_name, 0);
+    assert_eq!(lz00::read_header(&compressed).unwrap().name(), &long_name[..31]);
+    assert!(lz00::decompress(&compressed).unwrap().is_empty());
+}
+
+#[cfg(feature = "cnx")]
+#[test]
+fn test_cnx() {
+    use crate::cnx::{self, CnxDecoder, CnxEncoder};
+
+    let long_run = noise(1000);
+    for data in &[&b""[..], &b"a"[..], TEST_DATA, &long_run[..], &vec![7; 5000][..]] {
+        let compressed = cnx::compress(data, *b"bin");
+        let header = cnx::read_header(&compressed).unwrap();
+        assert_eq!(header.extension, *b"bin");
+        assert_eq!(header.compressed_size as usize, compressed.len() - cnx::HEADER_LEN);
+        assert_eq!(header.decompressed_size as usize, data.len());
+        assert!(cnx::decompress(&compressed).unwrap() == *data);
+    }
+    assert!(cnx::compress(TEST_DATA, *b"txt").len() < TEST_DATA.len() / 2);
+
+    // a literal, a run of two, a copy of five from three back, then a skip
+    // over padding before the last literal
+    let mut src = b"CNX\x02bin\x10\0\0\0\0\0\0\0\x09".to_vec();
+    src.extend_from_slice(&[0x2D, b'a', 2, b'b', b'c', 0x00, 0x41, 1, 0xFF, 0x01, b'd']);
+    src[11] = (src.len() - cnx::HEADER_LEN) as u8;
+    assert_eq!(cnx::decompress(&src).unwrap(), b"abcabcabd");
+    assert!(cnx::decompress(&src[..src.len() - 1]).is_err());
+
+    // copying from before the start of the output
+    let bad = b"CNX\x02bin\x10\0\0\0\x03\0\0\0\x04\x02\x00\x20";
+    assert!(cnx::decompress(bad).is_err());
+
+    let mut encoder = CnxEncoder::new(Vec::new(), *b"txt");
+    encoder.write_all(TEST_DATA).unwrap();
+    let mut compressed = encoder.into_inner().unwrap();
+    compressed.extend_from_slice(b"after");
+    let mut decoder = CnxDecoder::new(Cursor::new(&compressed[..]));
+    let mut decomp = Vec::new();
+    decoder.read_to_end(&mut decomp).unwrap();
+    assert!(decomp == TEST_DATA);
+    let mut rest = Vec::new();
+    decoder.into_inner().read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"after");
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing() {
+    // the instrumented paths, including flushes of a partly written buffer
+    // and after a sync flush, and streams after a reset
+    let data = TEST_DATA.repeat(4);
+    let mut encoder = PrsEncoder::<_, Modern>::new(TrickleWriter(Vec::new()));
+    for chunk in data.chunks(1000) {
+        encoder.flush_sync().unwrap();
+        encoder.write_all(chunk).unwrap();
+    }
+    let first = encoder.reset(TrickleWriter(Vec::new())).unwrap().0;
+    encoder.write_all(TEST_DATA).unwrap();
+    let second = encoder.into_inner().unwrap().0;
+    assert!(crate::decompress::<Modern>(&first).unwrap() == data);
+    assert!(crate::decompress::<Modern>(&second).unwrap() == TEST_DATA);
+
+    let mut decoder = PrsDecoder::<_, Modern>::new(&first[..]);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == data);
+
+    let mut decoder = crate::bufread::PrsDecoder::<_, Modern>::new(&second[..]);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert!(out == TEST_DATA);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes() {
+    use ::bytes::Buf;
+    use crate::bytes;
+
+    let expected = compress::<Modern, _>(TEST_DATA);
+    let (a, rest) = TEST_DATA.split_at(1000);
+    let (b, c) = rest.split_at(3);
+    let compressed = bytes::compress::<Modern, _>(&mut Buf::chain(a, b).chain(c));
+    assert!(compressed[..] == expected[..]);
+
+    let (head, tail) = expected.split_at(expected.len() / 3);
+    let mut src = Buf::chain(head, tail).chain(&b"after"[..]);
+    let data = bytes::decompress::<Modern, _>(&mut src).unwrap();
+    assert!(data[..] == *TEST_DATA);
+    assert_eq!(src.remaining(), 5);
+    assert_eq!(src.chunk(), b"after");
+
+    let mut out = b"prefix".to_vec();
+    let len = bytes::decompress_to::<Modern, _, _>(&mut &expected[..], &mut out).unwrap();
+    assert_eq!(len, TEST_DATA.len() as u64);
+    assert!(out[6..] == *TEST_DATA);
+
+    let mut truncated = Buf::chain(head, &tail[..tail.len() - 1]);
+    match bytes::decompress::<Modern, _>(&mut truncated) {
+        Err(DecompressError::Eof { .. }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    // two literals, then a pointer from before the start of the output
+    let mut out = Vec::new();
+    let corrupt = [0x83, b'a', b'b', 0xFA, 0, 0];
+    match bytes::decompress_to::<Legacy, _, _>(&mut &corrupt[..], &mut out) {
+        Err(DecompressError::InvalidPointer { .. }) => {},
+        r => panic!("unexpected result {:?}", r),
+    }
+    assert_eq!(out, b"ab");
+}
@@ -0,0 +1,36 @@
+//! Conversion of PRS streams between variants.
+
+use crate::{bufread::PrsDecoder, EncodeStats, PrsEncoder, Variant};
+
+use std::io::{self, BufRead, Write};
+
+/// Decode a PRS stream of variant `From` from `reader` and re-encode it as
+/// variant `To` into `writer`, returning statistics about the new stream.
+///
+/// Data passes through in bounded chunks, so memory use doesn't depend on the
+/// size of the stream. Only the PRS stream is consumed from `reader`; wrap
+/// a plain `Read` in a `BufReader` to use it here.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress, transcode, Legacy, Modern};
+///
+/// let legacy = compress::<Legacy>(b"Hello Hello Hello ");
+/// let mut modern = Vec::new();
+/// transcode::<Legacy, Modern, _, _>(&legacy[..], &mut modern).unwrap();
+/// assert_eq!(&decompress::<Modern>(&modern).unwrap()[..], b"Hello Hello Hello ");
+/// ```
+pub fn transcode<From, To, R, W>(reader: R, writer: W) -> io::Result<EncodeStats>
+where
+    From: Variant,
+    To: Variant,
+    R: BufRead,
+    W: Write,
+{
+    let mut decoder = PrsDecoder::<_, From>::new(reader);
+    let mut encoder = PrsEncoder::<_, To>::new(writer);
+    io::copy(&mut decoder, &mut encoder)?;
+    let (_, stats) = encoder.finish().map_err(|e| e.into_error())?;
+    Ok(stats)
+}
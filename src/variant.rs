@@ -1,7 +1,9 @@
 //! PRS variant policies. Applications usually expect and produce particular
 //! variations on PRS.
 
-use std::u8::MAX as U8MAX;
+use crate::CommandForm;
+
+use core::marker::PhantomData;
 
 /// Variant of PRS compression used. Varies with target game.
 ///
@@ -9,11 +11,39 @@ use std::u8::MAX as U8MAX;
 /// improper impls of this trait may result in panics in the implementation. If
 /// you have a variant of PRS that is not supported here, please open an issue
 /// on the issue tracker.
-pub trait Variant: private::Sealed {
-    #[doc(hidden)]
+///
+/// The associated constants describe which copies the variant can encode,
+/// for tools that analyze or generate command streams. A copy of `length`
+/// bytes from `distance` bytes back is encodable when `distance` is at most
+/// `MAX_COPY_DISTANCE` and either:
+///
+/// - `length` is from `MIN_COPY_LENGTH` to `MAX_SHORT_COPY_LENGTH` and
+///   `distance` is at most `MAX_SHORT_COPY_DISTANCE` (a short pointer);
+/// - `length` is from 3 to 9 (a long pointer with the length inline);
+/// - `length` is from `MIN_LONG_COPY_LENGTH` to `MAX_COPY_LENGTH` (a long
+///   pointer with a size byte).
+pub trait Variant: private::Sealed + Unpin {
+    /// Shortest copy any pointer can encode.
+    const MIN_COPY_LENGTH: u16 = 2;
+    /// Longest copy a short pointer can encode.
+    const MAX_SHORT_COPY_LENGTH: u16 = 5;
+    /// Furthest back a short pointer can reach.
+    const MAX_SHORT_COPY_DISTANCE: u16 = u8::MAX as u16;
+    /// Furthest back any pointer can reach.
+    const MAX_COPY_DISTANCE: u16 = 8191;
+    /// Shortest copy a long pointer with a size byte can encode.
     const MIN_LONG_COPY_LENGTH: u16;
+    /// Longest copy any pointer can encode.
+    const MAX_COPY_LENGTH: u16 = u8::MAX as u16 + Self::MIN_LONG_COPY_LENGTH;
+    /// Whether the offset word of long pointers is stored big endian rather
+    /// than little endian.
+    const BIG_ENDIAN_OFFSETS: bool = false;
+    /// Whether a long pointer whose offset field is zero ends the stream
+    /// rather than copying from the furthest distance, 8192 bytes back.
+    const ZERO_OFFSET_EOF: bool = false;
+    /// Id of the variant in framed stream headers.
     #[doc(hidden)]
-    const MAX_COPY_LENGTH: u16 = U8MAX as u16 + Self::MIN_LONG_COPY_LENGTH;
+    const FRAME_ID: u8;
 }
 
 /// PRS Variant used in games in the Dreamcast and Saturn era.
@@ -25,8 +55,23 @@ pub trait Variant: private::Sealed {
 pub enum Legacy {}
 
 impl Variant for Legacy {
-    #[doc(hidden)]
     const MIN_LONG_COPY_LENGTH: u16 = 1;
+    #[doc(hidden)]
+    const FRAME_ID: u8 = 0;
+}
+
+impl private::Sealed for Legacy {
+    fn read_pointer<B: private::Bits>(bits: &mut B) -> Option<(CommandForm, usize, usize)> {
+        read_pointer::<Self, B>(bits)
+    }
+
+    fn write_pointer<B: private::BitsMut>(bits: &mut B, distance: u16, length: u16) -> CommandForm {
+        write_pointer::<Self, B>(bits, distance, length)
+    }
+
+    fn write_eof<B: private::BitsMut>(bits: &mut B) {
+        write_eof(bits)
+    }
 }
 
 /// PRS Variant used in games made after the Dreamcast.
@@ -36,12 +81,257 @@ impl Variant for Legacy {
 pub enum Modern {}
 
 impl Variant for Modern {
-    #[doc(hidden)]
     const MIN_LONG_COPY_LENGTH: u16 = 10;
+    #[doc(hidden)]
+    const FRAME_ID: u8 = 1;
+}
+
+impl private::Sealed for Modern {
+    fn read_pointer<B: private::Bits>(bits: &mut B) -> Option<(CommandForm, usize, usize)> {
+        read_pointer::<Self, B>(bits)
+    }
+
+    fn write_pointer<B: private::BitsMut>(bits: &mut B, distance: u16, length: u16) -> CommandForm {
+        write_pointer::<Self, B>(bits, distance, length)
+    }
+
+    fn write_eof<B: private::BitsMut>(bits: &mut B) {
+        write_eof(bits)
+    }
+}
+
+/// Variant `V` with the offset word of long pointers stored big endian, as
+/// some console-era encoders write it.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{compress, decompress, BigEndian, Legacy};
+///
+/// let compressed = compress::<BigEndian<Legacy>>(b"abcabcabc");
+/// assert_eq!(decompress::<BigEndian<Legacy>>(&compressed).unwrap(), b"abcabcabc");
+/// assert_ne!(compressed, compress::<Legacy>(b"abcabcabc"));
+/// ```
+pub struct BigEndian<V: Variant>(PhantomData<V>);
+
+impl<V: Variant> Variant for BigEndian<V> {
+    const MIN_COPY_LENGTH: u16 = V::MIN_COPY_LENGTH;
+    const MAX_SHORT_COPY_LENGTH: u16 = V::MAX_SHORT_COPY_LENGTH;
+    const MAX_SHORT_COPY_DISTANCE: u16 = V::MAX_SHORT_COPY_DISTANCE;
+    const MAX_COPY_DISTANCE: u16 = V::MAX_COPY_DISTANCE;
+    const MIN_LONG_COPY_LENGTH: u16 = V::MIN_LONG_COPY_LENGTH;
+    const MAX_COPY_LENGTH: u16 = V::MAX_COPY_LENGTH;
+    const BIG_ENDIAN_OFFSETS: bool = true;
+    const ZERO_OFFSET_EOF: bool = V::ZERO_OFFSET_EOF;
+    #[doc(hidden)]
+    const FRAME_ID: u8 = V::FRAME_ID;
+}
+
+impl<V: Variant> private::Sealed for BigEndian<V> {
+    fn read_pointer<B: private::Bits>(bits: &mut B) -> Option<(CommandForm, usize, usize)> {
+        read_pointer::<Self, B>(bits)
+    }
+
+    fn write_pointer<B: private::BitsMut>(bits: &mut B, distance: u16, length: u16) -> CommandForm {
+        write_pointer::<Self, B>(bits, distance, length)
+    }
+
+    fn write_eof<B: private::BitsMut>(bits: &mut B) {
+        write_eof(bits)
+    }
+}
+
+/// Variant `V` ending the stream at long pointers whose offset field is zero,
+/// as some games' decoders do.
+///
+/// The offset field of a long pointer holds the distance negated in 13 bits,
+/// so zero stands for 8192 bytes back, which is how `V` decodes it. Some
+/// decoders test the field for zero before they test the whole word for the
+/// end of stream command, and stop there instead, so files with such
+/// pointers load differently in those games. Decode with this variant to
+/// see what they load. Encoders never write such pointers, since the
+/// furthest they copy from is 8191 bytes back, so compressing with this
+/// variant gives the same stream as `V`.
+///
+/// # Examples
+///
+/// ```
+/// use ages_prs::{decompress, decompress_prefix, Legacy, ZeroOffsetEof};
+///
+/// // a literal, then a long pointer with a zero offset field
+/// let stream = [0b101, b'a', 0x01, 0x00, 0xFF];
+/// assert!(decompress::<Legacy>(&stream).is_err());
+/// let (data, consumed) = decompress_prefix::<ZeroOffsetEof<Legacy>>(&stream).unwrap();
+/// assert_eq!(&data[..], b"a");
+/// assert_eq!(consumed, 4);
+/// ```
+pub struct ZeroOffsetEof<V: Variant>(PhantomData<V>);
+
+impl<V: Variant> Variant for ZeroOffsetEof<V> {
+    const MIN_COPY_LENGTH: u16 = V::MIN_COPY_LENGTH;
+    const MAX_SHORT_COPY_LENGTH: u16 = V::MAX_SHORT_COPY_LENGTH;
+    const MAX_SHORT_COPY_DISTANCE: u16 = V::MAX_SHORT_COPY_DISTANCE;
+    const MAX_COPY_DISTANCE: u16 = V::MAX_COPY_DISTANCE;
+    const MIN_LONG_COPY_LENGTH: u16 = V::MIN_LONG_COPY_LENGTH;
+    const MAX_COPY_LENGTH: u16 = V::MAX_COPY_LENGTH;
+    const BIG_ENDIAN_OFFSETS: bool = V::BIG_ENDIAN_OFFSETS;
+    const ZERO_OFFSET_EOF: bool = true;
+    #[doc(hidden)]
+    const FRAME_ID: u8 = V::FRAME_ID;
+}
+
+impl<V: Variant> private::Sealed for ZeroOffsetEof<V> {
+    fn read_pointer<B: private::Bits>(bits: &mut B) -> Option<(CommandForm, usize, usize)> {
+        read_pointer::<Self, B>(bits)
+    }
+
+    fn write_pointer<B: private::BitsMut>(bits: &mut B, distance: u16, length: u16) -> CommandForm {
+        write_pointer::<Self, B>(bits, distance, length)
+    }
+
+    fn write_eof<B: private::BitsMut>(bits: &mut B) {
+        write_eof(bits)
+    }
 }
 
-mod private {
-    pub trait Sealed {}
-    impl Sealed for super::Legacy {}
-    impl Sealed for super::Modern {}
+// ---- Command encoding shared by the variants ----
+
+/// Read the rest of a pointer or end of stream command, after the flag bit
+/// marking it as not a literal.
+fn read_pointer<V: Variant, B: private::Bits>(
+    bits: &mut B,
+) -> Option<(CommandForm, usize, usize)> {
+    if bits.bit()? {
+        // long ptr
+        let buf = [bits.byte()?, bits.byte()?];
+        let mut offset = if V::BIG_ENDIAN_OFFSETS {
+            i16::from_be_bytes(buf)
+        } else {
+            i16::from_le_bytes(buf)
+        } as i32;
+
+        if offset == 0 {
+            return Some((CommandForm::Eof, 0, 0));
+        }
+
+        let mut size = (offset & 0b111) as usize;
+        offset >>= 3;
+
+        if offset == 0 && V::ZERO_OFFSET_EOF {
+            return Some((CommandForm::Eof, 0, 0));
+        }
+
+        let form = if size == 0 {
+            size = bits.byte()? as usize;
+            size += V::MIN_LONG_COPY_LENGTH as usize;
+            CommandForm::SizedPointer
+        } else {
+            size += 2;
+            CommandForm::LongPointer
+        };
+        offset |= -8192i32;
+
+        Some((form, (-offset) as usize, size))
+    } else {
+        // short ptr
+        let flag = if bits.bit()? { 1 } else { 0 };
+        let bit = if bits.bit()? { 1 } else { 0 };
+        let size = (bit | (flag << 1)) + 2;
+        let offset = bits.byte()? as i32 | -256i32;
+
+        Some((CommandForm::ShortPointer, (-offset) as usize, size))
+    }
+}
+
+/// Write a pointer command in its shortest encoding, after the flag bit
+/// marking it as not a literal, returning the form used.
+fn write_pointer<V: Variant, B: private::BitsMut>(
+    bits: &mut B,
+    distance: u16,
+    length: u16,
+) -> CommandForm {
+    if distance > V::MAX_SHORT_COPY_DISTANCE
+        || !(V::MIN_COPY_LENGTH..=V::MAX_SHORT_COPY_LENGTH).contains(&length)
+    {
+        // long ptr
+        bits.put_bit(true);
+
+        let mut offset = distance as i32;
+
+        offset = -offset;
+        offset <<= 3;
+        // lengths from 3 to 9 fit in the offset word; the rest, and
+        // length 2 which would read as zero there, take a size byte
+        let inline = (3..=9).contains(&length);
+        if inline {
+            offset |= (length - 2) as i32;
+        }
+
+        let word = if V::BIG_ENDIAN_OFFSETS {
+            (offset as u16).to_be_bytes()
+        } else {
+            (offset as u16).to_le_bytes()
+        };
+        for b in word {
+            bits.put_byte(b);
+        }
+
+        if inline {
+            CommandForm::LongPointer
+        } else {
+            bits.put_byte((length - V::MIN_LONG_COPY_LENGTH) as u8);
+            CommandForm::SizedPointer
+        }
+    } else {
+        // short ptr
+        bits.put_bit(false);
+
+        let offset = distance as i32;
+        let size = (length - 2) as i32;
+
+        bits.put_bit(size & 0b10 > 0);
+        bits.put_bit(size & 0b01 > 0);
+        bits.put_byte((-offset & 0xFF) as u8);
+        CommandForm::ShortPointer
+    }
+}
+
+/// Write the end of stream command, after the flag bit marking it as not a
+/// literal.
+fn write_eof<B: private::BitsMut>(bits: &mut B) {
+    bits.put_bit(true); // long ptr
+    bits.put_byte(0); // zero offset = EOF
+    bits.put_byte(0);
+}
+
+/// The encoding of commands, which is up to each variant.
+///
+/// Everything but literals goes through here: the encoder's sink and the
+/// decoder's command reader only write and read the flag bit telling
+/// literals apart, so a variant with a different pointer layout or end of
+/// stream marker only needs a different `Sealed` impl.
+pub(crate) mod private {
+    use crate::CommandForm;
+
+    /// Source of the flag bits and bytes of a command stream, returning
+    /// `None` at the end of the input.
+    pub trait Bits {
+        fn bit(&mut self) -> Option<bool>;
+        fn byte(&mut self) -> Option<u8>;
+    }
+
+    /// Destination for the flag bits and bytes of a command stream.
+    pub trait BitsMut {
+        fn put_bit(&mut self, bit: bool);
+        fn put_byte(&mut self, b: u8);
+    }
+
+    pub trait Sealed {
+        /// Read a pointer as its form, distance and length, or the end of
+        /// the stream as `CommandForm::Eof`.
+        fn read_pointer<B: Bits>(bits: &mut B) -> Option<(CommandForm, usize, usize)>;
+        /// Write a pointer the variant can encode, returning its form.
+        fn write_pointer<B: BitsMut>(bits: &mut B, distance: u16, length: u16) -> CommandForm;
+        fn write_eof<B: BitsMut>(bits: &mut B);
+    }
 }
@@ -0,0 +1,34 @@
+//! WebAssembly bindings, enabled by the `wasm` feature.
+//!
+//! Exported to JavaScript through `wasm-bindgen` as `compressLegacy`,
+//! `compressModern`, `decompressLegacy` and `decompressModern`. Each takes a
+//! `Uint8Array` and returns a new `Uint8Array`; decompression throws an
+//! `Error` describing the failure for malformed input.
+
+use crate::{Legacy, Modern};
+
+use wasm_bindgen::prelude::*;
+
+/// Compress a buffer with the Legacy variant.
+#[wasm_bindgen(js_name = compressLegacy)]
+pub fn compress_legacy(data: &[u8]) -> Vec<u8> {
+    crate::compress::<Legacy>(data)
+}
+
+/// Compress a buffer with the Modern variant.
+#[wasm_bindgen(js_name = compressModern)]
+pub fn compress_modern(data: &[u8]) -> Vec<u8> {
+    crate::compress::<Modern>(data)
+}
+
+/// Decompress a Legacy variant PRS buffer.
+#[wasm_bindgen(js_name = decompressLegacy)]
+pub fn decompress_legacy(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(crate::decompress::<Legacy>(data)?)
+}
+
+/// Decompress a Modern variant PRS buffer.
+#[wasm_bindgen(js_name = decompressModern)]
+pub fn decompress_modern(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    Ok(crate::decompress::<Modern>(data)?)
+}
@@ -0,0 +1,179 @@
+//! PRS decoding into `Write` sinks.
+//!
+//! [`crate::PrsDecoder`](../struct.PrsDecoder.html) pulls compressed data out
+//! of a `Read`. The decoder in this module is pushed compressed data through
+//! `Write` instead, and writes the decompressed data to the `Write` it wraps,
+//! which suits producers that hand out data in chunks as it arrives.
+
+use crate::Variant;
+use crate::decompress::{Decompress, Status};
+
+use std::io::{self, Write};
+
+/// Capacity of the buffer holding decoded data on its way to the inner Write.
+const OUT_BUF_CAPACITY: usize = 8192;
+
+/// An IO sink for decoding a PRS stream written to it into a `Write`.
+///
+/// Writes are accepted up to the end of the PRS stream. Once it has been
+/// reached, further writes return `Ok(0)`, so `write_all` fails with
+/// `WriteZero` if the data written continues past the stream.
+pub struct PrsDecoder<W: Write, V: Variant> {
+    inner: W,
+    state: Decompress<V>,
+    /// decoded data not yet written to `inner`, allocated on first use
+    out_buf: Box<[u8]>,
+    out_pos: usize,
+    out_len: usize,
+}
+
+impl<W: Write, V: Variant> PrsDecoder<W, V> {
+    /// Wraps a Write sink, initializing the decoder state.
+    pub fn new(inner: W) -> PrsDecoder<W, V> {
+        PrsDecoder {
+            inner,
+            state: Decompress::new(),
+            out_buf: Box::default(),
+            out_pos: 0,
+            out_len: 0,
+        }
+    }
+
+    /// Wraps a Write sink, failing once more than `limit` bytes would be
+    /// decompressed into it.
+    ///
+    /// See [`crate::PrsDecoder::with_limit`](../struct.PrsDecoder.html#method.with_limit).
+    pub fn with_limit(inner: W, limit: u64) -> PrsDecoder<W, V> {
+        let mut decoder = PrsDecoder::new(inner);
+        decoder.state.set_limit(Some(limit));
+        decoder
+    }
+
+    /// Fail once more than `ratio` times as many bytes would be decompressed
+    /// as were read, or lift the limit with `None`.
+    ///
+    /// See [`crate::PrsDecoder::set_max_ratio`](../struct.PrsDecoder.html#method.set_max_ratio).
+    pub fn set_max_ratio(&mut self, ratio: Option<u32>) {
+        self.state.set_max_ratio(ratio);
+    }
+
+    /// Reference the inner Write.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Mutably reference the inner Write.
+    ///
+    /// Writing to it directly will interleave with the decompressed data.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner Write.
+    ///
+    /// Decoded data that has not been written to it yet is lost; call
+    /// [`finish`](#method.finish) to make sure the stream was complete and
+    /// everything was written.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Check that the whole PRS stream was written and write out any decoded
+    /// data still buffered, without unwrapping the inner Write.
+    ///
+    /// Fails with an `UnexpectedEof` error wrapping `DecompressError::Eof` if
+    /// the end of stream command has not been written yet.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.dump()?;
+        loop {
+            let status = self.decode(&[])?;
+            self.dump()?;
+            match status {
+                Status::Done => return Ok(()),
+                Status::NeedsInput => return Err(self.state.eof_error().into()),
+                Status::NeedsOutput => {},
+            }
+        }
+    }
+
+    /// Check that the whole PRS stream was written, returning the inner Write.
+    ///
+    /// Fails in the same way as [`try_finish`](#method.try_finish).
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner)
+    }
+
+    /// Start decoding a new PRS stream into `inner`, returning the previous
+    /// Write.
+    ///
+    /// Any data still buffered from the previous stream is discarded.
+    pub fn reset(&mut self, inner: W) -> W {
+        self.state.reset();
+        self.out_pos = 0;
+        self.out_len = 0;
+        std::mem::replace(&mut self.inner, inner)
+    }
+
+    /// Write out the buffered decoded data.
+    fn dump(&mut self) -> io::Result<()> {
+        while self.out_pos < self.out_len {
+            match self.inner.write(&self.out_buf[self.out_pos..self.out_len]) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the decoded data"
+                    ));
+                },
+                Ok(n) => self.out_pos += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode `input` into the empty output buffer.
+    fn decode(&mut self, input: &[u8]) -> io::Result<Status> {
+        if self.out_buf.is_empty() {
+            self.out_buf = vec![0; OUT_BUF_CAPACITY].into_boxed_slice();
+        }
+
+        let total_out = self.state.total_out();
+        let status = self.state.run(input, &mut self.out_buf)?;
+        self.out_pos = 0;
+        self.out_len = (self.state.total_out() - total_out) as usize;
+        Ok(status)
+    }
+}
+
+impl<W: Write, V: Variant> Write for PrsDecoder<W, V> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the inner Write may have failed partway through the previous write
+        self.dump()?;
+
+        let total_in = self.state.total_in();
+        loop {
+            let consumed = (self.state.total_in() - total_in) as usize;
+            let status = self.decode(&buf[consumed..])?;
+            if let Err(e) = self.dump() {
+                // the consumed input can't be taken back, so report it and
+                // leave the decoded data buffered for the next call
+                let consumed = (self.state.total_in() - total_in) as usize;
+                if consumed > 0 {
+                    return Ok(consumed);
+                }
+                return Err(e);
+            }
+            match status {
+                Status::NeedsOutput => {},
+                Status::NeedsInput | Status::Done => break,
+            }
+        }
+        Ok((self.state.total_in() - total_in) as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dump().and_then(|()| self.inner.flush())
+    }
+}